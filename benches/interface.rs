@@ -0,0 +1,156 @@
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rumble::constants::{BUFFER_POOL_PACKETS, INTERFACE_BATCH_SIZE};
+use rumble::utils::buffer_pool::BufferPool;
+use rumble::utils::interface::{
+    read_batch_from_interface, read_from_interface, write_batch_to_interface, write_to_interface,
+};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::runtime::Runtime;
+
+const PACKET_SIZE: usize = 1400;
+const PACKETS: usize = 4096;
+
+/// In-memory TUN device that preserves packet boundaries.
+struct MemoryDevice {
+    packet: Vec<u8>,
+    remaining: usize,
+    written: usize,
+}
+
+impl MemoryDevice {
+    fn new(packets: usize) -> Self {
+        Self {
+            packet: vec![0x45; PACKET_SIZE],
+            remaining: packets,
+            written: 0,
+        }
+    }
+}
+
+impl AsyncRead for MemoryDevice {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            buf.put_slice(&self.packet);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for MemoryDevice {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.written += 1;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn bench_read(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("read_from_interface");
+    group.throughput(Throughput::Bytes((PACKET_SIZE * PACKETS) as u64));
+
+    group.bench_function("single", |b| {
+        b.to_async(&runtime).iter(|| async {
+            let mut device = MemoryDevice::new(PACKETS);
+            let mut buffer_pool = BufferPool::new(PACKET_SIZE, BUFFER_POOL_PACKETS);
+
+            for _ in 0..PACKETS {
+                read_from_interface(&mut device, &mut buffer_pool)
+                    .await
+                    .unwrap();
+            }
+        })
+    });
+
+    for batch_size in [8, INTERFACE_BATCH_SIZE] {
+        group.bench_with_input(
+            BenchmarkId::new("batched", batch_size),
+            &batch_size,
+            |b, &batch_size| {
+                b.to_async(&runtime).iter(|| async move {
+                    let mut device = MemoryDevice::new(PACKETS);
+                    let mut buffer_pool = BufferPool::new(PACKET_SIZE, BUFFER_POOL_PACKETS);
+                    let mut batch = Vec::with_capacity(batch_size);
+                    let mut packets = 0;
+
+                    while packets < PACKETS {
+                        packets += read_batch_from_interface(
+                            &mut device,
+                            &mut buffer_pool,
+                            &mut batch,
+                            batch_size,
+                        )
+                        .await
+                        .unwrap();
+                        batch.clear();
+                    }
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+fn bench_write(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let packet = Bytes::from(vec![0x45; PACKET_SIZE]);
+    let mut group = c.benchmark_group("write_to_interface");
+    group.throughput(Throughput::Bytes((PACKET_SIZE * PACKETS) as u64));
+
+    group.bench_function("single", |b| {
+        b.to_async(&runtime).iter(|| async {
+            let mut device = MemoryDevice::new(0);
+
+            for _ in 0..PACKETS {
+                write_to_interface(&mut device, packet.clone())
+                    .await
+                    .unwrap();
+            }
+
+            assert_eq!(device.written, PACKETS);
+        })
+    });
+
+    group.bench_function("batched", |b| {
+        b.to_async(&runtime).iter(|| async {
+            let mut device = MemoryDevice::new(0);
+            let mut batch = Vec::with_capacity(INTERFACE_BATCH_SIZE);
+
+            for _ in 0..PACKETS / INTERFACE_BATCH_SIZE {
+                batch.extend((0..INTERFACE_BATCH_SIZE).map(|_| packet.clone()));
+                write_batch_to_interface(&mut device, &mut batch)
+                    .await
+                    .unwrap();
+            }
+
+            assert_eq!(device.written, PACKETS);
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_read, bench_write);
+criterion_main!(benches);
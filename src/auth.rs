@@ -1,3 +1,4 @@
 pub mod client;
+pub mod features;
 pub mod server;
 pub mod user;
\ No newline at end of file
@@ -6,13 +6,27 @@ use serde::{Deserialize, Serialize};
 use tokio::io::AsyncReadExt;
 
 use crate::config::ClientAuthenticationConfig;
+use crate::utils::control::Redirect;
 
+use super::features::Features;
 use super::server::AuthServerMessage;
 
 //Authentication message to client
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum AuthClientMessage {
     Authentication(String, String),
+    AuthenticationWithFeatures(String, String, Features),
+}
+
+/// Outcome of the authentication of the client
+#[derive(Clone, Debug, PartialEq)]
+pub enum AuthOutcome {
+    /// The client is authenticated and has been leased an address
+    Authenticated(IpNet, Features),
+    /// The server does not accept new sessions and redirects the client to another server
+    Redirected(Redirect),
+    /// The server refused the client or closed the stream without answering
+    Failed,
 }
 
 //Authentication client handling initial authentication and session management
@@ -21,12 +35,14 @@ pub struct AuthClient {
     recv_stream: RecvStream,
     username: String,
     password: String,
+    features: Features,
 }
 
 impl AuthClient {
     pub async fn new(
         connection: &Connection,
         authentication_config: &ClientAuthenticationConfig,
+        features: Features,
     ) -> Result<Self> {
         let (send, recv) = connection.open_bi().await?;
 
@@ -35,22 +51,40 @@ impl AuthClient {
             recv_stream: recv,
             username: authentication_config.username.clone(),
             password: authentication_config.password.clone(),
+            features,
         })
     }
 
-    //Establishes session with server, returns tunnel address
-    pub async fn authenticate(&mut self) -> Result<IpNet> {
-        let basic_auth = AuthClientMessage::Authentication(self.username.clone(), self.password.clone());
+    //Establishes session with server, returns tunnel address and the negotiated features, or
+    //the server the client is redirected to
+    pub async fn authenticate(&mut self) -> Result<AuthOutcome> {
+        // Servers that do not know about features only understand the basic authentication
+        let auth_message = if self.features == Features::default() {
+            AuthClientMessage::Authentication(self.username.clone(), self.password.clone())
+        } else {
+            AuthClientMessage::AuthenticationWithFeatures(
+                self.username.clone(),
+                self.password.clone(),
+                self.features,
+            )
+        };
 
-        self.send_message(basic_auth).await?;
+        self.send_message(auth_message).await?;
         let auth_response = self.recv_message().await?;
 
         match auth_response {
             Some(AuthServerMessage::Authenticated(addr, netmask)) => {
                 let address = IpNet::with_netmask(addr, netmask)?;
 
-                Ok(address)
+                Ok(AuthOutcome::Authenticated(address, Features::default()))
+            }
+            Some(AuthServerMessage::AuthenticatedWithFeatures(addr, netmask, features)) => {
+                let address = IpNet::with_netmask(addr, netmask)?;
+
+                Ok(AuthOutcome::Authenticated(address, features))
             }
+            Some(AuthServerMessage::Redirect(redirect)) => Ok(AuthOutcome::Redirected(redirect)),
+            Some(AuthServerMessage::Failed) | None => Ok(AuthOutcome::Failed),
             _ => Err(anyhow!("Authentication failed")),
         }
     }
@@ -0,0 +1,114 @@
+use crate::utils::compression::Compression;
+use serde::{Deserialize, Serialize};
+
+/// First protocol version that prefixes every datagram with its type
+pub const TYPED_FRAMING_VERSION: u8 = 1;
+
+/// First protocol version whose clients follow redirects to other servers
+pub const REDIRECT_VERSION: u8 = 2;
+
+/// Optional tunnel features negotiated during authentication.
+///
+/// The client requests the features it wants to use, the server answers with the subset it
+/// supports. Missing fields default to disabled, so older peers negotiate no features.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Features {
+    /// The version of the datagram protocol, `0` for peers predating protocol versions
+    #[serde(default)]
+    pub protocol_version: u8,
+    /// Whether multiple packets may be coalesced into a single datagram
+    #[serde(default)]
+    pub coalescing: bool,
+    /// Whether datagrams are protected by forward error correction
+    #[serde(default)]
+    pub fec: bool,
+    /// The algorithm packets are compressed with
+    #[serde(default)]
+    pub compression: Compression,
+    /// Whether packets that do not fit into a datagram may be sent over a unidirectional stream
+    #[serde(default)]
+    pub packet_stream: bool,
+}
+
+impl Features {
+    /// Negotiates the features requested by the client against the features supported by the
+    /// server.
+    ///
+    /// Arguments
+    /// `supported` - the features supported by the server
+    ///
+    /// Returns
+    /// `Features` - the features enabled for the connection
+    pub fn negotiate(&self, supported: &Features) -> Features {
+        Features {
+            protocol_version: self.protocol_version.min(supported.protocol_version),
+            coalescing: self.coalescing && supported.coalescing,
+            fec: self.fec && supported.fec,
+            compression: if self.compression == supported.compression {
+                self.compression
+            } else {
+                Compression::None
+            },
+            packet_stream: self.packet_stream && supported.packet_stream,
+        }
+    }
+
+    /// Checks whether datagrams are prefixed with their type, allowing control frames
+    pub fn typed_framing(&self) -> bool {
+        self.protocol_version >= TYPED_FRAMING_VERSION
+    }
+
+    /// Checks whether the client follows redirects to other servers
+    pub fn redirects(&self) -> bool {
+        self.protocol_version >= REDIRECT_VERSION
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::features::Features;
+    use crate::utils::compression::Compression;
+
+    #[test]
+    fn test_negotiate() {
+        let requested = Features {
+            protocol_version: 2,
+            coalescing: true,
+            fec: true,
+            compression: Compression::Zstd,
+            packet_stream: true,
+        };
+        let supported = Features {
+            protocol_version: 1,
+            coalescing: true,
+            fec: false,
+            compression: Compression::Lz4,
+            packet_stream: false,
+        };
+        let negotiated = Features {
+            protocol_version: 1,
+            coalescing: true,
+            fec: false,
+            compression: Compression::None,
+            packet_stream: false,
+        };
+
+        assert_eq!(
+            requested.negotiate(&Features::default()),
+            Features::default()
+        );
+        assert_eq!(requested.negotiate(&requested), requested);
+        assert_eq!(requested.negotiate(&supported), negotiated);
+        assert!(requested.redirects());
+        assert!(!negotiated.redirects());
+    }
+
+    #[test]
+    fn test_missing_features_are_disabled() {
+        let features: Features = serde_json::from_str("{}").unwrap();
+
+        assert_eq!(features, Features::default());
+        assert!(!features.typed_framing());
+        assert!(!features.redirects());
+    }
+}
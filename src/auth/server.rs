@@ -7,19 +7,38 @@ use quinn::{Connection, RecvStream, SendStream, VarInt};
 use serde::{Deserialize, Serialize};
 use tokio::{io::AsyncReadExt, sync::RwLock, time::timeout};
 
-use super::{client::AuthClientMessage, user::UserDatabase};
+use super::{client::AuthClientMessage, features::Features, user::UserDatabase};
+use crate::constants::{
+    CLOSE_CODE_AUTH_FAILED, CLOSE_CODE_REDIRECT, CLOSE_REASON_AUTH_MESSAGE_REFUSED,
+};
+use crate::server::address_pool::AddressPool;
+use crate::utils::control::Redirect;
 
 //Internal authentication state
 #[derive(Clone, Debug, PartialEq)]
 pub enum AuthState {
     Unauthenticated,
     Authenticated(String),
+    Redirected(String),
+}
+
+/// How a tunnel admits new sessions
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Admission {
+    /// New sessions are accepted
+    Accept,
+    /// New sessions are redirected to the server with the given connection string
+    Redirect(String),
+    /// New sessions are refused, e.g. because the tunnel is draining
+    Refuse,
 }
 
 //Authentication message sent
 #[derive(Serialize, Deserialize)]
 pub enum AuthServerMessage {
     Authenticated(IpAddr, IpAddr),
+    AuthenticatedWithFeatures(IpAddr, IpAddr, Features),
+    Redirect(Redirect),
     Ok,
     Failed,
 }
@@ -28,30 +47,40 @@ pub enum AuthServerMessage {
 pub struct AuthServer {
     user_database: Arc<UserDatabase>,
     auth_state: RwLock<AuthState>,
-    client_address: IpNet,
+    address_pool: Arc<AddressPool>,
+    client_address: Option<IpNet>,
     connection: Arc<Connection>,
     send_stream: SendStream,
     recv_stream: RecvStream,
     auth_timeout: Duration,
+    supported_features: Features,
+    features: Features,
+    admission: Admission,
 }
 
 impl AuthServer {
     pub async fn new(
         user_database: Arc<UserDatabase>,
         connection: Arc<Connection>,
-        client_address: IpNet,
+        address_pool: Arc<AddressPool>,
         auth_timeout: Duration,
+        supported_features: Features,
+        admission: Admission,
     ) -> Result<Self> {
         let (send_stream, recv_stream) = connection.accept_bi().await?;
 
         Ok(Self {
             user_database,
             auth_state: RwLock::new(AuthState::Unauthenticated),
-            client_address,
+            address_pool,
+            client_address: None,
             connection,
             send_stream,
             recv_stream,
             auth_timeout,
+            supported_features,
+            features: Features::default(),
+            admission,
         })
     }
     ///Handles authentication for a client
@@ -67,13 +96,27 @@ impl AuthServer {
             (
                 AuthState::Unauthenticated,
                 Some(AuthClientMessage::Authentication(username, password)),
-            ) => self.authenticate_user(username, password).await,
+            ) => self.authenticate_user(username, password, None).await,
+            (
+                AuthState::Unauthenticated,
+                Some(AuthClientMessage::AuthenticationWithFeatures(username, password, features)),
+            ) => {
+                self.authenticate_user(username, password, Some(features))
+                    .await
+            }
             _ => self.handle_failure().await,
         }
     }
 
-    ///Authenticates username and password
-    async fn authenticate_user(&mut self, username: String, password: String) -> Result<()> {
+    ///Authenticates username and password, leasing an address and negotiating the requested
+    ///features if any. Clients are redirected or refused instead if the tunnel does not admit new
+    ///sessions
+    async fn authenticate_user(
+        &mut self,
+        username: String,
+        password: String,
+        requested_features: Option<Features>,
+    ) -> Result<()> {
         if self
             .user_database
             .authenticate(&username, password)
@@ -86,10 +129,42 @@ impl AuthServer {
             return Err(anyhow!("Invalid username or password"));
         }
 
-        let response = AuthServerMessage::Authenticated(
-            self.client_address.addr(),
-            self.client_address.netmask(),
-        );
+        match self.admission.clone() {
+            Admission::Accept => (),
+            Admission::Redirect(address)
+                if requested_features.is_some_and(|features| features.redirects()) =>
+            {
+                return self.redirect(username, address).await;
+            }
+            Admission::Redirect(_) | Admission::Refuse => {
+                self.close_connection("Server does not accept new sessions")
+                    .await?;
+
+                return Err(anyhow!("Server does not accept new sessions"));
+            }
+        }
+
+        let Some(client_address) = self.address_pool.lease(&username) else {
+            self.close_connection("No address available").await?;
+
+            return Err(anyhow!("Could not find an available address for client"));
+        };
+        self.client_address = Some(client_address);
+
+        let response = match requested_features {
+            Some(requested_features) => {
+                self.features = requested_features.negotiate(&self.supported_features);
+
+                AuthServerMessage::AuthenticatedWithFeatures(
+                    client_address.addr(),
+                    client_address.netmask(),
+                    self.features,
+                )
+            }
+            None => {
+                AuthServerMessage::Authenticated(client_address.addr(), client_address.netmask())
+            }
+        };
 
         self.send_message(response).await?;
         self.set_state(AuthState::Authenticated(username)).await;
@@ -97,9 +172,28 @@ impl AuthServer {
         Ok(())
     }
 
+    ///Redirects the client to another server, closing the connection once the client received
+    ///the redirect
+    async fn redirect(&mut self, username: String, address: String) -> Result<()> {
+        self.send_message(AuthServerMessage::Redirect(Redirect {
+            address: address.clone(),
+            deadline: Duration::ZERO,
+        }))
+        .await?;
+        self.send_stream.finish().await?;
+
+        self.connection
+            .close(VarInt::from_u32(CLOSE_CODE_REDIRECT), b"Redirected");
+
+        self.set_state(AuthState::Redirected(username)).await;
+
+        Ok(())
+    }
+
     ///Handles authentication failure
     async fn handle_failure(&mut self) -> Result<()> {
-        self.close_connection("Authentication failed").await?;
+        self.close_connection(CLOSE_REASON_AUTH_MESSAGE_REFUSED)
+            .await?;
 
         Err(anyhow!("Authentication failed"))
     }
@@ -110,7 +204,7 @@ impl AuthServer {
         self.send_stream.finish().await?;
 
         self.connection
-            .close(VarInt::from_u32(0x01), reason.as_bytes());
+            .close(VarInt::from_u32(CLOSE_CODE_AUTH_FAILED), reason.as_bytes());
 
         self.set_state(AuthState::Unauthenticated).await;
 
@@ -133,6 +227,16 @@ impl AuthServer {
         serde_json::from_slice(&buf).context("Failed to parse AuthClientMessage")
     }
 
+    ///Returns the address leased to the client once it is authenticated
+    pub fn get_client_address(&self) -> Option<IpNet> {
+        self.client_address
+    }
+
+    ///Returns the features negotiated with the client
+    pub fn get_features(&self) -> Features {
+        self.features
+    }
+
     pub async fn get_state(&self) -> AuthState {
         self.auth_state.read().await.clone()
     }
@@ -1,14 +1,26 @@
 use anyhow::Result;
 use clap::Parser;
+use rpassword::prompt_password;
 use rumble::client::RumbleClient;
 use rumble::config::{ClientConfig, FromPath};
-use rumble::utils::cli::Args;
+use rumble::profile::ClientProfile;
+use rumble::utils::cli::ClientArgs;
 use rumble::utils::tracing::enable_tracing;
+use std::path::Path;
 use tracing::error;
 
 #[tokio::main]
 async fn main() {
-    let args: Args = Args::parse();
+    let args: ClientArgs = ClientArgs::parse();
+
+    if let Some(profile) = &args.import {
+        if let Err(e) = import_profile(profile, &args.args.config_path, args.force) {
+            eprintln!("Failed to import the profile: {e}");
+            std::process::exit(1);
+        }
+
+        return;
+    }
 
     match run_client(args).await {
         Ok(_) => {}
@@ -17,10 +29,33 @@ async fn main() {
 }
 
 ///Runs the Rumble client.
-async fn run_client(args: Args) -> Result<()> {
-    let config = ClientConfig::from_path(&args.config_path, &args.env_prefix)?;
+async fn run_client(args: ClientArgs) -> Result<()> {
+    let config = ClientConfig::from_path(&args.args.config_path, &args.args.env_prefix)?;
     enable_tracing(&config.log.level);
 
     let client = RumbleClient::new(config);
     client.run().await
+}
+
+///Writes the client config from a profile URI or a file containing one.
+fn import_profile(profile: &str, config_path: &Path, overwrite: bool) -> Result<()> {
+    let uri = match Path::new(profile).is_file() {
+        true => std::fs::read_to_string(profile)?,
+        false => profile.to_string(),
+    };
+
+    let profile: ClientProfile = uri.parse()?;
+
+    let password = match profile.password {
+        Some(_) => None,
+        None => Some(prompt_password(format!(
+            "Enter password for user '{}': ",
+            profile.username
+        ))?),
+    };
+
+    profile.import(config_path, password, overwrite)?;
+    println!("Wrote the client config to {config_path:?}");
+
+    Ok(())
 }
\ No newline at end of file
@@ -1,16 +1,35 @@
 use anyhow::Result;
 use clap::Parser;
 use rumble::config::{FromPath, ServerConfig};
+use rumble::profile::ClientProfile;
 use rumble::server::RumbleServer;
-use rumble::utils::cli::Args;
+use rumble::utils::cli::{Args, ServerArgs};
+use rumble::utils::qr::QrCode;
+use rumble::utils::signal::{shutdown_signal, DrainSignal, ReloadSignal};
 use rumble::utils::tracing::enable_tracing;
-use tracing::error;
+#[cfg(not(target_os = "linux"))]
+use std::convert::Infallible;
+use std::path::PathBuf;
+use tracing::{error, info, warn};
+#[cfg(target_os = "linux")]
+use {
+    rumble::server::handoff::take_over, std::os::unix::net::UnixStream, tokio::net::UnixListener,
+};
 
 #[tokio::main]
 async fn main() {
-    let args: Args = Args::parse();
+    let args: ServerArgs = ServerArgs::parse();
 
-    match run_server(args).await {
+    if let (Some(tunnel), Some(user)) = (&args.export, &args.user) {
+        if let Err(e) = export_profile(&args, tunnel, user) {
+            eprintln!("Failed to export the profile: {e}");
+            std::process::exit(1);
+        }
+
+        return;
+    }
+
+    match run_server(args.args).await {
         Ok(_) => {}
         Err(e) => error!("A critical error occurred: {e}"),
     }
@@ -21,6 +40,112 @@ async fn run_server(args: Args) -> Result<()> {
     let config = ServerConfig::from_path(&args.config_path, &args.env_prefix)?;
     enable_tracing(&config.log.level);
 
+    let handoff_path = config.handoff_path.clone();
     let server = RumbleServer::new(config).await?;
-    server.run().await
-}
\ No newline at end of file
+    let mut reload_signal = ReloadSignal::new()?;
+    let mut drain_signal = DrainSignal::new()?;
+    let handoff_listener = listen_for_handoff(&server, handoff_path)?;
+
+    let signals = async {
+        loop {
+            tokio::select! {
+                _ = reload_signal.recv() => {
+                    info!("Received SIGHUP, reloading config");
+
+                    match ServerConfig::from_path(&args.config_path, &args.env_prefix) {
+                        Ok(config) => server.reload(config)?,
+                        Err(e) => warn!("Failed to reload config, keeping the current one: {e}"),
+                    }
+                }
+                _ = drain_signal.recv() => {
+                    info!("Received SIGUSR1, draining");
+                    server.drain();
+                }
+            }
+        }
+    };
+
+    tokio::select! {
+        result = server.run() => result,
+        result = signals => result,
+        stream = accept_handoff(&handoff_listener) => hand_over(&server, stream?).await,
+        signal = shutdown_signal() => {
+            info!("Received {}", signal?);
+            server.shutdown().await;
+
+            Ok(())
+        }
+    }
+}
+
+///Prints the profile of a user of a tunnel.
+fn export_profile(args: &ServerArgs, tunnel: &str, user: &str) -> Result<()> {
+    let config = ServerConfig::from_path(&args.args.config_path, &args.args.env_prefix)?;
+    let profile = ClientProfile::export(
+        &config,
+        tunnel,
+        user,
+        args.servers.clone(),
+        args.embed_certificate,
+    )?;
+    let uri = profile.to_string();
+
+    if args.qr {
+        print!("{}", QrCode::encode(uri.as_bytes())?.render());
+    }
+
+    println!("{uri}");
+
+    Ok(())
+}
+
+/// Takes over the tunnels of a running server process, if any, and listens for the next one.
+#[cfg(target_os = "linux")]
+fn listen_for_handoff(
+    server: &RumbleServer,
+    handoff_path: Option<PathBuf>,
+) -> Result<Option<UnixListener>> {
+    let Some(path) = handoff_path else {
+        return Ok(None);
+    };
+
+    let (handoffs, listener) = take_over(&path)?;
+    server.adopt(handoffs);
+    listener.set_nonblocking(true)?;
+
+    Ok(Some(UnixListener::from_std(listener)?))
+}
+
+/// Waits for a new server process to connect to the handoff socket.
+#[cfg(target_os = "linux")]
+async fn accept_handoff(listener: &Option<UnixListener>) -> Result<UnixStream> {
+    match listener {
+        Some(listener) => Ok(listener.accept().await?.0.into_std()?),
+        None => std::future::pending().await,
+    }
+}
+
+/// Hands the tunnels over to the new server process.
+#[cfg(target_os = "linux")]
+async fn hand_over(server: &RumbleServer, stream: UnixStream) -> Result<()> {
+    server.hand_over(stream).await
+}
+
+#[cfg(not(target_os = "linux"))]
+fn listen_for_handoff(_server: &RumbleServer, handoff_path: Option<PathBuf>) -> Result<()> {
+    if handoff_path.is_some() {
+        warn!("Handing over to a new server process is only supported on Linux");
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn accept_handoff(_listener: &()) -> Result<Infallible> {
+    std::future::pending().await
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn hand_over(_server: &RumbleServer, stream: Infallible) -> Result<()> {
+    match stream {}
+}
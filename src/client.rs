@@ -1,19 +1,60 @@
-use crate::auth::client::AuthClient;
+use crate::auth::client::{AuthClient, AuthOutcome};
+use crate::auth::features::Features;
 
 use crate::config::ClientConfig;
-use crate::constants::QUINN_RUNTIME;
+use crate::constants::{
+    CLIENT_CLOSE_TIMEOUT, CLIENT_MAX_REDIRECTS, CLIENT_RECONNECT_ATTEMPTS,
+    CLIENT_RECONNECT_INTERVAL, CLOSE_CODE_AUTH_FAILED, CLOSE_CODE_DEAD_PEER,
+    CLOSE_CODE_MIGRATION_FAILED, CLOSE_CODE_REDIRECT, CLOSE_CODE_RESTART, CLOSE_CODE_SHUTDOWN,
+    CLOSE_REASON_AUTH_MESSAGE_REFUSED, HAPPY_EYEBALLS_DELAY, INTERFACE_BATCH_SIZE,
+    MTU_CHECK_INTERVAL, QUINN_RUNTIME,
+};
+#[cfg(target_os = "linux")]
+use crate::constants::{MIGRATION_CHECK_INTERVAL, MIGRATION_TIMEOUT, ROAMING_SETTLE_TIME};
+use crate::stats::ConnectionStats;
+use crate::utils::control::{process_control_frames, ControlFrame, Redirect};
+use crate::utils::datagram::{DatagramReceiver, DatagramTransmitter};
+use crate::utils::happy_eyeballs::{race, resolve_candidates, Candidate};
+use crate::utils::ifreq::set_interface_mtu;
+use crate::utils::liveness::probe_liveness;
+use crate::utils::mss::clamp_mss;
+#[cfg(target_os = "linux")]
+use crate::utils::netlink::NetworkMonitor;
+use crate::utils::packet_sender::PacketSender;
+use crate::utils::packet_stream::receive_packet_streams;
+use crate::utils::signal::shutdown_signal;
 use crate::utils::socket::bind_socket;
+use crate::utils::tasks::{join_task, poll_once};
 use anyhow::{anyhow, Result};
-use quinn::{Connection, Endpoint};
+use bytes::Bytes;
+use quinn::{Connection, ConnectionError, Endpoint, VarInt};
+use rand::Rng;
 
-use std::net::{Ipv4Addr, SocketAddr, ToSocketAddrs};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
 
-use crate::utils::interface::{read_from_interface, set_up_interface, write_to_interface};
+use crate::utils::interface::{
+    set_up_interface, split_interface, Interface, InterfaceReader, InterfaceWriter,
+};
 use std::sync::Arc;
-use tokio::io::{ReadHalf, WriteHalf};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::time::{sleep, timeout};
 use tokio::try_join;
 use tracing::{debug, info, warn};
-use tun::AsyncDevice;
+
+/// How a session with the server ended
+enum SessionEnd {
+    /// The connection was closed for good
+    Closed,
+    /// The server is restarting and the client should reconnect
+    Restart,
+    /// The connection could not be migrated to a new network path and the client should reconnect
+    MigrationFailed,
+    /// The server redirected the client to another server
+    Redirect(Redirect),
+    /// The connection to the server with the given connection string was lost
+    ConnectionLost(String),
+}
 
 /// Rumble client that connects to a server and relays packets between the server and a TUN interface
 pub struct RumbleClient {
@@ -29,92 +70,391 @@ impl RumbleClient {
         Self { client_config }
     }
 
-    /// Connects to the server and starts the workers
+    /// Connects to the server and starts the workers.
+    ///
+    /// On SIGTERM/SIGINT the connection is closed, telling the server that the client is shutting
+    /// down. Dropping the TUN interface afterwards removes its address and routes.
+    ///
+    /// If the server is restarting or the connection could not follow a network change, the
+    /// client reconnects right away, retrying for a while until the server accepts the
+    /// connection. Reconnecting resumes the TLS session of the previous connection.
+    ///
+    /// If the server redirects the client, the client connects to the other server instead and
+    /// prefers it for later reconnects, falling back to the configured servers if it fails.
+    ///
+    /// If several servers are configured, the client races them and uses the one answering the
+    /// fastest. When the connection is lost, the client fails over to the other servers in order,
+    /// trying the lost server last.
     pub async fn run(&self) -> Result<()> {
-        let connection = self.connect_to_server().await?;
-        let mut auth_client =
-            AuthClient::new(&connection, &self.client_config.authentication).await?;
+        let quinn_config = self.client_config.as_quinn_client_config()?;
+        let mut servers = self.client_config.server_list();
+        let mut reconnect_attempts = None;
+        let mut redirects = 0;
 
-        let assigned_address = auth_client.authenticate().await?;
+        loop {
+            let session_end = self.run_session(&quinn_config, &servers).await;
+
+            if !matches!(session_end, Ok(SessionEnd::Redirect(_)) | Err(_)) {
+                redirects = 0;
+            }
+
+            match session_end {
+                Ok(SessionEnd::Closed) => return Ok(()),
+                Ok(SessionEnd::Restart) => {
+                    info!("Server is restarting, reconnecting");
+                    reconnect_attempts = Some(0);
+                }
+                Ok(SessionEnd::MigrationFailed) => {
+                    info!("Connection could not be migrated, reconnecting");
+                    reconnect_attempts = Some(0);
+                }
+                Ok(SessionEnd::Redirect(redirect)) => {
+                    redirects += 1;
+
+                    if redirects > CLIENT_MAX_REDIRECTS {
+                        return Err(anyhow!(
+                            "Redirected too many times, last to {}",
+                            redirect.address
+                        ));
+                    }
+
+                    info!("Redirected to {}, reconnecting", redirect.address);
+                    servers = redirect_servers(redirect.address, self.client_config.server_list());
+                    reconnect_attempts = Some(0);
+                }
+                Ok(SessionEnd::ConnectionLost(server)) => {
+                    warn!("Connection to {server} lost, failing over");
+                    servers.retain(|candidate| *candidate != server);
+                    servers.push(server);
+                    reconnect_attempts = Some(0);
+                }
+                Err(e) => match reconnect_attempts {
+                    Some(attempts) if attempts < CLIENT_RECONNECT_ATTEMPTS => {
+                        warn!("Failed to reconnect to the server: {e}");
+                        reconnect_attempts = Some(attempts + 1);
+
+                        tokio::select! {
+                            _ = sleep(CLIENT_RECONNECT_INTERVAL) => (),
+                            signal = shutdown_signal() => {
+                                info!("Received {}", signal?);
+                                return Ok(());
+                            }
+                        }
+                    }
+                    _ => return Err(e),
+                },
+            }
+        }
+    }
+
+    /// Connects to the server and relays packets until the connection is closed.
+    ///
+    /// Arguments
+    /// `quinn_config` - the Quinn client config, shared by all sessions to resume TLS sessions
+    /// `servers` - the connection strings of the servers in order of preference
+    ///
+    /// Returns
+    /// `SessionEnd` - how the session ended
+    async fn run_session(
+        &self,
+        quinn_config: &quinn::ClientConfig,
+        servers: &[String],
+    ) -> Result<SessionEnd> {
+        let (mut server, mut endpoint, mut connection) = self
+            .connect_to_server(quinn_config.clone(), servers)
+            .await?;
+        let requested_features = self.client_config.connection.features();
+        let mut auth_outcome = self.authenticate(&connection, requested_features).await?;
+
+        // Servers predating features refuse `AuthenticationWithFeatures` and close the connection.
+        // Refused credentials or sessions must not be tried a second time
+        if auth_outcome == AuthOutcome::Failed
+            && requested_features != Features::default()
+            && is_auth_message_refused(&connection.closed().await)
+        {
+            debug!("Authentication with features failed, retrying with basic authentication");
+
+            (server, endpoint, connection) = self
+                .connect_to_server(quinn_config.clone(), &[server])
+                .await?;
+            auth_outcome = self.authenticate(&connection, Features::default()).await?;
+        }
+
+        let (assigned_address, features) = match auth_outcome {
+            AuthOutcome::Authenticated(assigned_address, features) => (assigned_address, features),
+            AuthOutcome::Redirected(redirect) => return Ok(SessionEnd::Redirect(redirect)),
+            AuthOutcome::Failed => return Err(anyhow!("Authentication failed")),
+        };
 
         info!("Received client address: {assigned_address}");
+        debug!("Negotiated features: {features:?}");
 
-        let interface = set_up_interface(assigned_address, self.client_config.connection.mtu)?;
+        let interface = set_up_interface(
+            assigned_address,
+            self.client_config.connection.mtu,
+            self.client_config.connection.offload,
+        )?;
 
-        self.relay_packets(
-            connection,
+        let (redirect_sender, redirect_receiver) = unbounded_channel();
+        let roaming = self.roam(&endpoint, &connection, interface.name());
+        let relay = self.relay_packets(
+            connection.clone(),
             interface,
             self.client_config.connection.mtu as usize,
-        )
-        .await?;
+            features,
+            redirect_sender,
+        );
 
-        Ok(())
+        tokio::select! {
+            result = relay => {
+                if let Some(ConnectionError::ApplicationClosed(close)) = connection.close_reason() {
+                    if close.error_code == VarInt::from_u32(CLOSE_CODE_SHUTDOWN) {
+                        info!("Server is shutting down");
+                        return Ok(SessionEnd::Closed);
+                    }
+
+                    if close.error_code == VarInt::from_u32(CLOSE_CODE_RESTART) {
+                        return Ok(SessionEnd::Restart);
+                    }
+                }
+
+                if connection.close_reason().is_some_and(|reason| is_connection_lost(&reason)) {
+                    return Ok(SessionEnd::ConnectionLost(server));
+                }
+
+                result.map(|_| SessionEnd::Closed)
+            }
+            result = roaming => {
+                result?;
+                connection.close(
+                    VarInt::from_u32(CLOSE_CODE_MIGRATION_FAILED),
+                    b"Client migration failed",
+                );
+
+                Ok(SessionEnd::MigrationFailed)
+            }
+            redirect = Self::wait_for_redirect(redirect_receiver) => {
+                connection.close(VarInt::from_u32(CLOSE_CODE_REDIRECT), b"Client redirected");
+
+                Ok(SessionEnd::Redirect(redirect))
+            }
+            signal = shutdown_signal() => {
+                info!("Received {}, closing the connection", signal?);
+                connection.close(VarInt::from_u32(CLOSE_CODE_SHUTDOWN), b"Client shutting down");
+
+                if timeout(CLIENT_CLOSE_TIMEOUT, endpoint.wait_idle())
+                    .await
+                    .is_err()
+                {
+                    warn!("Connection did not close in time");
+                }
+
+                Ok(SessionEnd::Closed)
+            }
+        }
+    }
+
+    /// Migrates the connection to a new socket whenever the network changes, e.g. when moving
+    /// from Wi-Fi to LTE. Changes of the TUN interface of the tunnel itself are ignored.
+    ///
+    /// Rebinding makes Quinn send from the new socket right away, so the server validates the new
+    /// path and moves the connection over.
+    ///
+    /// Arguments
+    /// `endpoint` - the Quinn endpoint the connection was made from
+    /// `connection` - the connection to the server
+    /// `interface_name` - the name of the TUN interface
+    ///
+    /// Returns
+    /// `Ok` once a migration failed, i.e. the server did not answer on the new path in time. Never
+    /// returns if roaming is disabled or not supported.
+    async fn roam(
+        &self,
+        endpoint: &Endpoint,
+        connection: &Connection,
+        interface_name: String,
+    ) -> Result<()> {
+        if !self.client_config.roaming {
+            return std::future::pending().await;
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            let mut monitor = match NetworkMonitor::new()
+                .and_then(|mut monitor| monitor.ignore_interface(&interface_name).map(|_| monitor))
+            {
+                Ok(monitor) => monitor,
+                Err(e) => {
+                    warn!("Cannot watch for network changes, roaming is disabled: {e}");
+                    return std::future::pending().await;
+                }
+            };
+
+            loop {
+                monitor.changed(ROAMING_SETTLE_TIME).await?;
+                info!("Network changed, migrating the connection");
+
+                let received = connection.stats().udp_rx.datagrams;
+                endpoint.rebind(self.bind_quinn_socket(connection.remote_address())?)?;
+
+                let answered = timeout(MIGRATION_TIMEOUT, async {
+                    while connection.stats().udp_rx.datagrams == received {
+                        sleep(MIGRATION_CHECK_INTERVAL).await;
+                    }
+                })
+                .await;
+
+                if answered.is_err() {
+                    warn!("Server did not answer on the new network path");
+                    return Ok(());
+                }
+
+                info!(
+                    "Connection migrated to local address {:?}",
+                    endpoint.local_addr()
+                );
+            }
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = (endpoint, connection, interface_name);
+            warn!("Roaming is not supported on this platform");
+            std::future::pending().await
+        }
     }
 
-    /// Connects to the Rumble server.
+    /// Waits for the server to redirect the client, then waits a random part of the redirect
+    /// deadline so that not all clients of a draining server reconnect at once.
+    ///
+    /// Arguments
+    /// `redirects` - the queue of redirects received from the server
     ///
     /// Returns
-    /// `Connection` - connection representing the connection to the server
-    async fn connect_to_server(&self) -> Result<Connection> {
-        let quinn_config = self.client_config.as_quinn_client_config()?;
-        let endpoint = self.create_quinn_endpoint()?;
-
-        let server_hostname = self
-            .client_config
-            .connection_string
-            .split(':')
-            .next()
-            .ok_or_else(|| {
-                anyhow!(
-                    "Could not parse hostname from connection string '{}'",
-                    self.client_config.connection_string
-                )
-            })?;
-
-        let server_addr = self
-            .client_config
-            .connection_string
-            .to_socket_addrs()?
-            .next()
-            .ok_or_else(|| {
-                anyhow!(
-                    "Connection string '{}' is invalid",
-                    self.client_config.connection_string
-                )
-            })?;
-
-        info!("Connecting: {}", self.client_config.connection_string);
-
-        let connection = endpoint
-            .connect_with(quinn_config, server_addr, server_hostname)?
-            .await?;
+    /// `Redirect` - the redirect to be followed, never returns if the server does not redirect
+    async fn wait_for_redirect(mut redirects: UnboundedReceiver<Redirect>) -> Redirect {
+        let Some(redirect) = redirects.recv().await else {
+            return std::future::pending().await;
+        };
 
+        let delay = rand::thread_rng().gen_range(Duration::ZERO..=redirect.deadline / 2);
         info!(
-            "Connection established: {}",
-            self.client_config.connection_string
+            "Server redirects to {}, reconnecting in {delay:?}",
+            redirect.address
         );
+        sleep(delay).await;
 
-        Ok(connection)
+        redirect
+    }
+
+    /// Authenticates the client on a new connection.
+    ///
+    /// Arguments
+    /// `connection` - the connection to the server
+    /// `features` - the features to request, the basic authentication is used if none are set
+    ///
+    /// Returns
+    /// `AuthOutcome` - the outcome of the authentication
+    async fn authenticate(
+        &self,
+        connection: &Connection,
+        features: Features,
+    ) -> Result<AuthOutcome> {
+        AuthClient::new(connection, &self.client_config.authentication, features)
+            .await?
+            .authenticate()
+            .await
+    }
+
+    /// Connects to the Rumble server answering the fastest.
+    ///
+    /// All addresses of all servers are raced in a happy eyeballs style, each from its own
+    /// endpoint.
+    ///
+    /// Arguments
+    /// `quinn_config` - the Quinn client config
+    /// `servers` - the connection strings of the servers in order of preference
+    ///
+    /// Returns
+    /// `String` - the connection string of the server connected to
+    /// `Endpoint` - the Quinn endpoint the connection was made from
+    /// `Connection` - connection representing the connection to the server
+    async fn connect_to_server(
+        &self,
+        quinn_config: quinn::ClientConfig,
+        servers: &[String],
+    ) -> Result<(String, Endpoint, Connection)> {
+        let candidates =
+            resolve_candidates(servers, self.client_config.server_name.as_deref()).await?;
+        info!("Connecting: {}", servers.join(", "));
+
+        let (candidate, (endpoint, connection)) =
+            race(candidates, HAPPY_EYEBALLS_DELAY, |candidate: Candidate| {
+                let endpoint = self.create_quinn_endpoint(candidate.address);
+                let quinn_config = quinn_config.clone();
+
+                async move {
+                    let endpoint = endpoint?;
+                    let connection = endpoint
+                        .connect_with(quinn_config, candidate.address, &candidate.server_name)?
+                        .await?;
+
+                    Ok((endpoint, connection))
+                }
+            })
+            .await?;
+
+        info!("Connection established: {}", candidate.connection_string);
+
+        Ok((candidate.connection_string, endpoint, connection))
     }
 
     /// Creates a Quinn endpoint.
     ///
+    /// Arguments
+    /// `remote_address` - the address of the server, the endpoint uses the same address family
+    ///
     /// Returns
     /// `Endpoint` - Quinn endpoint
-    fn create_quinn_endpoint(&self) -> Result<Endpoint> {
-        let bind_addr: SocketAddr = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0);
+    fn create_quinn_endpoint(&self, remote_address: SocketAddr) -> Result<Endpoint> {
+        let socket = self.bind_quinn_socket(remote_address)?;
+        let endpoint_config = self.client_config.connection.as_endpoint_config()?;
+        let endpoint = Endpoint::new(endpoint_config, None, socket, QUINN_RUNTIME.clone())?;
+
+        Ok(endpoint)
+    }
+
+    /// Binds a new UDP socket for the Quinn endpoint.
+    ///
+    /// The socket is bound to the configured local address or else the unspecified address of
+    /// the family of the server address.
+    ///
+    /// Arguments
+    /// `remote_address` - the address of the server
+    ///
+    /// Returns
+    /// `UdpSocket` - the bound UDP socket
+    fn bind_quinn_socket(&self, remote_address: SocketAddr) -> Result<std::net::UdpSocket> {
+        let bind_ip: IpAddr = match (self.client_config.bind_address, remote_address) {
+            (Some(bind_address), _) => bind_address,
+            (None, SocketAddr::V4(_)) => Ipv4Addr::UNSPECIFIED.into(),
+            (None, SocketAddr::V6(_)) => Ipv6Addr::UNSPECIFIED.into(),
+        };
+
+        if bind_ip.is_ipv4() != remote_address.is_ipv4() {
+            return Err(anyhow!(
+                "Cannot reach {remote_address} from the local address {bind_ip}"
+            ));
+        }
+
+        let bind_addr = SocketAddr::new(bind_ip, 0);
         debug!("QUIC socket local address: {:?}", bind_addr);
 
-        let socket = bind_socket(
+        bind_socket(
             bind_addr,
             self.client_config.connection.send_buffer_size as usize,
             self.client_config.connection.recv_buffer_size as usize,
-        )?;
-
-        let endpoint_config = self.client_config.connection.as_endpoint_config()?;
-        let endpoint = Endpoint::new(endpoint_config, None, socket, QUINN_RUNTIME.clone())?;
-
-        Ok(endpoint)
+        )
     }
 
     /// Relays packets between the TUN interface and the Rumble server
@@ -122,29 +462,78 @@ impl RumbleClient {
     /// Arguments
     /// `connection` - Quinn connection representing the connection to the server
     /// `interface` - TUN interface
+    /// `interface_mtu` - MTU of the TUN interface
+    /// `features` - features negotiated with the server
+    /// `redirects` - the queue of redirects received from the server
     async fn relay_packets(
         &self,
         connection: Connection,
-        interface: AsyncDevice,
+        interface: Interface,
         interface_mtu: usize,
+        features: Features,
+        redirects: UnboundedSender<Redirect>,
     ) -> Result<()> {
         let connection = Arc::new(connection);
-        let (read, write) = tokio::io::split(interface);
+        let stats = Arc::new(ConnectionStats::default());
+        let interface_name = interface.name();
+        let (read, write) = split_interface(interface, interface_mtu);
+        let (interface_sender, interface_receiver) = unbounded_channel();
+
+        let transmitter =
+            DatagramTransmitter::new(connection.clone(), &self.client_config.connection, features);
+        let (control_queue, control_frames) = unbounded_channel();
+        let (packet_sender, send_queued_packets) = PacketSender::new(
+            transmitter.clone(),
+            &self.client_config.connection,
+            features,
+            interface_sender.clone(),
+            stats.clone(),
+        );
 
-        let (outbound_task, inbound_task) = try_join!(
-            tokio::spawn(Self::process_outbound_traffic(
-                connection.clone(),
+        let result = try_join!(
+            join_task(tokio::spawn(Self::process_outbound_traffic(
                 read,
-                interface_mtu
-            )),
-            tokio::spawn(Self::process_inbound_traffic(
-                connection.clone(), 
+                packet_sender
+            ))),
+            join_task(tokio::spawn(Self::process_inbound_traffic(
+                transmitter.clone(),
                 write,
-            )),
-        )?;
+                interface_receiver,
+                self.client_config.connection.mss_clamp(),
+                DatagramReceiver::new(features, stats.clone()),
+                control_queue,
+            ))),
+            join_task(tokio::spawn(process_control_frames(
+                transmitter.clone(),
+                control_frames,
+                stats.clone(),
+                Some(redirects),
+            ))),
+            join_task(tokio::spawn(probe_liveness(
+                transmitter.clone(),
+                self.client_config
+                    .connection
+                    .liveness_interval
+                    .filter(|_| features.typed_framing()),
+                self.client_config.connection.liveness_timeout,
+                stats.clone(),
+            ))),
+            join_task(tokio::spawn(send_queued_packets)),
+            join_task(tokio::spawn(receive_packet_streams(
+                connection.clone(),
+                interface_sender
+            ))),
+            join_task(tokio::spawn(Self::watch_mtu(
+                transmitter,
+                interface_name,
+                interface_mtu as u32,
+                stats.clone(),
+            ))),
+        );
 
-        inbound_task?;
-        outbound_task?;
+        info!("Connection statistics: {}", stats.snapshot());
+
+        result?;
 
         Ok(())
     }
@@ -152,63 +541,210 @@ impl RumbleClient {
     /// Handles incoming packets from the TUN interface and relays them to the server
     ///
     /// Arguments
-    /// `connection` - Quinn connection representing the connection to the server
     /// `read_interface` - read half of the TUN interface
-    /// `interface_mtu` - MTU of the TUN interface
+    /// `packet_sender` - sender of the packets to the server
     async fn process_outbound_traffic(
-        connection: Arc<Connection>,
-        mut read_interface: ReadHalf<AsyncDevice>,
-        interface_mtu: usize,
+        mut read_interface: InterfaceReader,
+        packet_sender: PacketSender,
     ) -> Result<()> {
         debug!("Started outbound traffic task (interface -> QUIC tunnel)");
 
-        loop {
-            let quinn_mtu = connection
-                .max_datagram_size()
-                .ok_or_else(|| anyhow!("The Rumble server does not support datagram transfer"))?;
+        let mut batch = Vec::with_capacity(INTERFACE_BATCH_SIZE);
 
-            let data = read_from_interface(&mut read_interface, interface_mtu).await?;
+        loop {
+            read_interface.read_batch(&mut batch).await?;
 
-            if data.len() > quinn_mtu {
-                warn!(
-                    "Dropping packet of size {} due to maximum datagram size being {}",
-                    data.len(),
-                    quinn_mtu
-                );
-                continue;
+            for data in batch.drain(..) {
+                packet_sender.send(data)?;
             }
-
-            debug!(
-                "Sending {} bytes to {:?}",
-                data.len(),
-                connection.remote_address()
-            );
-
-            connection.send_datagram(data)?;
         }
     }
 
     /// Handles incoming packets from the Rumble server and relays them to the TUN interface.
     ///
     /// Arguments
-    /// `connection` - Quinn connection representing the connection to the server
+    /// `transmitter` - the transmitter of datagrams to the server
     /// `write_interface` - write half of the TUN interface
+    /// `queued_packets` - queue of packets received over the packet stream or generated locally
+    /// `mss_clamp` - the MTU to clamp the MSS of TCP SYN packets to, if enabled
+    /// `receiver` - the receiver decoding datagrams into packets
+    /// `control_queue` - the queue of received control frames
     async fn process_inbound_traffic(
-        connection: Arc<Connection>,
-        mut write_interface: WriteHalf<AsyncDevice>,
+        transmitter: DatagramTransmitter,
+        mut write_interface: InterfaceWriter,
+        mut queued_packets: UnboundedReceiver<Bytes>,
+        mss_clamp: Option<usize>,
+        mut receiver: DatagramReceiver,
+        control_queue: UnboundedSender<ControlFrame>,
     ) -> Result<()> {
         debug!("Started inbound traffic task (QUIC tunnel -> interface)");
 
+        let connection = transmitter.connection();
+        let mut batch = Vec::with_capacity(INTERFACE_BATCH_SIZE);
+
         loop {
-            let data = connection.read_datagram().await?;
+            let frame = tokio::select! {
+                datagram = connection.read_datagram() => receiver.receive(datagram?, &mut batch),
+                Some(packet) = queued_packets.recv() => {
+                    batch.push(packet);
+                    None
+                }
+            };
+
+            if let Some(frame) = frame {
+                control_queue.send(frame)?;
+            }
+
+            // Pick up datagrams that have already arrived so they can be written as one batch
+            while batch.len() < INTERFACE_BATCH_SIZE {
+                match poll_once(connection.read_datagram()).await {
+                    Some(Ok(data)) => {
+                        if let Some(frame) = receiver.receive(data, &mut batch) {
+                            control_queue.send(frame)?;
+                        }
+                    }
+                    _ => break,
+                }
+            }
+
+            if batch.is_empty() {
+                continue;
+            }
 
             debug!(
-                "Received {} bytes from {:?}",
-                data.len(),
+                "Received {} datagrams from {:?}",
+                batch.len(),
                 connection.remote_address()
             );
 
-            write_to_interface(&mut write_interface, data).await?;
+            if let Some(mtu) = mss_clamp {
+                let mtu = transmitter
+                    .max_datagram_size()
+                    .map_or(mtu, |size| size.min(mtu));
+
+                for data in batch.iter_mut() {
+                    *data = clamp_mss(std::mem::take(data), mtu);
+                }
+            }
+
+            write_interface.write_batch(&mut batch).await?;
+        }
+    }
+
+    /// Adjusts the MTU of the TUN interface to the maximum datagram size of the connection.
+    ///
+    /// Arguments
+    /// `transmitter` - the transmitter of datagrams to the server
+    /// `interface_name` - the name of the TUN interface
+    /// `configured_mtu` - the configured MTU, the interface MTU never exceeds it
+    /// `stats` - statistics of the connection
+    async fn watch_mtu(
+        transmitter: DatagramTransmitter,
+        interface_name: String,
+        configured_mtu: u32,
+        stats: Arc<ConnectionStats>,
+    ) -> Result<()> {
+        let mut current_mtu = configured_mtu;
+        stats.set_mtu(current_mtu);
+
+        loop {
+            let max_datagram_size = transmitter
+                .max_datagram_size()
+                .ok_or_else(|| anyhow!("The Rumble server does not support datagram transfer"))?;
+            let mtu = configured_mtu.min(max_datagram_size as u32);
+
+            if mtu != current_mtu {
+                match set_interface_mtu(&interface_name, mtu) {
+                    Ok(()) => {
+                        info!(
+                            "Changed MTU of interface {interface_name} from {current_mtu} to {mtu}"
+                        );
+                        stats.set_mtu(mtu);
+                        current_mtu = mtu;
+                    }
+                    Err(e) => warn!("Failed to change MTU of interface {interface_name}: {e}"),
+                }
+            }
+
+            tokio::select! {
+                _ = transmitter.connection().closed() => return Ok(()),
+                _ = sleep(MTU_CHECK_INTERVAL) => (),
+            }
+        }
+    }
+}
+
+/// Orders the servers to connect to after a redirect.
+///
+/// Arguments
+/// `redirect` - the connection string of the server the client is redirected to
+/// `servers` - the configured servers in order of preference
+///
+/// Returns
+/// `Vec<String>` - the redirect target followed by the configured servers
+fn redirect_servers(redirect: String, mut servers: Vec<String>) -> Vec<String> {
+    servers.retain(|server| *server != redirect);
+    servers.insert(0, redirect);
+
+    servers
+}
+
+/// Checks whether the server closed a connection because it did not understand the
+/// authentication message, rather than because it refused the client.
+///
+/// Arguments
+/// `reason` - the reason the connection was closed
+///
+/// Returns
+/// `true` if the server could not handle the authentication message
+fn is_auth_message_refused(reason: &ConnectionError) -> bool {
+    match reason {
+        ConnectionError::ApplicationClosed(close) => {
+            close.error_code == VarInt::from_u32(CLOSE_CODE_AUTH_FAILED)
+                && close.reason == CLOSE_REASON_AUTH_MESSAGE_REFUSED.as_bytes()
+        }
+        _ => false,
+    }
+}
+
+/// Checks whether a connection was lost rather than closed on purpose, in which case the client
+/// fails over to another server.
+///
+/// Arguments
+/// `reason` - the reason the connection was closed
+///
+/// Returns
+/// `true` if the server stopped answering or the connection broke down
+fn is_connection_lost(reason: &ConnectionError) -> bool {
+    match reason {
+        ConnectionError::TimedOut
+        | ConnectionError::Reset
+        | ConnectionError::TransportError(_)
+        | ConnectionError::ConnectionClosed(_) => true,
+        // Closed by the liveness probe, the other ways of closing end the session themselves
+        ConnectionError::LocallyClosed => true,
+        ConnectionError::ApplicationClosed(close) => {
+            close.error_code == VarInt::from_u32(CLOSE_CODE_DEAD_PEER)
         }
+        ConnectionError::VersionMismatch => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::redirect_servers;
+
+    #[test]
+    fn test_redirect_servers() {
+        let servers = vec!["a.example.com".to_string(), "b.example.com".to_string()];
+
+        assert_eq!(
+            redirect_servers("c.example.com".to_string(), servers.clone()),
+            ["c.example.com", "a.example.com", "b.example.com"]
+        );
+        assert_eq!(
+            redirect_servers("b.example.com".to_string(), servers),
+            ["b.example.com", "a.example.com"]
+        );
     }
-}
\ No newline at end of file
+}
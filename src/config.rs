@@ -1,22 +1,29 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use figment::{
-    providers::{Env, Format, Toml},
+    providers::{Env, Format, Serialized, Toml},
+    value::Dict,
     Figment,
 };
-use quinn::{EndpointConfig, MtuDiscoveryConfig, TransportConfig};
+use quinn::congestion::{BbrConfig, CubicConfig, NewRenoConfig};
+use quinn::{EndpointConfig, MtuDiscoveryConfig, TransportConfig, VarInt};
 use rustls::{Certificate, RootCertStore};
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use std::collections::HashMap;
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr};
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::{collections::hash_map::Entry, time::Duration};
 
+use crate::auth::features::Features;
 use crate::constants::{
-    QUIC_MTU_OVERHEAD, RUMBLE_CIPHER_SUITES, TLS_ALPN_PROTOCOLS, TLS_PROTOCOL_VERSIONS,
+    DEFAULT_PORT, PROTOCOL_VERSION, QUIC_MTU_OVERHEAD, RUMBLE_CIPHER_SUITES, TLS_ALPN_PROTOCOLS,
+    TLS_PROTOCOL_VERSIONS,
 };
-use crate::utils::certificates::{load_certificates_from_file, load_private_key_from_file};
+use crate::utils::certificate_resolver::ReloadingCertificateResolver;
+use crate::utils::certificates::{load_certificates_from_file, load_system_certificates};
+use crate::utils::compression::Compression;
+use crate::utils::pinning::PinnedServerVerifier;
 use tracing::{error, warn};
 
 /// Config for a Rumble server.
@@ -29,6 +36,66 @@ pub struct ServerConfig {
     pub connection: ConnectionConfig,
     /// Logging config
     pub log: LogConfig,
+    /// Tunnel supervision config
+    #[serde(default)]
+    pub supervisor: SupervisorConfig,
+    /// The longest time to wait for connections to close when shutting down
+    #[serde(default = "default_drain_deadline")]
+    pub drain_deadline: Duration,
+    /// Unix socket to hand the tunnels over to a new server process on, disabled if unset
+    pub handoff_path: Option<PathBuf>,
+    /// Config for taking the server out of rotation
+    #[serde(default)]
+    pub drain: DrainConfig,
+}
+
+/// Config for draining the server, e.g. for maintenance
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct DrainConfig {
+    /// Connection string of the server clients are redirected to, the redirect of each tunnel is
+    /// used if unset
+    pub redirect: Option<String>,
+    /// The time clients have to reconnect before their connections are closed
+    #[serde(default = "default_redirect_deadline")]
+    pub deadline: Duration,
+}
+
+impl Default for DrainConfig {
+    fn default() -> Self {
+        Self {
+            redirect: None,
+            deadline: default_redirect_deadline(),
+        }
+    }
+}
+
+/// Config for restarting failed tunnels
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct SupervisorConfig {
+    /// The delay before the first restart of a failed tunnel, doubled with every further failure
+    #[serde(default = "default_initial_backoff")]
+    pub initial_backoff: Duration,
+    /// The longest delay between restarts
+    #[serde(default = "default_max_backoff")]
+    pub max_backoff: Duration,
+    /// The number of consecutive failed restarts after which a tunnel is given up, unlimited if
+    /// unset
+    #[serde(default = "default_max_retries")]
+    pub max_retries: Option<u32>,
+    /// The time a restarted tunnel has to run without failing to be considered healthy again
+    #[serde(default = "default_stable_period")]
+    pub stable_period: Duration,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        Self {
+            initial_backoff: default_initial_backoff(),
+            max_backoff: default_max_backoff(),
+            max_retries: default_max_retries(),
+            stable_period: default_stable_period(),
+        }
+    }
 }
 
 /// Config for a Rumble tunnel
@@ -46,12 +113,23 @@ pub struct TunnelConfig {
     /// Port to bind the tunnel to
     #[serde(default = "default_bind_port")]
     pub bind_port: u16,
+    /// Number of workers sharing the port of the tunnel, each with its own socket and endpoint
+    #[serde(default = "default_workers")]
+    pub workers: u8,
     /// Address of this tunnel
     pub address_tunnel: Ipv4Addr,
     /// Address mask for this tunnel
     pub address_mask: Ipv4Addr,
     /// Path to a file containing a list of users and their password hashes
     pub users_file: PathBuf,
+    /// Connection string of the server new clients are redirected to, e.g. to balance the load
+    pub redirect: Option<String>,
+    /// Connection settings of this tunnel that override the global connection config
+    #[serde(default, rename = "connection")]
+    connection_overrides: Option<Dict>,
+    /// The connection config of this tunnel, if it overrides the global one
+    #[serde(skip)]
+    connection: Option<ConnectionConfig>,
 }
 
 /// Config for a Rumble client
@@ -59,10 +137,21 @@ pub struct TunnelConfig {
 pub struct ClientConfig {
     /// Connection string to be used to connect to a Rumble server
     pub connection_string: String,
+    /// Connection strings of further servers, raced against the first one and failed over to
+    #[serde(default)]
+    pub servers: Vec<String>,
+    /// Name to verify the server certificates against, defaults to the host of the connection
+    /// string, e.g. when connecting to an IP address
+    pub server_name: Option<String>,
+    /// Local address to bind to, defaults to the unspecified address of the server's family
+    pub bind_address: Option<IpAddr>,
     /// Authentication config
     pub authentication: ClientAuthenticationConfig,
     /// Misc connection config
     pub connection: ConnectionConfig,
+    /// Whether to migrate the connection when the network changes, e.g. from Wi-Fi to LTE
+    #[serde(default = "default_roaming")]
+    pub roaming: bool,
     /// Logging config
     pub log: LogConfig,
 }
@@ -75,7 +164,15 @@ pub struct ClientAuthenticationConfig {
     /// The password to use for authentication
     pub password: String,
     /// A list of trusted certificates
+    #[serde(default)]
     pub trusted_certificates: Vec<PathBuf>,
+    /// Base64 SHA-256 hashes of the public keys the server may present, several allow rotating
+    /// keys, e.g. `sha256/wQwFzxtrPk3uoDIsf3DyvFOTILai8FXGrTnxicw0pqw=`
+    #[serde(default)]
+    pub pinned_keys: Vec<String>,
+    /// Whether to trust the certificate authorities of the operating system
+    #[serde(default)]
+    pub system_trust_store: bool,
 }
 
 /// Misc connection config
@@ -86,15 +183,101 @@ pub struct ConnectionConfig {
     /// Timeout
     #[serde(default = "default_timeout")]
     pub timeout: Duration,
-    /// Keep alive interval
+    /// Keep alive interval of the QUIC connection, zero disables keep-alives
     #[serde(default = "default_keep_alive_interval")]
     pub keep_alive_interval: Duration,
+    /// Interval of application-level liveness probes, probing is disabled if unset. Has to be
+    /// shorter than `liveness_timeout`
+    pub liveness_interval: Option<Duration>,
+    /// The time without an answer to liveness probes after which the peer is considered dead
+    #[serde(default = "default_liveness_timeout")]
+    pub liveness_timeout: Duration,
+    /// The time without tunnel traffic after which a session is disconnected, if set
+    pub session_idle_timeout: Option<Duration>,
     /// The size of the send buffer of the socket and Quinn endpoint
     #[serde(default = "default_buffer_size")]
     pub send_buffer_size: u64,
     /// The size of the receive buffer of the socket and Quinn endpoint
     #[serde(default = "default_buffer_size")]
     pub recv_buffer_size: u64,
+    /// Whether to open the TUN interface with TSO/GSO and checksum offload (Linux only)
+    #[serde(default)]
+    pub offload: bool,
+    /// What to do with packets that do not fit into a QUIC datagram
+    #[serde(default)]
+    pub oversized_packets: OversizedPacketPolicy,
+    /// Whether to clamp the MSS of TCP SYN packets to the effective MTU of the tunnel
+    #[serde(default)]
+    pub mss_clamping: bool,
+    /// Whether to coalesce multiple small packets into a single datagram
+    #[serde(default)]
+    pub coalescing: bool,
+    /// The longest time a packet may wait for other packets to be coalesced with
+    #[serde(default = "default_coalescing_deadline")]
+    pub coalescing_deadline: Duration,
+    /// Whether to protect datagrams with XOR parity forward error correction
+    #[serde(default)]
+    pub fec: bool,
+    /// The number of parity datagrams sent per data datagram when FEC is enabled
+    #[serde(default = "default_fec_ratio")]
+    pub fec_ratio: f64,
+    /// Whether to raise the FEC ratio above `fec_ratio` when the connection loses packets
+    #[serde(default)]
+    pub fec_adaptive: bool,
+    /// The compression algorithm to apply to packets, both peers have to use the same one
+    #[serde(default)]
+    pub compression: Compression,
+    /// Tuning of the QUIC transport, unset values keep the Quinn defaults
+    #[serde(default)]
+    pub transport: TransportTuning,
+}
+
+/// Tuning of the QUIC transport
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+pub struct TransportTuning {
+    /// The congestion controller to use
+    #[serde(default)]
+    pub congestion_controller: CongestionController,
+    /// The number of bytes the peer may send on a single stream before receiving an update
+    pub stream_receive_window: Option<u64>,
+    /// The number of bytes the peer may send on all streams before receiving an update
+    pub receive_window: Option<u64>,
+    /// The number of unacknowledged bytes that may be in flight across all streams
+    pub send_window: Option<u64>,
+    /// The number of bytes of received datagrams buffered before the oldest are dropped
+    pub datagram_receive_buffer_size: Option<usize>,
+    /// The number of bytes of outgoing datagrams buffered before the oldest are dropped
+    pub datagram_send_buffer_size: Option<usize>,
+    /// The round trip time assumed before it has been measured
+    pub initial_rtt: Option<Duration>,
+}
+
+/// Congestion control algorithm of the QUIC connection
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CongestionController {
+    /// NewReno, the classic loss-based algorithm
+    NewReno,
+    /// CUBIC, the loss-based default of most operating systems
+    #[default]
+    Cubic,
+    /// BBR, which models the bottleneck bandwidth and RTT instead of reacting to losses
+    Bbr,
+}
+
+/// Handling of packets that exceed the maximum QUIC datagram size
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OversizedPacketPolicy {
+    /// Drop the packet
+    Drop,
+    /// Send the packet over a dedicated unidirectional QUIC stream, falling back to `Icmp` if the
+    /// peer does not support packet streams
+    #[default]
+    Stream,
+    /// Drop the packet and reply to the sender with an ICMP "Fragmentation Needed" or
+    /// "Packet Too Big" message carrying the usable MTU
+    Icmp,
 }
 
 /// Logging config
@@ -161,6 +344,21 @@ impl ConfigInit<ServerConfig> for ServerConfig {
             }
         }
 
+        let global_connection = figment.find_value("connection")?;
+
+        for tunnel in config.tunnels.values_mut() {
+            if let Some(overrides) = &tunnel.connection_overrides {
+                let connection = Figment::from(Serialized::defaults(&global_connection))
+                    .merge(Serialized::defaults(overrides))
+                    .extract()
+                    .map_err(|e| {
+                        anyhow!("Invalid connection config of tunnel '{}': {e}", tunnel.name)
+                    })?;
+
+                tunnel.connection = Some(connection);
+            }
+        }
+
         Ok(config)
     }
 }
@@ -180,7 +378,11 @@ fn default_bind_address() -> Ipv4Addr {
 }
 
 fn default_bind_port() -> u16 {
-    55555
+    DEFAULT_PORT
+}
+
+fn default_workers() -> u8 {
+    1
 }
 
 fn default_buffer_size() -> u64 {
@@ -195,9 +397,65 @@ fn default_keep_alive_interval() -> Duration {
     Duration::from_secs(25)
 }
 
+fn default_redirect_deadline() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_roaming() -> bool {
+    true
+}
+
+fn default_drain_deadline() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_initial_backoff() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_max_backoff() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_max_retries() -> Option<u32> {
+    Some(10)
+}
+
+fn default_stable_period() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_liveness_timeout() -> Duration {
+    Duration::from_secs(15)
+}
+
+fn default_coalescing_deadline() -> Duration {
+    Duration::from_millis(1)
+}
+
+fn default_fec_ratio() -> f64 {
+    0.1
+}
+
 impl ClientConfig {
+    /// Returns the connection strings of all servers in order of preference, without duplicates
+    pub fn server_list(&self) -> Vec<String> {
+        let mut servers = vec![self.connection_string.clone()];
+
+        for server in &self.servers {
+            if !servers.contains(server) {
+                servers.push(server.clone());
+            }
+        }
+
+        servers
+    }
+
     /// Creates Quinn client config from the Rumble client config.
     ///
+    /// Servers are verified against the trusted certificates and the system trust store. If keys
+    /// are pinned, the server has to present one of them in addition.
+    ///
     /// Returns
     /// `quinn::ClientConfig` - Quinn client config
     pub fn as_quinn_client_config(&self) -> Result<quinn::ClientConfig> {
@@ -221,25 +479,51 @@ impl ClientConfig {
             cert_store.add(&certificate)?;
         }
 
-        let mut rustls_config = rustls::ClientConfig::builder()
+        if self.authentication.system_trust_store {
+            let system_certificates: Vec<Vec<u8>> = load_system_certificates()?
+                .into_iter()
+                .map(|certificate| certificate.0)
+                .collect();
+
+            let (_, ignored) = cert_store.add_parsable_certificates(&system_certificates);
+
+            if ignored > 0 {
+                warn!("Ignored {ignored} unparsable certificates of the system trust store");
+            }
+        }
+
+        let pinned_keys = &self.authentication.pinned_keys;
+
+        if cert_store.is_empty() && pinned_keys.is_empty() {
+            return Err(anyhow!(
+                "No trusted certificates could be loaded and no keys are pinned, the server cannot be verified"
+            ));
+        }
+
+        let builder = rustls::ClientConfig::builder()
             .with_cipher_suites(RUMBLE_CIPHER_SUITES)
             .with_safe_default_kx_groups()
-            .with_protocol_versions(TLS_PROTOCOL_VERSIONS)?
-            .with_root_certificates(cert_store)
-            .with_no_client_auth();
+            .with_protocol_versions(TLS_PROTOCOL_VERSIONS)?;
+
+        let mut rustls_config = match pinned_keys.is_empty() {
+            true => builder
+                .with_root_certificates(cert_store)
+                .with_no_client_auth(),
+            false => {
+                let roots = (!cert_store.is_empty()).then_some(cert_store);
+                let verifier = PinnedServerVerifier::new(pinned_keys, roots)?;
+
+                builder
+                    .with_custom_certificate_verifier(Arc::new(verifier))
+                    .with_no_client_auth()
+            }
+        };
 
         rustls_config.alpn_protocols = TLS_ALPN_PROTOCOLS.clone();
 
         let mut quinn_config = quinn::ClientConfig::new(Arc::new(rustls_config));
-        let mut transport_config = TransportConfig::default();
-        let mut mtu_config = MtuDiscoveryConfig::default();
-
-        transport_config.max_idle_timeout(Some(self.connection.timeout.try_into()?));
-        transport_config.keep_alive_interval(Some(self.connection.keep_alive_interval));
-
-        mtu_config.upper_bound(self.connection.mtu as u16 + QUIC_MTU_OVERHEAD);
+        let transport_config = self.connection.as_transport_config()?;
 
-        transport_config.mtu_discovery_config(Some(mtu_config));
         quinn_config.transport_config(Arc::new(transport_config));
 
         Ok(quinn_config)
@@ -247,40 +531,53 @@ impl ClientConfig {
 }
 
 impl TunnelConfig {
+    /// Returns the connection config of this tunnel.
+    ///
+    /// Arguments
+    /// `global_connection_config` - the global connection config of the server
+    ///
+    /// Returns
+    /// `&ConnectionConfig` - the global connection config merged with the overrides of the tunnel
+    pub fn connection_config<'a>(
+        &'a self,
+        global_connection_config: &'a ConnectionConfig,
+    ) -> &'a ConnectionConfig {
+        self.connection.as_ref().unwrap_or(global_connection_config)
+    }
+
+    /// Loads the certificate of the tunnel.
+    ///
+    /// Returns
+    /// `ReloadingCertificateResolver` - resolver serving the certificate until its files change
+    pub fn certificate_resolver(&self) -> Result<ReloadingCertificateResolver> {
+        ReloadingCertificateResolver::new(&self.certificate_file, &self.certificate_key_file)
+    }
+
     /// Creates Quinn server config from the Rumble tunnel config.
     ///
     /// Arguments
     /// `connection_config` - the connection config to use
+    /// `certificate_resolver` - resolver of the certificate presented to clients
     ///
     /// Returns
     /// `quinn::ServerConfig` - the Quinn server config
     pub fn as_quinn_server_config(
         &self,
         connection_config: &ConnectionConfig,
+        certificate_resolver: Arc<ReloadingCertificateResolver>,
     ) -> Result<quinn::ServerConfig> {
-        let certificate_file_path = self.certificate_file.clone();
-        let certificate_key_path = self.certificate_key_file.clone();
-        let key = load_private_key_from_file(&certificate_key_path)?;
-        let certs = load_certificates_from_file(&certificate_file_path)?;
-
         let mut rustls_config = rustls::ServerConfig::builder()
             .with_cipher_suites(RUMBLE_CIPHER_SUITES)
             .with_safe_default_kx_groups()
             .with_protocol_versions(TLS_PROTOCOL_VERSIONS)?
             .with_no_client_auth()
-            .with_single_cert(certs, key)?;
+            .with_cert_resolver(certificate_resolver);
 
         rustls_config.alpn_protocols = TLS_ALPN_PROTOCOLS.clone();
 
         let mut quinn_config = quinn::ServerConfig::with_crypto(Arc::new(rustls_config));
-        let mut transport_config = TransportConfig::default();
-        let mut mtu_config = MtuDiscoveryConfig::default();
-
-        transport_config.max_idle_timeout(Some(connection_config.timeout.try_into()?));
+        let transport_config = connection_config.as_transport_config()?;
 
-        mtu_config.upper_bound(connection_config.mtu as u16 + QUIC_MTU_OVERHEAD);
-
-        transport_config.mtu_discovery_config(Some(mtu_config));
         quinn_config.transport_config(Arc::new(transport_config));
 
         Ok(quinn_config)
@@ -288,10 +585,315 @@ impl TunnelConfig {
 }
 
 impl ConnectionConfig {
+    /// Returns the MTU that the MSS of TCP SYN packets should be clamped to, if MSS clamping is
+    /// enabled.
+    pub fn mss_clamp(&self) -> Option<usize> {
+        self.mss_clamping.then_some(self.mtu as usize)
+    }
+
+    /// Returns the optional tunnel features enabled in the config.
+    ///
+    /// Returns
+    /// `Features` - features requested by a client or supported by a server
+    pub fn features(&self) -> Features {
+        Features {
+            protocol_version: PROTOCOL_VERSION,
+            coalescing: self.coalescing,
+            fec: self.fec,
+            compression: self.compression,
+            // Packet streams are always received, regardless of the own oversized packet policy
+            packet_stream: true,
+        }
+    }
+
+    /// Creates the Quinn transport config shared by clients and servers.
+    ///
+    /// Returns
+    /// `TransportConfig` - Quinn transport config
+    pub fn as_transport_config(&self) -> Result<TransportConfig> {
+        self.transport.validate(self.mtu)?;
+
+        // Probes are only answered after they are sent, so the peer could never answer in time
+        if let Some(interval) = self.liveness_interval {
+            if interval.is_zero() || interval >= self.liveness_timeout {
+                return Err(anyhow!(
+                    "liveness_interval ({interval:?}) must be greater than zero and less than \
+                     liveness_timeout ({:?})",
+                    self.liveness_timeout
+                ));
+            }
+        }
+
+        // Adaptive FEC raises the ratio from here up to one parity datagram per data datagram
+        if !(self.fec_ratio > 0.0 && self.fec_ratio <= 1.0) {
+            return Err(anyhow!(
+                "fec_ratio ({}) must be greater than zero and at most 1",
+                self.fec_ratio
+            ));
+        }
+
+        let mut transport_config = TransportConfig::default();
+        let mut mtu_config = MtuDiscoveryConfig::default();
+
+        transport_config.max_idle_timeout(Some(self.timeout.try_into()?));
+        transport_config.keep_alive_interval(
+            (!self.keep_alive_interval.is_zero()).then_some(self.keep_alive_interval),
+        );
+
+        mtu_config.upper_bound(self.mtu as u16 + QUIC_MTU_OVERHEAD);
+        transport_config.mtu_discovery_config(Some(mtu_config));
+
+        match self.transport.congestion_controller {
+            CongestionController::NewReno => {
+                transport_config.congestion_controller_factory(Arc::new(NewRenoConfig::default()))
+            }
+            CongestionController::Cubic => {
+                transport_config.congestion_controller_factory(Arc::new(CubicConfig::default()))
+            }
+            CongestionController::Bbr => {
+                transport_config.congestion_controller_factory(Arc::new(BbrConfig::default()))
+            }
+        };
+
+        if let Some(window) = self.transport.stream_receive_window {
+            transport_config.stream_receive_window(VarInt::from_u64(window)?);
+        }
+
+        if let Some(window) = self.transport.receive_window {
+            transport_config.receive_window(VarInt::from_u64(window)?);
+        }
+
+        if let Some(window) = self.transport.send_window {
+            transport_config.send_window(window);
+        }
+
+        if let Some(size) = self.transport.datagram_receive_buffer_size {
+            transport_config.datagram_receive_buffer_size(Some(size));
+        }
+
+        if let Some(size) = self.transport.datagram_send_buffer_size {
+            transport_config.datagram_send_buffer_size(size);
+        }
+
+        if let Some(rtt) = self.transport.initial_rtt {
+            transport_config.initial_rtt(rtt);
+        }
+
+        Ok(transport_config)
+    }
+
     pub fn as_endpoint_config(&self) -> Result<EndpointConfig> {
         let mut endpoint_config = EndpointConfig::default();
         endpoint_config.max_udp_payload_size(self.mtu as u16 + QUIC_MTU_OVERHEAD)?;
 
         Ok(endpoint_config)
     }
-}
\ No newline at end of file
+}
+
+impl TransportTuning {
+    /// Checks that the tuning values are usable.
+    ///
+    /// Arguments
+    /// `mtu` - the MTU of the tunnel
+    pub fn validate(&self, mtu: u32) -> Result<()> {
+        for (name, window) in [
+            ("stream_receive_window", self.stream_receive_window),
+            ("receive_window", self.receive_window),
+            ("send_window", self.send_window),
+        ] {
+            match window {
+                Some(0) => return Err(anyhow!("{name} must be greater than zero")),
+                Some(window) if window > VarInt::MAX.into_inner() => {
+                    return Err(anyhow!("{name} must not exceed {}", VarInt::MAX))
+                }
+                _ => (),
+            }
+        }
+
+        if let (Some(stream_window), Some(window)) =
+            (self.stream_receive_window, self.receive_window)
+        {
+            if stream_window > window {
+                return Err(anyhow!(
+                    "stream_receive_window ({stream_window}) must not exceed receive_window ({window})"
+                ));
+            }
+        }
+
+        for (name, size) in [
+            (
+                "datagram_receive_buffer_size",
+                self.datagram_receive_buffer_size,
+            ),
+            ("datagram_send_buffer_size", self.datagram_send_buffer_size),
+        ] {
+            if let Some(size) = size.filter(|size| *size < mtu as usize) {
+                return Err(anyhow!(
+                    "{name} ({size}) must be able to hold a packet of the MTU ({mtu})"
+                ));
+            }
+        }
+
+        if self.initial_rtt == Some(Duration::ZERO) {
+            return Err(anyhow!("initial_rtt must be greater than zero"));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{ConfigInit, CongestionController, ConnectionConfig, ServerConfig};
+    use figment::providers::{Format, Toml};
+    use figment::Figment;
+    use std::time::Duration;
+
+    fn connection_config(toml: &str) -> ConnectionConfig {
+        Figment::from(Toml::string(toml)).extract().unwrap()
+    }
+
+    #[test]
+    fn test_transport_tuning() {
+        let config = connection_config(
+            r#"
+            mtu = 1400
+
+            [transport]
+            congestion_controller = "bbr"
+            stream_receive_window = 8388608
+            receive_window = 33554432
+            datagram_receive_buffer_size = 4194304
+            initial_rtt = { secs = 0, nanos = 200000000 }
+            "#,
+        );
+
+        assert_eq!(
+            config.transport.congestion_controller,
+            CongestionController::Bbr
+        );
+        assert_eq!(
+            config.transport.initial_rtt,
+            Some(Duration::from_millis(200))
+        );
+        assert!(config.as_transport_config().is_ok());
+    }
+
+    #[test]
+    fn test_default_transport_tuning() {
+        let config = connection_config("mtu = 1400");
+
+        assert_eq!(
+            config.transport.congestion_controller,
+            CongestionController::Cubic
+        );
+        assert!(config.as_transport_config().is_ok());
+    }
+
+    #[test]
+    fn test_invalid_transport_tuning() {
+        for transport in [
+            "stream_receive_window = 0",
+            "send_window = 4611686018427387904",
+            "stream_receive_window = 2000000\nreceive_window = 1000000",
+            "datagram_send_buffer_size = 1000",
+            "initial_rtt = { secs = 0, nanos = 0 }",
+        ] {
+            let config = connection_config(&format!("mtu = 1400\n[transport]\n{transport}"));
+
+            assert!(config.as_transport_config().is_err(), "{transport}");
+        }
+    }
+
+    #[test]
+    fn test_liveness_interval() {
+        for (liveness, valid) in [
+            ("", true),
+            ("liveness_interval = { secs = 5, nanos = 0 }", true),
+            ("liveness_interval = { secs = 15, nanos = 0 }", false),
+            ("liveness_interval = { secs = 30, nanos = 0 }", false),
+            (
+                "liveness_interval = { secs = 30, nanos = 0 }\n\
+                 liveness_timeout = { secs = 90, nanos = 0 }",
+                true,
+            ),
+            ("liveness_interval = { secs = 0, nanos = 0 }", false),
+        ] {
+            let config = connection_config(&format!("mtu = 1400\n{liveness}"));
+
+            assert_eq!(config.as_transport_config().is_ok(), valid, "{liveness}");
+        }
+    }
+
+    #[test]
+    fn test_fec_ratio() {
+        for (ratio, valid) in [
+            ("0.1", true),
+            ("1.0", true),
+            ("0.0", false),
+            ("-0.5", false),
+            ("2.0", false),
+            ("nan", false),
+        ] {
+            let config = connection_config(&format!("mtu = 1400\nfec_ratio = {ratio}"));
+
+            assert_eq!(config.as_transport_config().is_ok(), valid, "{ratio}");
+        }
+    }
+
+    #[test]
+    fn test_tunnel_connection_overrides() {
+        let figment = Figment::from(Toml::string(
+            r#"
+            [connection]
+            mtu = 1400
+            timeout = { secs = 10, nanos = 0 }
+
+            [connection.transport]
+            congestion_controller = "bbr"
+            initial_rtt = { secs = 0, nanos = 100000000 }
+
+            [log]
+            level = "info"
+
+            [tunnels.datacenter]
+            name = "datacenter"
+            certificate_file = "cert.pem"
+            certificate_key_file = "key.pem"
+            address_tunnel = "10.0.0.1"
+            address_mask = "255.255.255.0"
+            users_file = "users"
+
+            [tunnels.mobile]
+            name = "mobile"
+            certificate_file = "cert.pem"
+            certificate_key_file = "key.pem"
+            address_tunnel = "10.0.1.1"
+            address_mask = "255.255.255.0"
+            users_file = "users"
+
+            [tunnels.mobile.connection]
+            mtu = 1200
+
+            [tunnels.mobile.connection.transport]
+            congestion_controller = "newreno"
+            "#,
+        ));
+
+        let config = ServerConfig::init(figment, "RUMBLE_TEST_").unwrap();
+        let datacenter = config.tunnels["datacenter"].connection_config(&config.connection);
+        let mobile = config.tunnels["mobile"].connection_config(&config.connection);
+
+        assert_eq!(datacenter, &config.connection);
+        assert_eq!(mobile.mtu, 1200);
+        assert_eq!(mobile.timeout, Duration::from_secs(10));
+        assert_eq!(
+            mobile.transport.congestion_controller,
+            CongestionController::NewReno
+        );
+        assert_eq!(
+            mobile.transport.initial_rtt,
+            Some(Duration::from_millis(100))
+        );
+    }
+}
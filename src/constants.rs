@@ -5,6 +5,8 @@ use std::time::Duration;
 use once_cell::sync::Lazy;
 use quinn::Runtime;
 
+use crate::utils::offload::virtio::VIRTIO_NET_HDR_LEN;
+
 /// Size of the buffer used for bincode (de)serialization
 pub const BINCODE_BUFFER_SIZE: usize = 128;
 
@@ -17,12 +19,114 @@ pub const IPV4_ADDR_SIZE: usize = std::mem::size_of::<Ipv4Addr>();
 /// Size of an `Ipv6Addr` address
 pub const IPV6_ADDR_SIZE: usize = std::mem::size_of::<Ipv6Addr>();
 
+/// Version of the datagram protocol spoken by this build
+pub const PROTOCOL_VERSION: u8 = 2;
+
+/// Application close code of connections whose authentication failed
+pub const CLOSE_CODE_AUTH_FAILED: u32 = 0x01;
+
+/// Close reason of connections whose authentication message the server could not understand.
+/// Servers predating features refuse `AuthenticationWithFeatures` with it
+pub const CLOSE_REASON_AUTH_MESSAGE_REFUSED: &str = "Authentication failed";
+
+/// Application close code of connections whose peer stopped answering liveness probes
+pub const CLOSE_CODE_DEAD_PEER: u32 = 0x02;
+
+/// Application close code of sessions disconnected due to inactivity
+pub const CLOSE_CODE_IDLE: u32 = 0x03;
+
+/// Application close code of connections closed because the server is shutting down
+pub const CLOSE_CODE_SHUTDOWN: u32 = 0x04;
+
+/// Application close code of connections closed because the server is handed over to a new
+/// process, clients should reconnect right away
+pub const CLOSE_CODE_RESTART: u32 = 0x05;
+
+/// Application close code of connections the client abandoned after failing to migrate them to a
+/// new network path
+pub const CLOSE_CODE_MIGRATION_FAILED: u32 = 0x06;
+
+/// Application close code of connections closed because the client is redirected to another
+/// server
+pub const CLOSE_CODE_REDIRECT: u32 = 0x07;
+
+/// Time the client waits for the server to acknowledge closing the connection on shutdown
+pub const CLIENT_CLOSE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Interval between attempts to reconnect to a restarting server
+pub const CLIENT_RECONNECT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Number of failed attempts to reconnect to a restarting server after which the client gives up
+pub const CLIENT_RECONNECT_ATTEMPTS: u32 = 20;
+
+/// Number of redirects in a row after which the client gives up, e.g. when servers redirect to
+/// each other
+pub const CLIENT_MAX_REDIRECTS: u32 = 5;
+
+/// Port of Rumble servers if none is configured or given in the connection string
+pub const DEFAULT_PORT: u16 = 55555;
+
+/// Service and protocol labels of the SRV records of Rumble servers
+pub const SRV_SERVICE: &str = "_rumble._udp";
+
+/// Time to wait for a name server to answer a query
+pub const DNS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Time the client waits for a connection attempt before racing it against the next server
+/// address, as recommended by RFC 8305
+pub const HAPPY_EYEBALLS_DELAY: Duration = Duration::from_millis(250);
+
+/// Interval between redirects sent to the clients of a draining tunnel, datagrams may be lost
+pub const DRAIN_REDIRECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time without further network changes after which the client migrates its connection
+pub const ROAMING_SETTLE_TIME: Duration = Duration::from_secs(1);
+
+/// Time the server has to answer on a new network path before the client reconnects instead
+pub const MIGRATION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Interval between checks whether the server answered on a new network path
+pub const MIGRATION_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Interval between checks of the network path of a connection on the server
+pub const PATH_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
 /// Default MTU overhead for QUIC
 pub const QUIC_MTU_OVERHEAD: u16 = 42;
 
+/// Maximum number of packets read from or written to the TUN interface in a single batch
+pub const INTERFACE_BATCH_SIZE: usize = 32;
+
+/// Number of packets that fit into a single allocation of the TUN interface buffer pool
+pub const BUFFER_POOL_PACKETS: usize = 64;
+
+/// Size of the buffer used for reading offloaded super-packets from the TUN interface
+pub const OFFLOAD_BUFFER_SIZE: usize = u16::MAX as usize + VIRTIO_NET_HDR_LEN;
+
+/// Number of super-packets that fit into a single allocation of the offload buffer pool
+pub const OFFLOAD_BUFFER_POOL_PACKETS: usize = 4;
+
+/// Number of oversized packets queued for the packet stream, further packets are dropped
+pub const PACKET_STREAM_QUEUE_SIZE: usize = 64;
+
 /// Interval used by various cleanup tasks.
 pub const CLEANUP_INTERVAL: Duration = Duration::from_secs(1);
 
+/// Interval in which the certificate files of tunnels are checked for changes
+pub const CERTIFICATE_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Interval in which the maximum datagram size of connections is checked for MTU changes
+pub const MTU_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Interval in which the adaptive FEC ratio is recomputed from the measured packet loss
+pub const FEC_ADAPT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest time a partial FEC group waits for more datagrams before its parity is sent
+pub const FEC_FLUSH_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Zstandard level used for compressing tunnel packets
+pub const ZSTD_COMPRESSION_LEVEL: i32 = 3;
+
 /// Supported TLS cipher suites for Rumble VPN
 pub static RUMBLE_CIPHER_SUITES: &[rustls::SupportedCipherSuite] = &[
     rustls::cipher_suite::TLS13_AES_256_GCM_SHA384,
@@ -2,5 +2,7 @@ pub mod auth;
 pub mod client;
 pub mod config;
 pub mod constants;
+pub mod profile;
 pub mod server;
-pub mod utils;
\ No newline at end of file
+pub mod stats;
+pub mod utils;
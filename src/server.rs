@@ -1,17 +1,32 @@
-use crate::server::tunnel::RumbleTunnel;
+use crate::config::DrainConfig;
+#[cfg(target_os = "linux")]
+use crate::server::handoff::{send_handoff, TunnelHandoff};
+use crate::server::supervisor::{TunnelConfigs, TunnelStatus, TunnelSupervisor};
 use crate::{config::ServerConfig, constants::CLEANUP_INTERVAL};
-use anyhow::Result;
-use dashmap::DashMap;
+use anyhow::{anyhow, Result};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Notify;
 use tokio::time::sleep;
-use tracing::{error, info};
+use tracing::{info, warn};
 
 pub mod address_pool;
 pub mod connection;
+#[cfg(target_os = "linux")]
+pub mod handoff;
+pub mod supervisor;
 pub mod tunnel;
+#[cfg(target_os = "linux")]
+pub mod workers;
 
 /// Rumble server with multiple underlying tunnels.
 pub struct RumbleServer {
-    active_tunnels: DashMap<String, RumbleTunnel>,
+    supervisor: TunnelSupervisor,
+    drain_deadline: Duration,
+    pending_config: Mutex<Option<ServerConfig>>,
+    reload_notify: Notify,
+    drain_config: Mutex<DrainConfig>,
+    drain_notify: Notify,
 }
 
 impl RumbleServer {
@@ -20,46 +35,179 @@ impl RumbleServer {
     /// Arguments
     /// `config` - the config for the server
     pub async fn new(config: ServerConfig) -> Result<Self> {
-        let tunnels = DashMap::new();
+        let supervisor = TunnelSupervisor::new(config.supervisor.clone());
 
-        for (name, tunnel_config) in config.tunnels.iter() {
-            let tunnel =
-                RumbleTunnel::new(name.clone(), tunnel_config.clone(), &config.connection)?;
-
-            tunnels.insert(name.clone(), tunnel);
+        for (name, (tunnel_config, connection_config)) in Self::tunnel_configs(&config) {
+            supervisor.add(name, tunnel_config, connection_config);
         }
 
         Ok(Self {
-            active_tunnels: tunnels,
+            supervisor,
+            drain_deadline: config.drain_deadline,
+            pending_config: Mutex::new(None),
+            reload_notify: Notify::new(),
+            drain_config: Mutex::new(config.drain),
+            drain_notify: Notify::new(),
         })
     }
 
-    /// Starts the server and all tunnels
+    /// Starts the server and supervises all tunnels
     pub async fn run(&self) -> Result<()> {
-        for mut entry in self.active_tunnels.iter_mut() {
-            let tunnel = entry.value_mut();
+        loop {
+            self.supervisor.supervise().await?;
 
-            tunnel.start().await?;
+            tokio::select! {
+                _ = sleep(CLEANUP_INTERVAL) => (),
+                _ = self.reload_notify.notified() => self.apply_pending_config().await?,
+                _ = self.drain_notify.notified() => self.supervisor.drain(self.drain_config()?)?,
+            }
         }
+    }
 
-        loop {
-            for mut entry in self.active_tunnels.iter_mut() {
-                let tunnel_name = entry.key().to_owned();
-                let tunnel = entry.value_mut();
+    /// Reloads the server config.
+    ///
+    /// The tunnels are updated by the running server: new tunnels are started, removed ones are
+    /// stopped and only tunnels whose settings changed are restarted. The log, supervisor and
+    /// drain deadline settings only take effect after a restart, the drain config is used by the
+    /// next drain.
+    ///
+    /// Arguments
+    /// `config` - the new config for the server
+    pub fn reload(&self, config: ServerConfig) -> Result<()> {
+        *self
+            .pending_config
+            .lock()
+            .map_err(|_| anyhow!("Pending config lock is poisoned"))? = Some(config);
+        self.reload_notify.notify_one();
 
-                if tunnel.is_ok() {
-                    continue;
-                }
+        Ok(())
+    }
 
-                error!("Tunnel '{tunnel_name}' encountered an error, restarting...");
+    /// Takes the server out of rotation, e.g. for maintenance.
+    ///
+    /// New sessions are redirected to the server of the drain config, or of the tunnel config if
+    /// unset, and refused if there is none. Existing clients are told to reconnect to that server
+    /// within the drain deadline, the connections left afterwards are closed. The server keeps
+    /// draining until it is restarted.
+    pub fn drain(&self) {
+        self.drain_notify.notify_one();
+    }
 
-                tunnel.stop().await?;
-                tunnel.start().await?;
+    /// Shuts the server down, closing all connections and stopping all tunnels
+    pub async fn shutdown(&self) {
+        info!(
+            "Shutting down, waiting up to {:?} for connections to close",
+            self.drain_deadline
+        );
+        self.supervisor.shutdown(self.drain_deadline).await;
+    }
 
-                info!("Tunnel '{tunnel_name}' restarted successfully");
-            }
+    /// Adopts the tunnels handed over by another server process, must be called before the
+    /// server is run.
+    ///
+    /// Arguments
+    /// `handoffs` - the tunnels handed over
+    #[cfg(target_os = "linux")]
+    pub fn adopt(&self, handoffs: Vec<TunnelHandoff>) {
+        self.supervisor.adopt(handoffs);
+    }
+
+    /// Hands all tunnels over to a new server process.
+    ///
+    /// The clients are told to reconnect right away, their connections are then accepted by the
+    /// new server process on the same UDP sockets. The tunnels are sent before the closed
+    /// connections are drained, so reconnecting clients do not wait for the drain deadline. The
+    /// closed connections are drained over throwaway sockets, so they do not read the datagrams
+    /// meant for the new server process.
+    ///
+    /// Arguments
+    /// `stream` - the connection to the new server process
+    #[cfg(target_os = "linux")]
+    pub async fn hand_over(&self, stream: std::os::unix::net::UnixStream) -> Result<()> {
+        info!("Handing the tunnels over to a new server process");
+        let handoffs = self.supervisor.hand_over().await?;
+
+        tokio::task::spawn_blocking(move || {
+            stream.set_nonblocking(false)?;
+            send_handoff(&stream, &handoffs)
+        })
+        .await??;
+
+        info!("Tunnels handed over");
+        self.supervisor.release_sockets();
+        self.supervisor.drain_handoff(self.drain_deadline).await;
+
+        Ok(())
+    }
+
+    /// Returns the supervision status of every tunnel
+    pub fn tunnel_statuses(&self) -> Vec<(String, TunnelStatus)> {
+        self.supervisor.statuses()
+    }
 
-            sleep(CLEANUP_INTERVAL).await;
+    /// Applies the config passed to the last reload, if it has not been applied yet.
+    async fn apply_pending_config(&self) -> Result<()> {
+        let config = self
+            .pending_config
+            .lock()
+            .map_err(|_| anyhow!("Pending config lock is poisoned"))?
+            .take();
+
+        let Some(config) = config else {
+            return Ok(());
+        };
+
+        let changes = self
+            .supervisor
+            .reconcile(Self::tunnel_configs(&config), self.drain_deadline)
+            .await;
+
+        if changes.is_empty() {
+            info!("Reloaded config, no tunnel has changed");
+        } else {
+            info!(
+                "Reloaded config, added tunnels: {:?}, removed tunnels: {:?}, restarted tunnels: {:?}",
+                changes.added, changes.removed, changes.changed
+            );
         }
+
+        *self
+            .drain_config
+            .lock()
+            .map_err(|_| anyhow!("Drain config lock is poisoned"))? = config.drain.clone();
+
+        if config.drain_deadline != self.drain_deadline {
+            warn!("The changed drain deadline takes effect after a restart");
+        }
+
+        Ok(())
+    }
+
+    /// Returns the current drain config
+    fn drain_config(&self) -> Result<DrainConfig> {
+        Ok(self
+            .drain_config
+            .lock()
+            .map_err(|_| anyhow!("Drain config lock is poisoned"))?
+            .clone())
+    }
+
+    /// Returns the config of every tunnel of the server config.
+    ///
+    /// Arguments
+    /// `config` - the server config
+    fn tunnel_configs(config: &ServerConfig) -> TunnelConfigs {
+        config
+            .tunnels
+            .iter()
+            .map(|(name, tunnel_config)| {
+                let connection_config = tunnel_config.connection_config(&config.connection);
+
+                (
+                    name.clone(),
+                    (tunnel_config.clone(), connection_config.clone()),
+                )
+            })
+            .collect()
     }
-}
\ No newline at end of file
+}
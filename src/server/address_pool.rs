@@ -1,12 +1,17 @@
 use anyhow::Result;
-use dashmap::DashSet;
+use dashmap::{DashMap, DashSet};
 use ipnet::{IpAddrRange, IpNet, Ipv4AddrRange, Ipv6AddrRange};
+use std::collections::HashMap;
 use std::net::IpAddr;
 
-/// Pool of addresses from which addresses can be requested and released
+/// Pool of addresses from which addresses can be requested and released.
+///
+/// Addresses leased to a user are remembered after they are released, so a reconnecting user
+/// gets the same address again while it is not in use.
 pub struct AddressPool {
     network: IpNet,
     used_addresses: DashSet<IpAddr>,
+    leases: DashMap<String, IpAddr>,
 }
 
 impl AddressPool {
@@ -20,9 +25,50 @@ impl AddressPool {
         Ok(Self {
             network,
             used_addresses: used,
+            leases: DashMap::new(),
         })
     }
 
+    /// Leases an address to a user, preferring the address the user was leased before.
+    ///
+    /// Arguments
+    /// `username` - the user requesting an address
+    ///
+    /// Returns
+    /// `Option<IpNet>` - the leased address, `None` if the pool is exhausted
+    pub fn lease(&self, username: &str) -> Option<IpNet> {
+        if let Some(address) = self.leases.get(username).map(|address| *address) {
+            if self.used_addresses.insert(address) {
+                return Some(self.with_netmask(address));
+            }
+        }
+
+        let address = self.next_available_address()?;
+        self.leases.insert(username.to_string(), address.addr());
+
+        Some(address)
+    }
+
+    /// Returns the addresses leased to users
+    pub fn leases(&self) -> HashMap<String, IpAddr> {
+        self.leases
+            .iter()
+            .map(|entry| (entry.key().clone(), *entry.value()))
+            .collect()
+    }
+
+    /// Restores the leases of users, ignoring addresses outside of the pool.
+    ///
+    /// Arguments
+    /// `leases` - the addresses leased to users
+    pub fn restore_leases(&self, leases: HashMap<String, IpAddr>) {
+        for (username, address) in leases {
+            if self.network.contains(&address) {
+                self.leases.insert(username, address);
+            }
+        }
+    }
+
     /// Returns the next available address
     pub fn next_available_address(&self) -> Option<IpNet> {
         let mut range = match self.network {
@@ -38,8 +84,7 @@ impl AddressPool {
             .find(|address| !self.used_addresses.contains(address))
             .map(|address| {
                 self.used_addresses.insert(address);
-                IpNet::with_netmask(address, self.network.netmask())
-                    .expect("Netmask will always be valid")
+                self.with_netmask(address)
             })
     }
 
@@ -51,6 +96,11 @@ impl AddressPool {
         self.used_addresses.remove(&address);
     }
 
+    /// Returns the address with the network mask of the pool
+    fn with_netmask(&self, address: IpAddr) -> IpNet {
+        IpNet::with_netmask(address, self.network.netmask()).expect("Netmask will always be valid")
+    }
+
     /// Resets the address pool by releasing all addresses.
     pub fn reset(&self) {
         self.used_addresses.clear();
@@ -102,4 +152,30 @@ mod tests {
             )
         );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_leases() {
+        let pool = AddressPool::new(IpNet::V4(
+            Ipv4Net::with_netmask(Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(255, 255, 255, 0))
+                .unwrap(),
+        ))
+        .unwrap();
+
+        let alice = pool.lease("alice").unwrap();
+        let bob = pool.lease("bob").unwrap();
+        assert_ne!(alice, bob);
+
+        pool.release_address(alice.addr());
+        pool.release_address(bob.addr());
+        assert_eq!(pool.lease("bob").unwrap(), bob);
+        assert_eq!(pool.lease("alice").unwrap(), alice);
+
+        let restored = AddressPool::new(IpNet::V4(
+            Ipv4Net::with_netmask(Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(255, 255, 255, 0))
+                .unwrap(),
+        ))
+        .unwrap();
+        restored.restore_leases(pool.leases());
+        assert_eq!(restored.lease("bob").unwrap(), bob);
+    }
+}
@@ -1,26 +1,42 @@
-use crate::auth::server::{AuthServer, AuthState};
+use crate::auth::features::Features;
+use crate::auth::server::{Admission, AuthServer, AuthState};
 use crate::auth::user::UserDatabase;
 use crate::config::ConnectionConfig;
+use crate::constants::{MTU_CHECK_INTERVAL, PATH_CHECK_INTERVAL};
+use crate::server::address_pool::AddressPool;
+use crate::stats::ConnectionStats;
+use crate::utils::control::{process_control_frames, ControlFrame, Redirect};
+use crate::utils::datagram::{DatagramReceiver, DatagramTransmitter};
+use crate::utils::liveness::{enforce_idle_timeout, probe_liveness};
+use crate::utils::mss::clamp_mss;
+use crate::utils::packet_sender::PacketSender;
+use crate::utils::packet_stream::receive_packet_streams;
 use crate::utils::tasks::join_or_abort_task;
 use anyhow::{anyhow, Result};
 use bytes::Bytes;
 use delegate::delegate;
 use ipnet::IpNet;
 
-use quinn::Connection;
+use quinn::{Connection, VarInt};
+use std::future::Future;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
 use tokio::sync::RwLock;
 use tokio::task::JoinHandle;
-use tracing::{debug, error};
+use tokio::time::sleep;
+use tokio::try_join;
+use tracing::{debug, error, info};
 
 /// Represents a Rumble connection with authentication and IO.
 pub struct RumbleConnection {
     connection: Arc<Connection>,
+    connection_config: ConnectionConfig,
     auth_server: Arc<RwLock<AuthServer>>,
     tun_queue: Arc<UnboundedSender<Bytes>>,
+    packet_sender: Option<Arc<PacketSender>>,
+    stats: Arc<ConnectionStats>,
     tasks: Vec<JoinHandle<Result<()>>>,
 }
 
@@ -31,33 +47,67 @@ impl RumbleConnection {
     /// `connection` - the underlying QUIC connection
     /// `tun_queue` - the queue to send data to the TUN interface
     /// `user_database` - the user database
-    /// `auth_timeout` - the authentication timeout
-    /// `client_address` - the assigned client address
+    /// `address_pool` - the pool the client address is leased from
+    /// `admission` - how the tunnel admits new sessions
     pub async fn new(
         connection: Connection,
         connection_config: &ConnectionConfig,
         tun_queue: Arc<UnboundedSender<Bytes>>,
         user_database: Arc<UserDatabase>,
-        client_address: IpNet,
+        address_pool: Arc<AddressPool>,
+        admission: Admission,
     ) -> Result<Self> {
         let connection = Arc::new(connection);
         let auth_server = AuthServer::new(
             user_database,
             connection.clone(),
-            client_address,
+            address_pool,
             connection_config.timeout,
+            connection_config.features(),
+            admission,
         )
         .await?;
 
         Ok(Self {
             connection,
+            connection_config: connection_config.clone(),
             auth_server: Arc::new(RwLock::new(auth_server)),
             tun_queue,
+            packet_sender: None,
+            stats: Arc::new(ConnectionStats::default()),
             tasks: Vec::new(),
         })
     }
 
+    /// Authenticates the client.
+    ///
+    /// Returns
+    /// `Option<IpNet>` - the address leased to the client, `None` if the client was redirected to
+    /// another server
+    pub async fn authenticate(&self) -> Result<Option<IpNet>> {
+        let mut auth_server = self.auth_server.write().await;
+        let result = auth_server.handle_authentication().await;
+
+        if let AuthState::Redirected(_) = auth_server.get_state().await {
+            return result.map(|_| None);
+        }
+
+        match (result, auth_server.get_client_address()) {
+            (Ok(()), Some(client_address)) => Ok(Some(client_address)),
+            (Ok(()), None) => Err(anyhow!("Authenticated client has no address")),
+            (Err(e), _) => Err(e),
+        }
+    }
+
+    /// Returns the address leased to the client, if it is authenticated
+    pub async fn client_address(&self) -> Option<IpNet> {
+        self.auth_server.read().await.get_client_address()
+    }
+
     /// Starts the tasks for this instance of Rumble connection.
+    ///
+    /// The client has to be authenticated first. Packets can be sent to the client as soon as
+    /// this returns.
     pub async fn start(&mut self) -> Result<()> {
         if self.is_ok() {
             return Err(anyhow!(
@@ -65,10 +115,31 @@ impl RumbleConnection {
             ));
         }
 
+        let features = self.auth_server.read().await.get_features();
+        debug!(
+            "Negotiated features with {:?}: {features:?}",
+            self.connection.remote_address()
+        );
+
+        let transmitter =
+            DatagramTransmitter::new(self.connection.clone(), &self.connection_config, features);
+        let (packet_sender, send_queued_packets) = PacketSender::new(
+            transmitter.clone(),
+            &self.connection_config,
+            features,
+            (*self.tun_queue).clone(),
+            self.stats.clone(),
+        );
+        self.packet_sender = Some(Arc::new(packet_sender));
+
         self.tasks.push(tokio::spawn(Self::process_incoming_data(
-            self.connection.clone(),
+            transmitter,
+            send_queued_packets,
+            self.connection_config.clone(),
             self.tun_queue.clone(),
             self.auth_server.clone(),
+            features,
+            self.stats.clone(),
         )));
 
         Ok(())
@@ -95,45 +166,146 @@ impl RumbleConnection {
         !self.tasks.is_empty() && self.tasks.iter().all(|task| !task.is_finished())
     }
 
+    /// Returns the traffic statistics of this connection
+    pub fn stats(&self) -> &ConnectionStats {
+        &self.stats
+    }
+
     /// Sends an unreliable datagram to the client.
     ///
     /// Arguments
     /// `data` - the data to be sent
     pub async fn send_datagram(&self, data: Bytes) -> Result<()> {
-        match self.auth_server.read().await.get_state().await {
-            AuthState::Authenticated(_) => (),
-            _ => {
-                return Err(anyhow!(
-                    "Attempted to send datagram to unauthenticated client {:?}",
-                    self.connection.remote_address(),
-                ))
-            }
-        }
+        self.authenticated_sender()?.send_datagram(data)
+    }
+
+    /// Sends a packet to the client.
+    ///
+    /// Packets that do not fit into a datagram are handled according to the oversized packet
+    /// policy.
+    ///
+    /// Arguments
+    /// `data` - the packet to be sent
+    pub async fn send_packet(&self, data: Bytes) -> Result<()> {
+        self.authenticated_sender()?.send(data)
+    }
 
-        self.connection.send_datagram(data)?;
+    /// Tells the client to reconnect to another server.
+    ///
+    /// Arguments
+    /// `redirect` - the server to reconnect to and the deadline for reconnecting
+    pub async fn send_redirect(&self, redirect: Redirect) -> Result<()> {
+        self.authenticated_sender()?
+            .send_control_frame(&ControlFrame::Redirect(redirect))
+    }
 
-        Ok(())
+    /// Sends a notification to the client.
+    ///
+    /// Arguments
+    /// `message` - the message to be shown to the user
+    pub async fn send_notification(&self, message: &str) -> Result<()> {
+        self.authenticated_sender()?
+            .send_control_frame(&ControlFrame::Notification {
+                message: message.to_string(),
+            })
+    }
+
+    /// Returns the packet sender, which is only available once the connection is started.
+    pub fn packet_sender(&self) -> Option<Arc<PacketSender>> {
+        self.packet_sender.clone()
+    }
+
+    /// Returns the packet sender, which is only available once the client is authenticated.
+    fn authenticated_sender(&self) -> Result<&PacketSender> {
+        self.packet_sender.as_deref().ok_or_else(|| {
+            anyhow!(
+                "Attempted to send data to unauthenticated client {:?}",
+                self.connection.remote_address(),
+            )
+        })
     }
 
     delegate! {
         to self.connection {
             pub fn max_datagram_size(&self) -> Option<usize>;
             pub fn remote_address(&self) -> SocketAddr;
+            pub fn close(&self, error_code: VarInt, reason: &[u8]);
         }
     }
 
     /// Processes incoming data and sends it to TUN queue
     ///
     /// Arguments
-    /// `connection` - a reference to the underlying QUIC connection
+    /// `transmitter` - the transmitter of datagrams to the client
+    /// `send_queued_packets` - the future sending the packets queued by the packet sender
+    /// `connection_config` - the connection config
     /// `tun_queue` - a sender of an unbounded queue used by the tunnel worker to receive data
     /// `auth_server` - a reference to the authentication server
+    /// `features` - the features negotiated with the client
+    /// `stats` - statistics of the connection
     async fn process_incoming_data(
-        connection: Arc<Connection>,
+        transmitter: DatagramTransmitter,
+        send_queued_packets: impl Future<Output = Result<()>>,
+        connection_config: ConnectionConfig,
         tun_queue: Arc<UnboundedSender<Bytes>>,
         auth_server: Arc<RwLock<AuthServer>>,
+        features: Features,
+        stats: Arc<ConnectionStats>,
     ) -> Result<()> {
-        Self::handle_authentication(&auth_server).await?;
+        let connection = transmitter.connection().clone();
+        let (control_queue, control_frames) = unbounded_channel();
+
+        try_join!(
+            Self::process_incoming_datagrams(
+                transmitter.clone(),
+                tun_queue.clone(),
+                auth_server,
+                connection_config.mss_clamp(),
+                DatagramReceiver::new(features, stats.clone()),
+                control_queue,
+            ),
+            receive_packet_streams(connection.clone(), (*tun_queue).clone()),
+            send_queued_packets,
+            process_control_frames(transmitter.clone(), control_frames, stats.clone(), None),
+            probe_liveness(
+                transmitter.clone(),
+                connection_config
+                    .liveness_interval
+                    .filter(|_| features.typed_framing()),
+                connection_config.liveness_timeout,
+                stats.clone(),
+            ),
+            enforce_idle_timeout(
+                connection.clone(),
+                connection_config.session_idle_timeout,
+                stats.clone(),
+            ),
+            Self::watch_path(connection.clone(), stats.clone()),
+            Self::watch_mtu(transmitter, connection_config.mtu, stats),
+        )?;
+
+        Ok(())
+    }
+
+    /// Processes incoming datagrams and sends them to TUN queue
+    ///
+    /// Arguments
+    /// `transmitter` - the transmitter of datagrams to the client
+    /// `tun_queue` - a sender of an unbounded queue used by the tunnel worker to receive data
+    /// `auth_server` - a reference to the authentication server
+    /// `mss_clamp` - the MTU to clamp the MSS of TCP SYN packets to, if enabled
+    /// `receiver` - the receiver decoding datagrams into packets
+    /// `control_queue` - the queue of received control frames
+    async fn process_incoming_datagrams(
+        transmitter: DatagramTransmitter,
+        tun_queue: Arc<UnboundedSender<Bytes>>,
+        auth_server: Arc<RwLock<AuthServer>>,
+        mss_clamp: Option<usize>,
+        mut receiver: DatagramReceiver,
+        control_queue: UnboundedSender<ControlFrame>,
+    ) -> Result<()> {
+        let connection = transmitter.connection();
+        let mut packets = Vec::new();
 
         loop {
             match auth_server.read().await.get_state().await {
@@ -153,12 +325,83 @@ impl RumbleConnection {
                 connection.remote_address()
             );
 
-            tun_queue.send(data)?;
+            if let Some(frame) = receiver.receive(data, &mut packets) {
+                control_queue.send(frame)?;
+            }
+
+            for packet in packets.drain(..) {
+                let packet = match mss_clamp {
+                    Some(mtu) => {
+                        let mtu = transmitter
+                            .max_datagram_size()
+                            .map_or(mtu, |size| size.min(mtu));
+                        clamp_mss(packet, mtu)
+                    }
+                    None => packet,
+                };
+
+                tun_queue.send(packet)?;
+            }
         }
     }
 
-    async fn handle_authentication(auth_server: &Arc<RwLock<AuthServer>>) -> Result<()> {
-        let mut auth_server = auth_server.write().await;
-        auth_server.handle_authentication().await
+    /// Logs changes of the network path of the client, e.g. after it moved from Wi-Fi to LTE and
+    /// migrated the connection.
+    ///
+    /// Arguments
+    /// `connection` - a reference to the underlying QUIC connection
+    /// `stats` - statistics of the connection
+    async fn watch_path(connection: Arc<Connection>, stats: Arc<ConnectionStats>) -> Result<()> {
+        let mut current_address = connection.remote_address();
+
+        loop {
+            tokio::select! {
+                _ = connection.closed() => return Ok(()),
+                _ = sleep(PATH_CHECK_INTERVAL) => (),
+            }
+
+            let address = connection.remote_address();
+
+            if address != current_address {
+                info!("Client {current_address} migrated to {address}");
+                stats.record_path_migration();
+                current_address = address;
+            }
+        }
+    }
+
+    /// Tracks the MTU of the tunnel to the client as the maximum datagram size changes.
+    ///
+    /// Arguments
+    /// `transmitter` - the transmitter of datagrams to the client
+    /// `configured_mtu` - the configured MTU, the tunnel MTU never exceeds it
+    /// `stats` - statistics of the connection
+    async fn watch_mtu(
+        transmitter: DatagramTransmitter,
+        configured_mtu: u32,
+        stats: Arc<ConnectionStats>,
+    ) -> Result<()> {
+        let connection = transmitter.connection();
+        let mut current_mtu = None;
+
+        loop {
+            if let Some(max_datagram_size) = transmitter.max_datagram_size() {
+                let mtu = configured_mtu.min(max_datagram_size as u32);
+
+                if current_mtu != Some(mtu) {
+                    info!(
+                        "Tunnel MTU of client {} changed to {mtu}",
+                        connection.remote_address()
+                    );
+                    stats.set_mtu(mtu);
+                    current_mtu = Some(mtu);
+                }
+            }
+
+            tokio::select! {
+                _ = connection.closed() => return Ok(()),
+                _ = sleep(MTU_CHECK_INTERVAL) => (),
+            }
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,343 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use tracing::info;
+
+/// The most file descriptors passed in a handoff, the sockets and the TUN interface of every
+/// tunnel (`SCM_MAX_FD` is 253)
+const HANDOFF_MAX_FDS: usize = 252;
+
+/// Size of the length prefix of the handoff state
+const HANDOFF_LENGTH_LEN: usize = 4;
+
+/// State of a tunnel that is handed over to a new server process.
+///
+/// Clients authenticate every connection with their username and password, so there are no
+/// session tokens to hand over. Reconnecting clients authenticate again and get their leased
+/// address back.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TunnelHandoffState {
+    /// The name of the tunnel
+    pub name: String,
+    /// The name of the TUN interface
+    pub interface_name: String,
+    /// The address of the TUN interface
+    pub address_tunnel: Ipv4Addr,
+    /// The network mask of the TUN interface
+    pub address_mask: Ipv4Addr,
+    /// Whether the TUN interface was opened with segmentation offload
+    pub offload: bool,
+    /// The number of UDP sockets of the tunnel, one for every worker
+    #[serde(default = "default_sockets")]
+    pub sockets: usize,
+    /// The addresses leased to users
+    pub leases: HashMap<String, IpAddr>,
+}
+
+/// Tunnel handed over to a new server process, along with its bound UDP sockets and its TUN
+/// interface.
+#[derive(Debug)]
+pub struct TunnelHandoff {
+    /// The state of the tunnel
+    pub state: TunnelHandoffState,
+    /// The UDP sockets the tunnel is bound to, ordered by worker
+    pub sockets: Vec<UdpSocket>,
+    /// The file descriptor of the TUN interface
+    pub interface: OwnedFd,
+}
+
+/// Takes over the tunnels of a running server process, if there is one, and listens for the
+/// next server process to hand the tunnels over to.
+///
+/// Arguments
+/// `path` - the path of the Unix socket used for the handoff
+///
+/// Returns
+/// `Vec<TunnelHandoff>` - the tunnels handed over by the running server process
+/// `UnixListener` - the listener for the next server process
+pub fn take_over(path: &Path) -> Result<(Vec<TunnelHandoff>, UnixListener)> {
+    let tunnels = match UnixStream::connect(path) {
+        Ok(stream) => {
+            info!("Taking over the tunnels of the running server process");
+            receive_handoff(&stream).context("receive handoff")?
+        }
+        Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused) => {
+            Vec::new()
+        }
+        Err(e) => return Err(e).context("connect to handoff socket"),
+    };
+
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => {
+            return Err(e).context("remove handoff socket")
+        }
+        _ => (),
+    }
+
+    let listener = UnixListener::bind(path).context("bind handoff socket")?;
+
+    Ok((tunnels, listener))
+}
+
+/// Sends tunnels to a new server process.
+///
+/// The file descriptors are passed with `SCM_RIGHTS` together with the length of the state,
+/// which follows as JSON.
+///
+/// Arguments
+/// `stream` - the connection to the new server process
+/// `tunnels` - the tunnels to be handed over
+pub fn send_handoff(mut stream: &UnixStream, tunnels: &[TunnelHandoff]) -> Result<()> {
+    let fds: Vec<RawFd> = tunnels
+        .iter()
+        .flat_map(|tunnel| {
+            tunnel
+                .sockets
+                .iter()
+                .map(|socket| socket.as_raw_fd())
+                .chain([tunnel.interface.as_raw_fd()])
+        })
+        .collect();
+
+    if fds.len() > HANDOFF_MAX_FDS {
+        return Err(anyhow!(
+            "Cannot hand over more than {HANDOFF_MAX_FDS} sockets and interfaces"
+        ));
+    }
+
+    if let Some(tunnel) = tunnels
+        .iter()
+        .find(|tunnel| tunnel.sockets.len() != tunnel.state.sockets)
+    {
+        return Err(anyhow!(
+            "Tunnel '{}' has {} sockets, its state lists {}",
+            tunnel.state.name,
+            tunnel.sockets.len(),
+            tunnel.state.sockets
+        ));
+    }
+
+    let states: Vec<&TunnelHandoffState> = tunnels.iter().map(|tunnel| &tunnel.state).collect();
+    let state = serde_json::to_vec(&states)?;
+    let length = u32::try_from(state.len())?.to_be_bytes();
+
+    send_with_fds(stream, &length, &fds)?;
+    stream.write_all(&state)?;
+    stream.flush()?;
+
+    Ok(())
+}
+
+/// Receives tunnels from a running server process.
+///
+/// Arguments
+/// `stream` - the connection to the running server process
+///
+/// Returns
+/// `Vec<TunnelHandoff>` - the tunnels handed over
+pub fn receive_handoff(mut stream: &UnixStream) -> Result<Vec<TunnelHandoff>> {
+    let mut length = [0; HANDOFF_LENGTH_LEN];
+    let mut fds = receive_with_fds(stream, &mut length)?.into_iter();
+
+    let mut state = vec![0; u32::from_be_bytes(length) as usize];
+    stream.read_exact(&mut state)?;
+    let states: Vec<TunnelHandoffState> = serde_json::from_slice(&state)?;
+
+    let expected: usize = states.iter().map(|state| state.sockets + 1).sum();
+
+    if fds.len() != expected {
+        return Err(anyhow!(
+            "Received {} file descriptors for {} tunnels",
+            fds.len(),
+            states.len()
+        ));
+    }
+
+    Ok(states
+        .into_iter()
+        .map(|state| TunnelHandoff {
+            sockets: fds
+                .by_ref()
+                .take(state.sockets)
+                .map(UdpSocket::from)
+                .collect(),
+            interface: fds.next().expect("Descriptors were counted"),
+            state,
+        })
+        .collect())
+}
+
+fn default_sockets() -> usize {
+    1
+}
+
+/// Sends data along with file descriptors over a Unix socket.
+///
+/// Arguments
+/// `stream` - the Unix socket
+/// `data` - the data to be sent, must not be empty
+/// `fds` - the file descriptors to be passed
+fn send_with_fds(stream: &UnixStream, data: &[u8], fds: &[RawFd]) -> Result<()> {
+    let fds_len = std::mem::size_of_val(fds);
+    // SAFETY: `CMSG_SPACE` only computes a size
+    let mut control = vec![0_u8; unsafe { libc::CMSG_SPACE(fds_len as u32) } as usize];
+    let mut iov = libc::iovec {
+        iov_base: data.as_ptr() as *mut libc::c_void,
+        iov_len: data.len(),
+    };
+
+    // SAFETY: all pointers in the message refer to buffers that outlive the call, the control
+    // buffer is large enough for a single `SCM_RIGHTS` message with all descriptors
+    unsafe {
+        let mut message: libc::msghdr = std::mem::zeroed();
+        message.msg_iov = &mut iov;
+        message.msg_iovlen = 1;
+
+        if !fds.is_empty() {
+            message.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+            message.msg_controllen = control.len() as _;
+
+            let header = libc::CMSG_FIRSTHDR(&message);
+            (*header).cmsg_level = libc::SOL_SOCKET;
+            (*header).cmsg_type = libc::SCM_RIGHTS;
+            (*header).cmsg_len = libc::CMSG_LEN(fds_len as u32) as _;
+            std::ptr::copy_nonoverlapping(
+                fds.as_ptr() as *const u8,
+                libc::CMSG_DATA(header),
+                fds_len,
+            );
+        }
+
+        let sent = libc::sendmsg(stream.as_raw_fd(), &message, 0);
+
+        if sent < 0 {
+            return Err(io::Error::last_os_error()).context("sendmsg");
+        }
+
+        if sent as usize != data.len() {
+            return Err(anyhow!("Sent {sent} of {} bytes", data.len()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Receives data along with file descriptors from a Unix socket.
+///
+/// Arguments
+/// `stream` - the Unix socket
+/// `data` - the buffer to be filled completely
+///
+/// Returns
+/// `Vec<OwnedFd>` - the received file descriptors
+fn receive_with_fds(stream: &UnixStream, data: &mut [u8]) -> Result<Vec<OwnedFd>> {
+    let fds_len = HANDOFF_MAX_FDS * std::mem::size_of::<RawFd>();
+    // SAFETY: `CMSG_SPACE` only computes a size
+    let mut control = vec![0_u8; unsafe { libc::CMSG_SPACE(fds_len as u32) } as usize];
+    let mut iov = libc::iovec {
+        iov_base: data.as_mut_ptr() as *mut libc::c_void,
+        iov_len: data.len(),
+    };
+    let mut fds = Vec::new();
+
+    // SAFETY: all pointers in the message refer to buffers that outlive the call, received
+    // descriptors are owned by us and wrapped right away
+    unsafe {
+        let mut message: libc::msghdr = std::mem::zeroed();
+        message.msg_iov = &mut iov;
+        message.msg_iovlen = 1;
+        message.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        message.msg_controllen = control.len() as _;
+
+        let received = libc::recvmsg(stream.as_raw_fd(), &mut message, libc::MSG_CMSG_CLOEXEC);
+
+        if received < 0 {
+            return Err(io::Error::last_os_error()).context("recvmsg");
+        }
+
+        let mut header = libc::CMSG_FIRSTHDR(&message);
+
+        while !header.is_null() {
+            if (*header).cmsg_level == libc::SOL_SOCKET && (*header).cmsg_type == libc::SCM_RIGHTS {
+                let data_len = (*header).cmsg_len as usize - libc::CMSG_LEN(0) as usize;
+                let count = data_len / std::mem::size_of::<RawFd>();
+                let data = libc::CMSG_DATA(header) as *const RawFd;
+
+                for i in 0..count {
+                    fds.push(OwnedFd::from_raw_fd(std::ptr::read_unaligned(data.add(i))));
+                }
+            }
+
+            header = libc::CMSG_NXTHDR(&message, header);
+        }
+
+        if message.msg_flags & libc::MSG_CTRUNC != 0 {
+            return Err(anyhow!("Received too many file descriptors"));
+        }
+
+        if (received as usize) < data.len() {
+            let mut stream = stream;
+            stream.read_exact(&mut data[received as usize..])?;
+        }
+    }
+
+    Ok(fds)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::server::handoff::{
+        receive_handoff, send_handoff, TunnelHandoff, TunnelHandoffState,
+    };
+    use std::collections::HashMap;
+    use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+    use std::os::fd::OwnedFd;
+    use std::os::unix::net::UnixStream;
+
+    #[test]
+    fn test_handoff() {
+        let (old, new) = UnixStream::pair().unwrap();
+        let sockets = vec![
+            UdpSocket::bind("127.0.0.1:0").unwrap(),
+            UdpSocket::bind("127.0.0.1:0").unwrap(),
+        ];
+        let addresses: Vec<_> = sockets.iter().map(|s| s.local_addr().unwrap()).collect();
+        let (interface, _) = UnixStream::pair().unwrap();
+
+        let state = TunnelHandoffState {
+            name: "datacenter".to_string(),
+            interface_name: "tun0".to_string(),
+            address_tunnel: Ipv4Addr::new(10, 0, 0, 1),
+            address_mask: Ipv4Addr::new(255, 255, 255, 0),
+            offload: false,
+            sockets: 2,
+            leases: HashMap::from([("alice".to_string(), IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)))]),
+        };
+
+        send_handoff(
+            &old,
+            &[TunnelHandoff {
+                state: state.clone(),
+                sockets,
+                interface: OwnedFd::from(interface),
+            }],
+        )
+        .unwrap();
+
+        let tunnels = receive_handoff(&new).unwrap();
+
+        assert_eq!(tunnels.len(), 1);
+        assert_eq!(tunnels[0].state, state);
+        let received: Vec<_> = tunnels[0]
+            .sockets
+            .iter()
+            .map(|s| s.local_addr().unwrap())
+            .collect();
+        assert_eq!(received, addresses);
+    }
+}
@@ -0,0 +1,781 @@
+use crate::config::{ConnectionConfig, DrainConfig, SupervisorConfig, TunnelConfig};
+#[cfg(target_os = "linux")]
+use crate::server::handoff::TunnelHandoff;
+use crate::server::tunnel::RumbleTunnel;
+use anyhow::{anyhow, Result};
+use dashmap::DashMap;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::time::Instant as TokioInstant;
+use tracing::{error, info, warn};
+
+/// State of a supervised tunnel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TunnelState {
+    /// The tunnel is about to be started
+    Starting,
+    /// The tunnel is running and has been stable
+    Running,
+    /// The tunnel is running, but has failed within the stable period
+    Degraded,
+    /// The tunnel failed and waits for its next restart
+    BackingOff,
+    /// The tunnel failed too many times and is not restarted anymore
+    Failed,
+}
+
+impl Display for TunnelState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let state = match self {
+            TunnelState::Starting => "starting",
+            TunnelState::Running => "running",
+            TunnelState::Degraded => "degraded",
+            TunnelState::BackingOff => "backing off",
+            TunnelState::Failed => "failed",
+        };
+
+        write!(f, "{state}")
+    }
+}
+
+/// Supervision status of a tunnel.
+#[derive(Clone, Debug)]
+pub struct TunnelStatus {
+    /// The current state of the tunnel
+    pub state: TunnelState,
+    /// The number of consecutive failures since the tunnel was last stable
+    pub failures: u32,
+    /// The reason of the last failure
+    pub last_error: Option<String>,
+    /// When the tunnel was last started, or is restarted next while backing off
+    since: Instant,
+}
+
+impl TunnelStatus {
+    /// Creates the status of a tunnel that is about to be started
+    fn new(now: Instant) -> Self {
+        Self {
+            state: TunnelState::Starting,
+            failures: 0,
+            last_error: None,
+            since: now,
+        }
+    }
+
+    /// Records a successful start of the tunnel.
+    ///
+    /// Arguments
+    /// `now` - the current time
+    fn record_started(&mut self, now: Instant) {
+        self.state = if self.failures == 0 {
+            TunnelState::Running
+        } else {
+            TunnelState::Degraded
+        };
+        self.since = now;
+    }
+
+    /// Records a failure of the tunnel and schedules the next restart, if retries are left.
+    ///
+    /// Arguments
+    /// `error` - the reason of the failure
+    /// `config` - the supervisor config
+    /// `now` - the current time
+    fn record_failure(&mut self, error: String, config: &SupervisorConfig, now: Instant) {
+        self.failures += 1;
+        self.last_error = Some(error);
+
+        if config
+            .max_retries
+            .is_some_and(|max_retries| self.failures > max_retries)
+        {
+            self.state = TunnelState::Failed;
+            return;
+        }
+
+        self.state = TunnelState::BackingOff;
+        self.since = now + backoff(self.failures, config);
+    }
+
+    /// Marks a degraded tunnel as healthy once it has run for the stable period.
+    ///
+    /// Arguments
+    /// `config` - the supervisor config
+    /// `now` - the current time
+    fn check_stable(&mut self, config: &SupervisorConfig, now: Instant) {
+        if self.state == TunnelState::Degraded && now >= self.since + config.stable_period {
+            self.state = TunnelState::Running;
+            self.failures = 0;
+        }
+    }
+
+    /// Checks whether a backing off tunnel is due to be restarted.
+    ///
+    /// Arguments
+    /// `now` - the current time
+    fn check_backoff(&mut self, now: Instant) {
+        if self.state == TunnelState::BackingOff && now >= self.since {
+            self.state = TunnelState::Starting;
+        }
+    }
+}
+
+impl Display for TunnelStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({} consecutive failures", self.state, self.failures)?;
+
+        if let Some(last_error) = &self.last_error {
+            write!(f, ", last error: {last_error}")?;
+        }
+
+        write!(f, ")")
+    }
+}
+
+/// Returns the delay before the next restart of a tunnel.
+///
+/// Arguments
+/// `failures` - the number of consecutive failures of the tunnel
+/// `config` - the supervisor config
+pub fn backoff(failures: u32, config: &SupervisorConfig) -> Duration {
+    let exponent = failures.saturating_sub(1).min(31);
+
+    config
+        .initial_backoff
+        .saturating_mul(1 << exponent)
+        .min(config.max_backoff)
+}
+
+/// Tunnel configs by tunnel name, each with the connection config of the tunnel
+pub type TunnelConfigs = HashMap<String, (TunnelConfig, ConnectionConfig)>;
+
+/// Differences between the supervised tunnels and a new set of tunnel configs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TunnelChanges {
+    /// Tunnels that are not supervised yet
+    pub added: Vec<String>,
+    /// Tunnels that are no longer configured
+    pub removed: Vec<String>,
+    /// Tunnels whose config has changed
+    pub changed: Vec<String>,
+}
+
+impl TunnelChanges {
+    /// Returns `true` if no tunnel is added, removed or changed
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// A tunnel and its supervision status.
+struct SupervisedTunnel {
+    tunnel_config: TunnelConfig,
+    connection_config: ConnectionConfig,
+    tunnel: Option<RumbleTunnel>,
+    #[cfg(target_os = "linux")]
+    adopted: Option<TunnelHandoff>,
+    status: TunnelStatus,
+}
+
+impl SupervisedTunnel {
+    /// Prepares the tunnel to be started, creating it first if that has not succeeded yet.
+    ///
+    /// Arguments
+    /// `name` - the name of the tunnel
+    /// `drain` - the drain config if the server is draining
+    ///
+    /// Returns
+    /// `RumbleTunnel` - the tunnel taken out of the entry, so it can be started without holding
+    /// the entry locked
+    fn prepare_start(&mut self, name: &str, drain: Option<DrainConfig>) -> Result<RumbleTunnel> {
+        let tunnel = match &mut self.tunnel {
+            Some(tunnel) => tunnel,
+            None => self.tunnel.insert(RumbleTunnel::new(
+                name.to_string(),
+                self.tunnel_config.clone(),
+                &self.connection_config,
+            )?),
+        };
+
+        #[cfg(target_os = "linux")]
+        if let Some(handoff) = self.adopted.take() {
+            tunnel.adopt(handoff);
+        }
+
+        if let Some(drain) = drain {
+            tunnel.drain(drain.redirect, drain.deadline)?;
+        }
+
+        self.tunnel
+            .take()
+            .ok_or_else(|| anyhow!("Tunnel '{name}' does not exist"))
+    }
+}
+
+/// Stops a tunnel after a failure.
+///
+/// Returns
+/// `String` - the reason of the failure
+async fn stop_failed(tunnel: &mut RumbleTunnel) -> String {
+    match tunnel.stop().await {
+        Ok(()) => "Tunnel tasks stopped unexpectedly".to_string(),
+        Err(e) => e.to_string(),
+    }
+}
+
+/// Starts tunnels and restarts them with exponential backoff when they fail.
+///
+/// Tunnels are taken out of their entries while they are started or stopped, so the entries are
+/// never locked across an await and the statuses can be read at any time.
+pub struct TunnelSupervisor {
+    config: SupervisorConfig,
+    tunnels: DashMap<String, SupervisedTunnel>,
+    drain: Mutex<Option<DrainConfig>>,
+}
+
+impl TunnelSupervisor {
+    /// Creates a new supervisor.
+    ///
+    /// Arguments
+    /// `config` - the supervisor config
+    pub fn new(config: SupervisorConfig) -> Self {
+        Self {
+            config,
+            tunnels: DashMap::new(),
+            drain: Mutex::new(None),
+        }
+    }
+
+    /// Adds a tunnel, which is started with the next supervision pass.
+    ///
+    /// Arguments
+    /// `name` - the name of the tunnel
+    /// `tunnel_config` - the tunnel config
+    /// `connection_config` - the connection config of the tunnel
+    pub fn add(
+        &self,
+        name: String,
+        tunnel_config: TunnelConfig,
+        connection_config: ConnectionConfig,
+    ) {
+        self.tunnels.insert(
+            name,
+            SupervisedTunnel {
+                tunnel_config,
+                connection_config,
+                tunnel: None,
+                #[cfg(target_os = "linux")]
+                adopted: None,
+                status: TunnelStatus::new(Instant::now()),
+            },
+        );
+    }
+
+    /// Returns the status of every tunnel
+    pub fn statuses(&self) -> Vec<(String, TunnelStatus)> {
+        self.tunnels
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().status.clone()))
+            .collect()
+    }
+
+    /// Starts, checks and restarts the tunnels as required by their state.
+    ///
+    /// Returns
+    /// `Err` if no tunnel is left that is not given up
+    pub async fn supervise(&self) -> Result<()> {
+        for name in self.names() {
+            let state = match self.tunnels.get_mut(&name) {
+                Some(mut supervised) => {
+                    supervised.status.check_backoff(Instant::now());
+                    supervised.status.state
+                }
+                None => continue,
+            };
+
+            match state {
+                TunnelState::Starting => self.start_tunnel(&name).await?,
+                TunnelState::Running | TunnelState::Degraded => self.check_tunnel(&name).await,
+                TunnelState::BackingOff | TunnelState::Failed => (),
+            }
+        }
+
+        if !self.tunnels.is_empty()
+            && self
+                .tunnels
+                .iter()
+                .all(|entry| entry.value().status.state == TunnelState::Failed)
+        {
+            return Err(anyhow!("All tunnels have failed"));
+        }
+
+        Ok(())
+    }
+
+    /// Starts a tunnel and records the outcome in its status.
+    ///
+    /// Arguments
+    /// `name` - the name of the tunnel
+    async fn start_tunnel(&self, name: &str) -> Result<()> {
+        let drain = self.draining()?;
+        let prepared = match self.tunnels.get_mut(name) {
+            Some(mut supervised) => supervised.prepare_start(name, drain.clone()),
+            None => return Ok(()),
+        };
+
+        let result = match prepared {
+            Ok(mut tunnel) => {
+                let mut result = tunnel.start().await;
+
+                // The server may have started draining while the tunnel was taken out
+                if let Some(current) = self
+                    .draining()?
+                    .filter(|current| Some(current) != drain.as_ref())
+                {
+                    result = result.and_then(|_| tunnel.drain(current.redirect, current.deadline));
+                }
+
+                self.restore(name, tunnel).await;
+                result
+            }
+            Err(e) => Err(e),
+        };
+
+        let Some(mut supervised) = self.tunnels.get_mut(name) else {
+            return Ok(());
+        };
+
+        match result {
+            Ok(()) => {
+                supervised.status.record_started(Instant::now());
+                info!("Tunnel '{name}' started: {}", supervised.status);
+            }
+            Err(e) => {
+                supervised
+                    .status
+                    .record_failure(e.to_string(), &self.config, Instant::now());
+                self.log_failure(name, &supervised.status);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether a started tunnel is still running, stopping it and recording the failure
+    /// if it is not.
+    ///
+    /// Arguments
+    /// `name` - the name of the tunnel
+    async fn check_tunnel(&self, name: &str) {
+        let tunnel = match self.tunnels.get_mut(name) {
+            Some(mut supervised) => {
+                let running = supervised
+                    .tunnel
+                    .as_ref()
+                    .is_some_and(|tunnel| tunnel.is_ok());
+
+                if running {
+                    supervised.status.check_stable(&self.config, Instant::now());
+                    return;
+                }
+
+                supervised.tunnel.take()
+            }
+            None => return,
+        };
+
+        let error = match tunnel {
+            Some(mut tunnel) => {
+                let error = stop_failed(&mut tunnel).await;
+                self.restore(name, tunnel).await;
+                error
+            }
+            None => "Tunnel does not exist".to_string(),
+        };
+
+        if let Some(mut supervised) = self.tunnels.get_mut(name) {
+            supervised
+                .status
+                .record_failure(error, &self.config, Instant::now());
+            self.log_failure(name, &supervised.status);
+        }
+    }
+
+    /// Returns the names of all supervised tunnels
+    fn names(&self) -> Vec<String> {
+        self.tunnels
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect()
+    }
+
+    /// Takes a tunnel out of its entry, so it can be awaited without holding the entry locked.
+    ///
+    /// Arguments
+    /// `name` - the name of the tunnel
+    fn take_tunnel(&self, name: &str) -> Option<RumbleTunnel> {
+        self.tunnels.get_mut(name)?.tunnel.take()
+    }
+
+    /// Puts a tunnel taken out of its entry back, stopping it if the tunnel has been removed in
+    /// the meantime.
+    ///
+    /// Arguments
+    /// `name` - the name of the tunnel
+    /// `tunnel` - the tunnel taken out
+    async fn restore(&self, name: &str, mut tunnel: RumbleTunnel) {
+        if let Some(mut supervised) = self.tunnels.get_mut(name) {
+            supervised.tunnel = Some(tunnel);
+            return;
+        }
+
+        if let Err(e) = tunnel.stop().await {
+            warn!("Removed tunnel '{name}' stopped with an error: {e}");
+        }
+    }
+
+    /// Compares the supervised tunnels with a new set of tunnel configs.
+    ///
+    /// Arguments
+    /// `tunnel_configs` - the new tunnel configs
+    ///
+    /// Returns
+    /// `TunnelChanges` - the tunnels to be added, removed and restarted, sorted by name
+    pub fn diff(&self, tunnel_configs: &TunnelConfigs) -> TunnelChanges {
+        let mut changes = TunnelChanges::default();
+
+        for entry in self.tunnels.iter() {
+            match tunnel_configs.get(entry.key()) {
+                None => changes.removed.push(entry.key().clone()),
+                Some((tunnel_config, connection_config))
+                    if *tunnel_config != entry.value().tunnel_config
+                        || *connection_config != entry.value().connection_config =>
+                {
+                    changes.changed.push(entry.key().clone())
+                }
+                Some(_) => (),
+            }
+        }
+
+        changes.added = tunnel_configs
+            .keys()
+            .filter(|name| !self.tunnels.contains_key(*name))
+            .cloned()
+            .collect();
+
+        changes.added.sort();
+        changes.removed.sort();
+        changes.changed.sort();
+
+        changes
+    }
+
+    /// Applies a new set of tunnel configs.
+    ///
+    /// Removed and changed tunnels are shut down, new and changed tunnels are started with the
+    /// next supervision pass. Unchanged tunnels keep running along with their sessions.
+    ///
+    /// Arguments
+    /// `tunnel_configs` - the new tunnel configs
+    /// `drain_deadline` - the longest time to wait for connections of stopped tunnels to close
+    ///
+    /// Returns
+    /// `TunnelChanges` - the tunnels that have been added, removed and restarted
+    pub async fn reconcile(
+        &self,
+        mut tunnel_configs: TunnelConfigs,
+        drain_deadline: Duration,
+    ) -> TunnelChanges {
+        let changes = self.diff(&tunnel_configs);
+        let deadline = TokioInstant::now() + drain_deadline;
+
+        for name in changes.removed.iter().chain(changes.changed.iter()) {
+            let Some((_, mut supervised)) = self.tunnels.remove(name) else {
+                continue;
+            };
+
+            if let Some(tunnel) = &mut supervised.tunnel {
+                if let Err(e) = tunnel.shutdown(deadline).await {
+                    warn!("Tunnel '{name}' stopped with an error: {e}");
+                }
+            }
+
+            info!("Tunnel '{name}' stopped");
+        }
+
+        for name in changes.added.iter().chain(changes.changed.iter()) {
+            if let Some((tunnel_config, connection_config)) = tunnel_configs.remove(name) {
+                self.add(name.clone(), tunnel_config, connection_config);
+            }
+        }
+
+        changes
+    }
+
+    /// Shuts down all tunnels.
+    ///
+    /// All tunnels stop accepting connections and close their connections first, then the
+    /// connections are given until the drain deadline to close before the tunnels are stopped.
+    ///
+    /// Arguments
+    /// `drain_deadline` - the longest time to wait for connections to close
+    pub async fn shutdown(&self, drain_deadline: Duration) {
+        let deadline = TokioInstant::now() + drain_deadline;
+
+        for entry in self.tunnels.iter() {
+            if let Some(tunnel) = &entry.value().tunnel {
+                tunnel.close();
+            }
+        }
+
+        for name in self.names() {
+            if let Some(mut tunnel) = self.take_tunnel(&name) {
+                match tunnel.shutdown(deadline).await {
+                    Ok(()) => info!("Tunnel '{name}' stopped"),
+                    Err(e) => warn!("Tunnel '{name}' stopped with an error: {e}"),
+                }
+
+                self.restore(&name, tunnel).await;
+            }
+        }
+    }
+
+    /// Takes all tunnels out of rotation, including tunnels started later on.
+    ///
+    /// Arguments
+    /// `drain` - the drain config
+    pub fn drain(&self, drain: DrainConfig) -> Result<()> {
+        // Set first, so tunnels being started right now pick it up once they are started
+        *self
+            .drain
+            .lock()
+            .map_err(|_| anyhow!("Drain lock is poisoned"))? = Some(drain.clone());
+
+        for mut entry in self.tunnels.iter_mut() {
+            if let Some(tunnel) = &mut entry.value_mut().tunnel {
+                tunnel.drain(drain.redirect.clone(), drain.deadline)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the drain config if the tunnels are draining
+    fn draining(&self) -> Result<Option<DrainConfig>> {
+        Ok(self
+            .drain
+            .lock()
+            .map_err(|_| anyhow!("Drain lock is poisoned"))?
+            .clone())
+    }
+
+    /// Adopts tunnels handed over by another server process, they are used the next time the
+    /// tunnels with the same names are started.
+    ///
+    /// Arguments
+    /// `handoffs` - the tunnels handed over
+    #[cfg(target_os = "linux")]
+    pub fn adopt(&self, handoffs: Vec<TunnelHandoff>) {
+        for handoff in handoffs {
+            match self.tunnels.get_mut(&handoff.state.name) {
+                Some(mut supervised) => supervised.adopted = Some(handoff),
+                None => warn!(
+                    "Tunnel '{}' was handed over, but is not configured anymore",
+                    handoff.state.name
+                ),
+            }
+        }
+    }
+
+    /// Hands all running tunnels over to a new server process.
+    ///
+    /// The tunnels close their connections and stop right away, so that the new server process
+    /// can accept the reconnecting clients as soon as it has the tunnels.
+    ///
+    /// Returns
+    /// `Vec<TunnelHandoff>` - the tunnels to be handed over
+    #[cfg(target_os = "linux")]
+    pub async fn hand_over(&self) -> Result<Vec<TunnelHandoff>> {
+        let mut handoffs = Vec::new();
+
+        for name in self.names() {
+            if let Some(mut tunnel) = self.take_tunnel(&name) {
+                let handoff = tunnel.hand_over().await;
+                self.restore(&name, tunnel).await;
+
+                handoffs.extend(handoff?);
+            }
+        }
+
+        Ok(handoffs)
+    }
+
+    /// Stops the tunnels handed over from reading the UDP sockets now owned by the new server
+    /// process.
+    #[cfg(target_os = "linux")]
+    pub fn release_sockets(&self) {
+        for entry in self.tunnels.iter() {
+            if let Some(tunnel) = &entry.value().tunnel {
+                tunnel.release_sockets();
+            }
+        }
+    }
+
+    /// Waits for the connections closed by the handoff to be drained.
+    ///
+    /// Arguments
+    /// `drain_deadline` - the longest time to wait for connections to close
+    #[cfg(target_os = "linux")]
+    pub async fn drain_handoff(&self, drain_deadline: Duration) {
+        let deadline = TokioInstant::now() + drain_deadline;
+
+        for name in self.names() {
+            if let Some(mut tunnel) = self.take_tunnel(&name) {
+                tunnel.drain_handoff(deadline).await;
+                self.restore(&name, tunnel).await;
+            }
+        }
+    }
+
+    /// Logs the failure of a tunnel.
+    fn log_failure(&self, name: &str, status: &TunnelStatus) {
+        match status.state {
+            TunnelState::Failed => error!("Tunnel '{name}' has been given up: {status}"),
+            _ => warn!(
+                "Tunnel '{name}' failed, restarting in {:?}: {status}",
+                backoff(status.failures, &self.config)
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{ConnectionConfig, SupervisorConfig, TunnelConfig};
+    use crate::server::supervisor::{
+        backoff, TunnelChanges, TunnelConfigs, TunnelState, TunnelStatus, TunnelSupervisor,
+    };
+    use figment::providers::{Format, Toml};
+    use figment::Figment;
+    use std::time::{Duration, Instant};
+
+    fn config() -> SupervisorConfig {
+        SupervisorConfig {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(10),
+            max_retries: Some(3),
+            stable_period: Duration::from_secs(30),
+        }
+    }
+
+    fn tunnel_config(name: &str, port: u16) -> TunnelConfig {
+        Figment::from(Toml::string(&format!(
+            r#"
+            name = "{name}"
+            certificate_file = "cert.pem"
+            certificate_key_file = "key.pem"
+            bind_port = {port}
+            address_tunnel = "10.0.0.1"
+            address_mask = "255.255.255.0"
+            users_file = "users"
+            "#
+        )))
+        .extract()
+        .unwrap()
+    }
+
+    fn connection_config(mtu: u32) -> ConnectionConfig {
+        Figment::from(Toml::string(&format!("mtu = {mtu}")))
+            .extract()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_diff_tunnels() {
+        let supervisor = TunnelSupervisor::new(config());
+
+        for name in ["kept", "moved", "resized", "dropped"] {
+            supervisor.add(
+                name.to_string(),
+                tunnel_config(name, 9000),
+                connection_config(1400),
+            );
+        }
+
+        let tunnel_configs: TunnelConfigs = [
+            ("kept", 9000, 1400),
+            ("moved", 9001, 1400),
+            ("resized", 9000, 1300),
+            ("new", 9002, 1400),
+        ]
+        .into_iter()
+        .map(|(name, port, mtu)| {
+            (
+                name.to_string(),
+                (tunnel_config(name, port), connection_config(mtu)),
+            )
+        })
+        .collect();
+
+        assert_eq!(
+            supervisor.diff(&tunnel_configs),
+            TunnelChanges {
+                added: vec!["new".to_string()],
+                removed: vec!["dropped".to_string()],
+                changed: vec!["moved".to_string(), "resized".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_backoff() {
+        let config = config();
+
+        assert_eq!(backoff(1, &config), Duration::from_secs(1));
+        assert_eq!(backoff(2, &config), Duration::from_secs(2));
+        assert_eq!(backoff(4, &config), Duration::from_secs(8));
+        assert_eq!(backoff(5, &config), Duration::from_secs(10));
+        assert_eq!(backoff(100, &config), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_restart_after_backoff() {
+        let config = config();
+        let now = Instant::now();
+        let mut status = TunnelStatus::new(now);
+
+        status.record_failure("bind failed".to_string(), &config, now);
+        assert_eq!(status.state, TunnelState::BackingOff);
+        assert_eq!(status.last_error.as_deref(), Some("bind failed"));
+
+        status.check_backoff(now + Duration::from_millis(500));
+        assert_eq!(status.state, TunnelState::BackingOff);
+
+        status.check_backoff(now + Duration::from_secs(1));
+        assert_eq!(status.state, TunnelState::Starting);
+
+        status.record_started(now + Duration::from_secs(1));
+        assert_eq!(status.state, TunnelState::Degraded);
+
+        status.check_stable(&config, now + Duration::from_secs(31));
+        assert_eq!(status.state, TunnelState::Running);
+        assert_eq!(status.failures, 0);
+    }
+
+    #[test]
+    fn test_give_up_after_max_retries() {
+        let config = config();
+        let now = Instant::now();
+        let mut status = TunnelStatus::new(now);
+
+        for _ in 0..3 {
+            status.record_failure("interface busy".to_string(), &config, now);
+            assert_eq!(status.state, TunnelState::BackingOff);
+        }
+
+        status.record_failure("interface busy".to_string(), &config, now);
+        assert_eq!(status.state, TunnelState::Failed);
+    }
+}
@@ -1,12 +1,26 @@
+#[cfg(target_os = "linux")]
+use std::net::{Ipv4Addr, Ipv6Addr};
 use std::net::{IpAddr, SocketAddr, SocketAddrV4};
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
 
+use crate::auth::server::Admission;
 use crate::auth::user::{load_users_file, UserDatabase};
 use crate::config::{ConnectionConfig, TunnelConfig};
 use crate::server::address_pool::AddressPool;
 use crate::server::connection::RumbleConnection;
-use crate::utils::interface::{read_from_interface, set_up_interface, write_to_interface};
+#[cfg(target_os = "linux")]
+use crate::server::handoff::{TunnelHandoff, TunnelHandoffState};
+#[cfg(target_os = "linux")]
+use crate::server::workers::{attach_steering_program, WorkerConnectionIdGenerator};
+use crate::utils::control::Redirect;
+#[cfg(target_os = "linux")]
+use crate::utils::interface::adopt_interface;
+use crate::utils::interface::{
+    set_up_interface, split_interface, Interface, InterfaceReader, InterfaceWriter,
+};
+#[cfg(target_os = "linux")]
+use crate::utils::socket::bind_reuse_port_sockets;
 use crate::utils::socket::bind_socket;
 use crate::utils::tasks::join_or_abort_task;
 use anyhow::{anyhow, Result};
@@ -14,17 +28,19 @@ use bytes::Bytes;
 use dashmap::DashMap;
 use etherparse::{IpHeader, PacketHeaders};
 use ipnet::Ipv4Net;
-use quinn::Endpoint;
-use tokio::io::{ReadHalf, WriteHalf};
+use quinn::{Connecting, Endpoint, VarInt};
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 use tokio::task::JoinHandle;
-use tokio::time::sleep;
+use tokio::time::{sleep, timeout_at, Instant};
 
-use crate::constants::{CLEANUP_INTERVAL, QUINN_RUNTIME};
+use crate::constants::{
+    CLEANUP_INTERVAL, CLOSE_CODE_RESTART, CLOSE_CODE_SHUTDOWN, DRAIN_REDIRECT_INTERVAL,
+    INTERFACE_BATCH_SIZE, QUINN_RUNTIME,
+};
 use tracing::{debug, error, info, warn};
-use tun::AsyncDevice;
 
 type SharedConnections = Arc<DashMap<IpAddr, RumbleConnection>>;
+type SharedAdmission = Arc<RwLock<Admission>>;
 
 /// Represents a Rumble tunnel encapsulating Rumble connections and TUN interface IO.
 pub struct RumbleTunnel {
@@ -34,12 +50,22 @@ pub struct RumbleTunnel {
     active_connections: SharedConnections,
     user_database: Arc<UserDatabase>,
     address_pool: Arc<AddressPool>,
+    admission: SharedAdmission,
     buffer_size: usize,
+    endpoints: Vec<Endpoint>,
+    #[cfg(target_os = "linux")]
+    adopted: Option<TunnelHandoff>,
+    #[cfg(target_os = "linux")]
+    handoff: Option<TunnelHandoff>,
     tasks: Vec<JoinHandle<Result<()>>>,
+    drain_task: Option<JoinHandle<()>>,
 }
 
 impl RumbleTunnel {
-        /// Handles incoming connections by spawning a new RumbleConnection
+    /// Handles incoming connections by spawning a new RumbleConnection for each of them.
+    ///
+    /// Connections are authenticated concurrently and only become active once the client is
+    /// authenticated and has been leased an address.
     ///
     /// Arguments
     /// `active_connections` - a map of connections and their associated client IP addresses
@@ -47,6 +73,7 @@ impl RumbleTunnel {
     /// `address_pool` - the address pool being used
     /// `write_queue_sender` - the channel for sending data to the TUN interface worker
     /// `user_database` - the user database
+    /// `admission` - how new sessions are admitted
     /// `endpoint` - the QUIC endpoint
     async fn handle_incoming_connections(
         active_connections: Arc<DashMap<IpAddr, RumbleConnection>>,
@@ -54,6 +81,7 @@ impl RumbleTunnel {
         address_pool: Arc<AddressPool>,
         write_queue_sender: Arc<UnboundedSender<Bytes>>,
         user_database: Arc<UserDatabase>,
+        admission: SharedAdmission,
         endpoint: Endpoint,
     ) -> Result<()> {
         info!(
@@ -62,33 +90,96 @@ impl RumbleTunnel {
         );
 
         while let Some(handshake) = endpoint.accept().await {
+            let remote_address = handshake.remote_address();
             debug!(
                 "Received incoming connection from '{}'",
-                handshake.remote_address().ip()
+                remote_address.ip()
             );
 
-            let client_tun_ip = address_pool
-                .next_available_address()
-                .ok_or_else(|| anyhow!("Could not find an available address for client"))?;
-
-            let mut connection = RumbleConnection::new(
-                handshake.await?,
-                &connection_config,
+            let connection = Self::accept_connection(
+                handshake,
+                active_connections.clone(),
+                connection_config.clone(),
+                address_pool.clone(),
                 write_queue_sender.clone(),
                 user_database.clone(),
-                client_tun_ip,
-            )
-            .await?;
-
-            connection.start().await?;
-            info!(
-                "Connection established: {client_tun_ip} ({})",
-                connection.remote_address(),
+                admission
+                    .read()
+                    .map_err(|_| anyhow!("Admission lock is poisoned"))?
+                    .clone(),
             );
 
-            active_connections.insert(client_tun_ip.addr(), connection);
+            tokio::spawn(async move {
+                if let Err(e) = connection.await {
+                    warn!("Failed to accept connection from {remote_address}: {e}");
+                }
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Authenticates an incoming connection and activates it.
+    ///
+    /// Arguments
+    /// `handshake` - the incoming QUIC connection
+    /// `active_connections` - a map of connections and their associated client IP addresses
+    /// `connection_config` - the connection configuration
+    /// `address_pool` - the address pool being used
+    /// `write_queue_sender` - the channel for sending data to the TUN interface worker
+    /// `user_database` - the user database
+    /// `admission` - how the session is admitted
+    async fn accept_connection(
+        handshake: Connecting,
+        active_connections: SharedConnections,
+        connection_config: ConnectionConfig,
+        address_pool: Arc<AddressPool>,
+        write_queue_sender: Arc<UnboundedSender<Bytes>>,
+        user_database: Arc<UserDatabase>,
+        admission: Admission,
+    ) -> Result<()> {
+        let mut connection = RumbleConnection::new(
+            handshake.await?,
+            &connection_config,
+            write_queue_sender,
+            user_database,
+            address_pool.clone(),
+            admission,
+        )
+        .await?;
+
+        let client_address = match connection.authenticate().await {
+            Ok(Some(client_address)) => client_address,
+            Ok(None) => {
+                info!(
+                    "Redirected client {} to another server",
+                    connection.remote_address()
+                );
+
+                return Ok(());
+            }
+            Err(e) => {
+                if let Some(client_address) = connection.client_address().await {
+                    address_pool.release_address(client_address.addr());
+                }
+
+                return Err(e);
+            }
+        };
+
+        if let Err(e) = connection.start().await {
+            address_pool.release_address(client_address.addr());
+
+            return Err(e);
         }
 
+        info!(
+            "Connection established: {client_address} ({})",
+            connection.remote_address(),
+        );
+
+        active_connections.insert(client_address.addr(), connection);
+
         Ok(())
     }
 
@@ -106,8 +197,22 @@ impl RumbleTunnel {
         let interface_address =
             Ipv4Net::with_netmask(tunnel_config.address_tunnel, tunnel_config.address_mask)?.into();
 
+        if tunnel_config.workers == 0 {
+            return Err(anyhow!("Tunnel '{name}' needs at least one worker"));
+        }
+
+        if tunnel_config.workers > 1 && !cfg!(target_os = "linux") {
+            return Err(anyhow!(
+                "Tunnel '{name}' cannot use multiple workers on this platform"
+            ));
+        }
+
         let user_database = UserDatabase::new(load_users_file(&tunnel_config.users_file)?);
         let address_pool = AddressPool::new(interface_address)?;
+        let admission = match &tunnel_config.redirect {
+            Some(address) => Admission::Redirect(address.clone()),
+            None => Admission::Accept,
+        };
 
         Ok(Self {
             name,
@@ -116,8 +221,15 @@ impl RumbleTunnel {
             active_connections: Arc::new(DashMap::new()),
             user_database: Arc::new(user_database),
             address_pool: Arc::new(address_pool),
+            admission: Arc::new(RwLock::new(admission)),
             buffer_size: connection_config.mtu as usize,
+            endpoints: Vec::new(),
+            #[cfg(target_os = "linux")]
+            adopted: None,
+            #[cfg(target_os = "linux")]
+            handoff: None,
             tasks: Vec::new(),
+            drain_task: None,
         })
     }
 
@@ -127,25 +239,57 @@ impl RumbleTunnel {
             return Err(anyhow!("Tunnel '{}' is already running", self.name));
         }
 
-        let interface_address = Ipv4Net::with_netmask(
-            self.tunnel_config.address_tunnel,
-            self.tunnel_config.address_mask,
-        )?
-        .into();
-        let interface = set_up_interface(interface_address, self.connection_config.mtu)?;
+        let (interface, sockets) = self.set_up_interface_and_socket()?;
+
+        #[cfg(target_os = "linux")]
+        {
+            self.handoff = Some(TunnelHandoff {
+                state: TunnelHandoffState {
+                    name: self.name.clone(),
+                    interface_name: interface.name(),
+                    address_tunnel: self.tunnel_config.address_tunnel,
+                    address_mask: self.tunnel_config.address_mask,
+                    offload: interface.has_offload(),
+                    sockets: sockets.len(),
+                    leases: Default::default(),
+                },
+                sockets: sockets
+                    .iter()
+                    .map(|socket| socket.try_clone())
+                    .collect::<Result<_, _>>()?,
+                interface: interface.try_clone_fd()?,
+            });
+        }
 
-        let (tun_read, tun_write) = tokio::io::split(interface);
+        let (tun_read, tun_write) = split_interface(interface, self.buffer_size);
         let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
 
+        let certificate_resolver = Arc::new(self.tunnel_config.certificate_resolver()?);
         let quinn_configuration = self
             .tunnel_config
-            .as_quinn_server_config(&self.connection_config)?;
-        let endpoint = self.create_quinn_endpoint(quinn_configuration)?;
+            .as_quinn_server_config(&self.connection_config, certificate_resolver.clone())?;
+        let sender = Arc::new(sender);
+
+        for (worker, socket) in sockets.into_iter().enumerate() {
+            let endpoint =
+                self.create_quinn_endpoint(quinn_configuration.clone(), socket, worker as u8)?;
+            self.endpoints.push(endpoint.clone());
+
+            self.tasks
+                .push(tokio::spawn(Self::handle_incoming_connections(
+                    self.active_connections.clone(),
+                    self.connection_config.clone(),
+                    self.address_pool.clone(),
+                    sender.clone(),
+                    self.user_database.clone(),
+                    self.admission.clone(),
+                    endpoint,
+                )));
+        }
 
         self.tasks.push(tokio::spawn(Self::process_outbound_traffic(
             tun_read,
             self.active_connections.clone(),
-            self.buffer_size,
         )));
 
         self.tasks.push(tokio::spawn(Self::process_inbound_traffic(
@@ -157,33 +301,273 @@ impl RumbleTunnel {
             self.address_pool.clone(),
         )));
 
-        self.tasks
-            .push(tokio::spawn(Self::handle_incoming_connections(
+        self.tasks.push(tokio::spawn(certificate_resolver.watch()));
+
+        Ok(())
+    }
+
+    /// Adopts the UDP socket, TUN interface and address leases handed over by another server
+    /// process, which are used the next time the tunnel is started.
+    ///
+    /// Arguments
+    /// `handoff` - the tunnel handed over
+    #[cfg(target_os = "linux")]
+    pub fn adopt(&mut self, handoff: TunnelHandoff) {
+        self.address_pool
+            .restore_leases(handoff.state.leases.clone());
+        self.adopted = Some(handoff);
+    }
+
+    /// Stops accepting new connections and closes all connections, telling the clients that the
+    /// server is shutting down.
+    pub fn close(&self) {
+        self.close_with(CLOSE_CODE_SHUTDOWN, b"Server shutting down");
+    }
+
+    /// Stops accepting new connections and closes all connections, telling the clients to
+    /// reconnect right away as the tunnel is handed over to a new server process.
+    pub fn close_for_handoff(&self) {
+        self.close_with(CLOSE_CODE_RESTART, b"Server restarting");
+    }
+
+    /// Closes the tunnel, waits for the connections to be closed and stops the tunnel.
+    ///
+    /// Arguments
+    /// `drain_deadline` - the point in time after which the connections are no longer waited for
+    pub async fn shutdown(&mut self, drain_deadline: Instant) -> Result<()> {
+        self.close();
+        self.wait_idle(drain_deadline).await;
+
+        self.stop().await
+    }
+
+    /// Closes the tunnel and stops it right away, keeping its UDP socket and TUN interface open
+    /// for a new server process.
+    ///
+    /// The closed endpoints keep running until [`Self::drain_handoff`], so that clients that
+    /// missed the close are told again. Once the tunnel is sent, [`Self::release_sockets`] has to
+    /// move them off the shared UDP sockets, which then belong to the new server process.
+    ///
+    /// Returns
+    /// `Option<TunnelHandoff>` - the tunnel to be handed over, `None` if it is not running
+    #[cfg(target_os = "linux")]
+    pub async fn hand_over(&mut self) -> Result<Option<TunnelHandoff>> {
+        self.close_for_handoff();
+
+        let handoff = self.handoff.take().map(|mut handoff| {
+            handoff.state.leases = self.address_pool.leases();
+            handoff
+        });
+
+        // Stops reading the TUN interface, which now belongs to the new server process
+        let endpoints = std::mem::take(&mut self.endpoints);
+
+        if let Err(e) = self.stop().await {
+            warn!("Tunnel '{}' stopped with an error: {e}", self.name);
+        }
+
+        self.endpoints = endpoints;
+
+        Ok(handoff)
+    }
+
+    /// Moves the endpoints closed by [`Self::hand_over`] to throwaway sockets, so that they stop
+    /// reading datagrams meant for the new server process from the shared UDP sockets.
+    #[cfg(target_os = "linux")]
+    pub fn release_sockets(&self) {
+        for endpoint in &self.endpoints {
+            let result = endpoint.local_addr().and_then(|address| {
+                let unspecified: IpAddr = match address {
+                    SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+                    SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+                };
+                let socket = std::net::UdpSocket::bind(SocketAddr::new(unspecified, 0))?;
+                socket.set_nonblocking(true)?;
+
+                endpoint.rebind(socket)
+            });
+
+            if let Err(e) = result {
+                warn!(
+                    "Failed to release the socket of tunnel '{}', it may still read datagrams \
+                     of the new server process: {e}",
+                    self.name
+                );
+            }
+        }
+    }
+
+    /// Waits for the connections closed by [`Self::hand_over`] to be drained.
+    ///
+    /// Arguments
+    /// `drain_deadline` - the point in time after which the connections are no longer waited for
+    #[cfg(target_os = "linux")]
+    pub async fn drain_handoff(&mut self, drain_deadline: Instant) {
+        self.wait_idle(drain_deadline).await;
+    }
+
+    /// Takes the tunnel out of rotation.
+    ///
+    /// New sessions are redirected to another server, or refused if there is none. Clients of
+    /// existing sessions are told to reconnect to the other server, the connections still open
+    /// at the deadline are closed.
+    ///
+    /// Arguments
+    /// `redirect` - the connection string of the server clients are redirected to, the redirect
+    /// of the tunnel config is used if `None`
+    /// `deadline` - the time clients have to reconnect
+    pub fn drain(&mut self, redirect: Option<String>, deadline: Duration) -> Result<()> {
+        let redirect = redirect.or_else(|| self.tunnel_config.redirect.clone());
+
+        *self
+            .admission
+            .write()
+            .map_err(|_| anyhow!("Admission lock is poisoned"))? = match &redirect {
+            Some(address) => Admission::Redirect(address.clone()),
+            None => Admission::Refuse,
+        };
+
+        info!(
+            "Draining tunnel '{}', redirecting clients to {redirect:?} within {deadline:?}",
+            self.name
+        );
+
+        if let Some(task) = self.drain_task.take() {
+            task.abort();
+        }
+
+        if self.is_ok() {
+            self.drain_task = Some(tokio::spawn(Self::redirect_connections(
                 self.active_connections.clone(),
-                self.connection_config.clone(),
-                self.address_pool.clone(),
-                Arc::new(sender),
-                self.user_database.clone(),
-                endpoint,
+                redirect,
+                Instant::now() + deadline,
             )));
+        }
 
         Ok(())
     }
 
+    /// Redirects the clients of all connections until the deadline, then closes the connections
+    /// that are still open.
+    ///
+    /// Clients that do not support redirects are only told when the server goes away, they are
+    /// redirected when they reconnect if they support it.
+    ///
+    /// Arguments
+    /// `connections` - a map of connections and their associated client IP addresses
+    /// `redirect` - the connection string of the server clients are redirected to
+    /// `deadline` - the point in time at which remaining connections are closed
+    async fn redirect_connections(
+        connections: SharedConnections,
+        redirect: Option<String>,
+        deadline: Instant,
+    ) {
+        if redirect.is_none() {
+            let message = format!(
+                "Server is going down for maintenance in {:?}",
+                deadline.duration_since(Instant::now())
+            );
+
+            for connection in connections.iter() {
+                let _ = connection.send_notification(&message).await;
+            }
+        }
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+
+            if remaining.is_zero() || connections.is_empty() {
+                break;
+            }
+
+            if let Some(address) = &redirect {
+                for connection in connections.iter() {
+                    // The datagram may be lost, so the redirect is repeated until the deadline
+                    let _ = connection
+                        .send_redirect(Redirect {
+                            address: address.clone(),
+                            deadline: remaining,
+                        })
+                        .await;
+                }
+            }
+
+            sleep(DRAIN_REDIRECT_INTERVAL.min(remaining)).await;
+        }
+
+        let (code, reason): (u32, &[u8]) = match redirect {
+            Some(_) => (CLOSE_CODE_RESTART, b"Server draining, reconnect"),
+            None => (CLOSE_CODE_SHUTDOWN, b"Server down for maintenance"),
+        };
+
+        for connection in connections.iter() {
+            connection.close(VarInt::from_u32(code), reason);
+        }
+    }
+
+    /// Closes all connections with the given code and reason.
+    ///
+    /// Arguments
+    /// `code` - the application close code
+    /// `reason` - the reason sent to the clients
+    fn close_with(&self, code: u32, reason: &[u8]) {
+        if !self.endpoints.is_empty() {
+            info!("Closing all connections of tunnel '{}'", self.name);
+        }
+
+        for endpoint in &self.endpoints {
+            endpoint.close(VarInt::from_u32(code), reason);
+        }
+    }
+
+    /// Waits for the closed connections to be drained.
+    ///
+    /// Arguments
+    /// `drain_deadline` - the point in time after which the connections are no longer waited for
+    async fn wait_idle(&mut self, drain_deadline: Instant) {
+        for endpoint in std::mem::take(&mut self.endpoints) {
+            if timeout_at(drain_deadline, endpoint.wait_idle())
+                .await
+                .is_err()
+            {
+                warn!(
+                    "Connections of tunnel '{}' did not close before the drain deadline",
+                    self.name
+                );
+            }
+        }
+    }
+
     /// Stops the tasks for Rumble tunnel.
+    ///
+    /// Returns
+    /// `Err` with the first error that occurred in the tunnel tasks, once all tasks are stopped
     pub async fn stop(&mut self) -> Result<()> {
         let timeout = Duration::from_secs(1);
+        let mut first_error = None;
 
+        self.endpoints.clear();
+        if let Some(task) = self.drain_task.take() {
+            task.abort();
+        }
+        #[cfg(target_os = "linux")]
+        {
+            self.handoff = None;
+        }
         self.active_connections.clear();
         self.address_pool.reset();
 
         while let Some(task) = self.tasks.pop() {
             if let Some(Err(e)) = join_or_abort_task(task, timeout).await {
                 error!("An error occurred in tunnel '{}': {e}", self.name);
+                first_error.get_or_insert(e);
             }
         }
 
-        Ok(())
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
     }
 
     /// Checks whether Rumble tunnel is running.
@@ -223,6 +607,11 @@ impl RumbleTunnel {
                     .remove(&connection_addr)
                     .expect("Stale connection exists");
 
+                info!(
+                    "Connection statistics for client {connection_addr}: {}",
+                    connection.stats().snapshot()
+                );
+
                 connection.stop().await?;
                 address_pool.release_address(connection_addr);
             }
@@ -231,21 +620,108 @@ impl RumbleTunnel {
         }
     }
 
+    /// Sets up the TUN interface and binds the UDP sockets of the tunnel workers, adopting the
+    /// ones handed over by another server process if they match the tunnel config.
+    ///
+    /// Returns
+    /// `Interface` - the TUN interface
+    /// `Vec<UdpSocket>` - the bound UDP sockets, one for every worker
+    fn set_up_interface_and_socket(&mut self) -> Result<(Interface, Vec<std::net::UdpSocket>)> {
+        let bind_address = SocketAddr::V4(SocketAddrV4::new(
+            self.tunnel_config.bind_address,
+            self.tunnel_config.bind_port,
+        ));
+
+        #[cfg(target_os = "linux")]
+        if let Some(handoff) = self.adopted.take() {
+            let state = &handoff.state;
+
+            if state.address_tunnel == self.tunnel_config.address_tunnel
+                && state.address_mask == self.tunnel_config.address_mask
+                && state.offload == self.connection_config.offload
+                && handoff.sockets.len() == self.tunnel_config.workers as usize
+                && handoff
+                    .sockets
+                    .iter()
+                    .all(|socket| socket.local_addr().ok() == Some(bind_address))
+            {
+                info!(
+                    "Adopting interface '{}' and {} socket(s) {bind_address} of tunnel '{}'",
+                    state.interface_name,
+                    handoff.sockets.len(),
+                    self.name
+                );
+                let interface = adopt_interface(
+                    handoff.interface,
+                    state.interface_name.clone(),
+                    state.offload,
+                )?;
+
+                return Ok((interface, handoff.sockets));
+            }
+
+            warn!(
+                "Config of tunnel '{}' changed, not adopting the handed over interface and socket",
+                self.name
+            );
+        }
+
+        let interface_address = Ipv4Net::with_netmask(
+            self.tunnel_config.address_tunnel,
+            self.tunnel_config.address_mask,
+        )?
+        .into();
+        let interface = set_up_interface(
+            interface_address,
+            self.connection_config.mtu,
+            self.connection_config.offload,
+        )?;
+
+        let send_buffer_size = self.connection_config.send_buffer_size as usize;
+        let recv_buffer_size = self.connection_config.recv_buffer_size as usize;
+
+        #[cfg(target_os = "linux")]
+        if self.tunnel_config.workers > 1 {
+            let sockets = bind_reuse_port_sockets(
+                bind_address,
+                self.tunnel_config.workers as usize,
+                send_buffer_size,
+                recv_buffer_size,
+            )?;
+            attach_steering_program(&sockets[0], self.tunnel_config.workers)?;
+
+            return Ok((interface, sockets));
+        }
+
+        let socket = bind_socket(bind_address, send_buffer_size, recv_buffer_size)?;
+
+        Ok((interface, vec![socket]))
+    }
+
     /// Creates a Quinn QUIC endpoint that clients can connect to.
     ///
     /// ### Arguments
     /// `quinn_config` - the Quinn server configuration to use
-    fn create_quinn_endpoint(&self, quinn_config: quinn::ServerConfig) -> Result<Endpoint> {
-        let socket = bind_socket(
-            SocketAddr::V4(SocketAddrV4::new(
-                self.tunnel_config.bind_address,
-                self.tunnel_config.bind_port,
-            )),
-            self.connection_config.send_buffer_size as usize,
-            self.connection_config.recv_buffer_size as usize,
-        )?;
+    /// `socket` - the bound UDP socket
+    /// `worker` - the index of the worker driving the endpoint
+    fn create_quinn_endpoint(
+        &self,
+        quinn_config: quinn::ServerConfig,
+        socket: std::net::UdpSocket,
+        worker: u8,
+    ) -> Result<Endpoint> {
+        #[allow(unused_mut)]
+        let mut endpoint_config = self.connection_config.as_endpoint_config()?;
+
+        #[cfg(target_os = "linux")]
+        if self.tunnel_config.workers > 1 {
+            endpoint_config
+                .cid_generator(move || Box::new(WorkerConnectionIdGenerator::new(worker)));
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        let _ = worker;
 
-        let endpoint_config = self.connection_config.as_endpoint_config()?;
         let endpoint = Endpoint::new(
             endpoint_config,
             Some(quinn_config),
@@ -261,65 +737,72 @@ impl RumbleTunnel {
     /// Arguments
     /// `tun_read` - the read half of the TUN interface
     /// `active_connections` - a map of connections and their associated client IP addresses
-    /// `buffer_size` - the size of the buffer to use when reading from the TUN interface
     async fn process_outbound_traffic(
-        mut tun_read: ReadHalf<AsyncDevice>,
+        mut tun_read: InterfaceReader,
         active_connections: Arc<DashMap<IpAddr, RumbleConnection>>,
-        buffer_size: usize,
     ) -> Result<()> {
         debug!("Started outbound traffic task (interface -> QUIC tunnel)");
 
-        loop {
-            let buf = read_from_interface(&mut tun_read, buffer_size).await?;
-
-            let headers = match PacketHeaders::from_ip_slice(&buf) {
-                Ok(headers) => headers,
-                Err(e) => {
-                    warn!("Failed to parse IP packet: {e}");
-                    continue;
-                }
-            };
+        let mut batch = Vec::with_capacity(INTERFACE_BATCH_SIZE);
 
-            let ip_header = match headers.ip {
-                Some(ip_header) => ip_header,
-                None => {
-                    warn!("Received a packet with invalid IP header");
-                    continue;
-                }
-            };
-
-            let dest_addr: IpAddr = match ip_header {
-                IpHeader::Version4(header, _) => header.destination.into(),
-                IpHeader::Version6(header, _) => header.destination.into(),
-            };
-            debug!("Destination address for packet: {dest_addr}");
-
-            let connection = match active_connections.get(&dest_addr) {
-                Some(connection) => connection,
-                None => continue,
-            };
-            debug!("Found connection for IP {dest_addr}");
-
-            let max_datagram_size = connection.max_datagram_size().ok_or_else(|| {
-                anyhow!(
-                    "Client {} failed to provide maximum datagram size",
-                    connection.remote_address()
-                )
-            })?;
+        loop {
+            tun_read.read_batch(&mut batch).await?;
 
-            if buf.len() > max_datagram_size {
-                warn!(
-                    "Dropping packet of size {} due to maximum datagram size being {}",
-                    buf.len(),
-                    max_datagram_size
-                );
-                continue;
+            for buf in batch.drain(..) {
+                Self::route_packet(&active_connections, buf)?;
             }
+        }
+    }
 
-            debug!("Quinn MTU: {max_datagram_size}");
+    /// Sends a packet read from TUN to the client it is addressed to
+    ///
+    /// Arguments
+    /// `active_connections` - a map of connections and their associated client IP addresses
+    /// `buf` - the packet to be sent
+    fn route_packet(
+        active_connections: &DashMap<IpAddr, RumbleConnection>,
+        buf: Bytes,
+    ) -> Result<()> {
+        let headers = match PacketHeaders::from_ip_slice(&buf) {
+            Ok(headers) => headers,
+            Err(e) => {
+                warn!("Failed to parse IP packet: {e}");
+                return Ok(());
+            }
+        };
 
-            connection.send_datagram(buf).await?;
+        let ip_header = match headers.ip {
+            Some(ip_header) => ip_header,
+            None => {
+                warn!("Received a packet with invalid IP header");
+                return Ok(());
+            }
+        };
+
+        let dest_addr: IpAddr = match ip_header {
+            IpHeader::Version4(header, _) => header.destination.into(),
+            IpHeader::Version6(header, _) => header.destination.into(),
+        };
+        debug!("Destination address for packet: {dest_addr}");
+
+        // The sender is cloned out of the map, no shard lock is held while sending
+        let packet_sender = match active_connections.get(&dest_addr) {
+            Some(connection) => connection.packet_sender(),
+            None => return Ok(()),
+        };
+        debug!("Found connection for IP {dest_addr}");
+
+        // A single client failing must not stop the traffic of all other clients
+        match packet_sender {
+            Some(packet_sender) => {
+                if let Err(e) = packet_sender.send(buf) {
+                    debug!("Failed to send packet to {dest_addr}: {e}");
+                }
+            }
+            None => debug!("Dropping packet for {dest_addr}, the connection is not started"),
         }
+
+        Ok(())
     }
 
     /// Reads data from the QUIC connection and sends it to TUN
@@ -328,16 +811,27 @@ impl RumbleTunnel {
     /// `tun_write` - the write half of TUN
     /// `write_queue_receiver` - the channel for sending data to TUN
     async fn process_inbound_traffic(
-        mut tun_write: WriteHalf<AsyncDevice>,
+        mut tun_write: InterfaceWriter,
         mut write_queue_receiver: UnboundedReceiver<Bytes>,
     ) -> Result<()> {
         debug!("Started inbound traffic task (QUIC tunnel -> interface)");
 
+        let mut batch = Vec::with_capacity(INTERFACE_BATCH_SIZE);
+
         while let Some(buf) = write_queue_receiver.recv().await {
-            debug!("Sent {} bytes to tunnel", buf.len());
-            write_to_interface(&mut tun_write, buf).await?;
+            batch.push(buf);
+
+            while batch.len() < INTERFACE_BATCH_SIZE {
+                match write_queue_receiver.try_recv() {
+                    Ok(buf) => batch.push(buf),
+                    Err(_) => break,
+                }
+            }
+
+            debug!("Sent {} packets to tunnel", batch.len());
+            tun_write.write_batch(&mut batch).await?;
         }
 
         Ok(())
     }
-}
\ No newline at end of file
+}
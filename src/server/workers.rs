@@ -0,0 +1,184 @@
+use anyhow::{Context, Result};
+use quinn_proto::{ConnectionId, ConnectionIdGenerator};
+use rand::RngCore;
+use std::io;
+use std::net::UdpSocket;
+use std::os::fd::AsRawFd;
+use std::time::Duration;
+
+/// Length of the connection IDs issued by workers
+const WORKER_CID_LEN: usize = 8;
+
+/// Offset of the first destination connection ID byte in a QUIC short header packet
+const SHORT_HEADER_CID_OFFSET: u32 = 1;
+
+/// Offset of the first destination connection ID byte in a QUIC long header packet (flags,
+/// version, connection ID length)
+const LONG_HEADER_CID_OFFSET: u32 = 6;
+
+/// Bit set in the first byte of QUIC long header packets
+const LONG_HEADER_FORM: u32 = 0x80;
+
+/// Generates connection IDs whose first byte is the index of the worker that owns the connection.
+///
+/// The remaining bytes are random. The worker index is visible to observers, but it is shared by
+/// all connections of a worker, so it does not link connection IDs of a single connection.
+pub struct WorkerConnectionIdGenerator {
+    worker: u8,
+}
+
+impl WorkerConnectionIdGenerator {
+    /// Creates a new connection ID generator.
+    ///
+    /// Arguments
+    /// `worker` - the index of the worker
+    pub fn new(worker: u8) -> Self {
+        Self { worker }
+    }
+}
+
+impl ConnectionIdGenerator for WorkerConnectionIdGenerator {
+    fn generate_cid(&mut self) -> ConnectionId {
+        let mut bytes = [0; WORKER_CID_LEN];
+        bytes[0] = self.worker;
+        rand::thread_rng().fill_bytes(&mut bytes[1..]);
+
+        ConnectionId::new(&bytes)
+    }
+
+    fn cid_len(&self) -> usize {
+        WORKER_CID_LEN
+    }
+
+    fn cid_lifetime(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Builds a classic BPF program that steers QUIC packets to the socket of the worker encoded in
+/// their destination connection ID.
+///
+/// Packets of established connections carry a connection ID issued by a worker, so they reach
+/// that worker even after the client migrated to another address. The first Initial packet of a
+/// connection carries a random connection ID, which picks a random worker.
+///
+/// Arguments
+/// `workers` - the number of workers
+///
+/// Returns
+/// `Vec<libc::sock_filter>` - the program, returning the index of the socket in the group
+pub fn steering_program(workers: u8) -> Vec<libc::sock_filter> {
+    let ldb = (libc::BPF_LD | libc::BPF_B | libc::BPF_ABS) as u16;
+
+    vec![
+        // A = first byte of the packet
+        statement(ldb, 0),
+        // long header packets have the connection ID at a different offset
+        jump(
+            (libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K) as u16,
+            LONG_HEADER_FORM,
+            2,
+            0,
+        ),
+        statement(ldb, SHORT_HEADER_CID_OFFSET),
+        statement((libc::BPF_JMP | libc::BPF_JA) as u16, 1),
+        statement(ldb, LONG_HEADER_CID_OFFSET),
+        // A = worker index % workers
+        statement(
+            (libc::BPF_ALU | libc::BPF_MOD | libc::BPF_K) as u16,
+            workers.max(1) as u32,
+        ),
+        statement((libc::BPF_RET | libc::BPF_A) as u16, 0),
+    ]
+}
+
+/// Creates a BPF statement
+fn statement(code: u16, k: u32) -> libc::sock_filter {
+    jump(code, k, 0, 0)
+}
+
+/// Creates a BPF jump, `jt` and `jf` are the offsets taken if the condition is true or false
+fn jump(code: u16, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter { code, jt, jf, k }
+}
+
+/// Attaches the steering program to the `SO_REUSEPORT` group of a socket.
+///
+/// The program returns the index of the socket in the order the sockets joined the group, so the
+/// sockets have to be bound in the order of the worker indices.
+///
+/// Arguments
+/// `socket` - a socket of the group
+/// `workers` - the number of workers
+pub fn attach_steering_program(socket: &UdpSocket, workers: u8) -> Result<()> {
+    let mut program = steering_program(workers);
+    let program = libc::sock_fprog {
+        len: u16::try_from(program.len())?,
+        filter: program.as_mut_ptr(),
+    };
+
+    // SAFETY: the program outlives the call, the kernel copies it
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_ATTACH_REUSEPORT_CBPF,
+            &program as *const libc::sock_fprog as *const libc::c_void,
+            std::mem::size_of::<libc::sock_fprog>() as libc::socklen_t,
+        )
+    };
+
+    if result < 0 {
+        return Err(io::Error::last_os_error()).context("SO_ATTACH_REUSEPORT_CBPF");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::server::workers::{attach_steering_program, WorkerConnectionIdGenerator};
+    use crate::utils::socket::bind_reuse_port_sockets;
+    use quinn_proto::ConnectionIdGenerator;
+    use std::net::{SocketAddr, UdpSocket};
+    use std::time::Duration;
+
+    #[test]
+    fn test_connection_ids_carry_worker() {
+        let mut generator = WorkerConnectionIdGenerator::new(3);
+        let first = generator.generate_cid();
+        let second = generator.generate_cid();
+
+        assert_eq!(first.len(), generator.cid_len());
+        assert_eq!(first[0], 3);
+        assert_eq!(second[0], 3);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_steering() {
+        let address: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let sockets = bind_reuse_port_sockets(address, 3, 1 << 16, 1 << 16).unwrap();
+        attach_steering_program(&sockets[0], 3).unwrap();
+
+        for socket in &sockets {
+            socket
+                .set_read_timeout(Some(Duration::from_secs(1)))
+                .unwrap();
+        }
+
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let target = sockets[0].local_addr().unwrap();
+        let mut buffer = [0; 64];
+
+        // short header packet with a connection ID issued by worker 2
+        let short_header = [0x40, 2, 0, 0, 0, 0, 0, 0, 0];
+        sender.send_to(&short_header, target).unwrap();
+        assert_eq!(sockets[2].recv(&mut buffer).unwrap(), short_header.len());
+
+        // long header packet with a connection ID issued by worker 1
+        let long_header = [0xc0, 0, 0, 0, 1, 8, 1, 0, 0, 0, 0, 0, 0, 0];
+        sender.send_to(&long_header, target).unwrap();
+        assert_eq!(sockets[1].recv(&mut buffer).unwrap(), long_header.len());
+    }
+}
@@ -0,0 +1,209 @@
+use crate::utils::clock::timestamp;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Traffic statistics of a single Rumble connection.
+#[derive(Debug, Default)]
+pub struct ConnectionStats {
+    mtu: AtomicU64,
+    oversized_packets_streamed: AtomicU64,
+    oversized_packets_dropped: AtomicU64,
+    packet_too_big_sent: AtomicU64,
+    fec_recovered_packets: AtomicU64,
+    path_migrations: AtomicU64,
+    compression_input_bytes: AtomicU64,
+    compression_output_bytes: AtomicU64,
+    rtt: AtomicU64,
+    last_latency_reply: AtomicU64,
+    last_datagram_received: AtomicU64,
+    last_tunnel_activity: AtomicU64,
+}
+
+impl ConnectionStats {
+    /// Records the current MTU of the tunnel
+    pub fn set_mtu(&self, mtu: u32) {
+        self.mtu.store(mtu as u64, Ordering::Relaxed);
+    }
+
+    /// Records an oversized packet that was sent over the packet stream
+    pub fn record_streamed_packet(&self) {
+        self.oversized_packets_streamed
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records an oversized packet that was dropped
+    pub fn record_dropped_packet(&self) {
+        self.oversized_packets_dropped
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records an ICMP "Packet Too Big"/"Fragmentation Needed" reply sent for an oversized packet
+    pub fn record_packet_too_big(&self) {
+        self.packet_too_big_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records datagrams recovered by forward error correction
+    pub fn record_fec_recovered(&self, count: u64) {
+        self.fec_recovered_packets
+            .fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Records a migration of the connection to a new network path
+    pub fn record_path_migration(&self) {
+        self.path_migrations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a packet sent with compression enabled
+    ///
+    /// Arguments
+    /// `input` - the size of the packet
+    /// `output` - the size of the packet as sent, equal to `input` if it was not compressed
+    pub fn record_compression(&self, input: usize, output: usize) {
+        self.compression_input_bytes
+            .fetch_add(input as u64, Ordering::Relaxed);
+        self.compression_output_bytes
+            .fetch_add(output as u64, Ordering::Relaxed);
+    }
+
+    /// Records the round trip time measured by a latency probe
+    pub fn record_latency_reply(&self, rtt: Duration) {
+        self.rtt.store(rtt.as_micros() as u64, Ordering::Relaxed);
+        self.last_latency_reply
+            .store(timestamp(), Ordering::Relaxed);
+    }
+
+    /// Records the reception of a datagram of any type
+    pub fn record_datagram_received(&self) {
+        self.last_datagram_received
+            .store(timestamp(), Ordering::Relaxed);
+    }
+
+    /// Records an IP packet sent or received through the tunnel
+    pub fn record_tunnel_activity(&self) {
+        self.last_tunnel_activity
+            .store(timestamp(), Ordering::Relaxed);
+    }
+
+    /// Returns the timestamp of the last latency reply, `0` if none was received
+    pub fn last_latency_reply(&self) -> u64 {
+        self.last_latency_reply.load(Ordering::Relaxed)
+    }
+
+    /// Returns the timestamp of the last received datagram, `0` if none was received
+    pub fn last_datagram_received(&self) -> u64 {
+        self.last_datagram_received.load(Ordering::Relaxed)
+    }
+
+    /// Returns the timestamp of the last tunnel packet, `0` if none was sent or received
+    pub fn last_tunnel_activity(&self) -> u64 {
+        self.last_tunnel_activity.load(Ordering::Relaxed)
+    }
+
+    /// Returns a point-in-time copy of the statistics
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            mtu: self.mtu.load(Ordering::Relaxed),
+            oversized_packets_streamed: self.oversized_packets_streamed.load(Ordering::Relaxed),
+            oversized_packets_dropped: self.oversized_packets_dropped.load(Ordering::Relaxed),
+            packet_too_big_sent: self.packet_too_big_sent.load(Ordering::Relaxed),
+            fec_recovered_packets: self.fec_recovered_packets.load(Ordering::Relaxed),
+            path_migrations: self.path_migrations.load(Ordering::Relaxed),
+            compression_input_bytes: self.compression_input_bytes.load(Ordering::Relaxed),
+            compression_output_bytes: self.compression_output_bytes.load(Ordering::Relaxed),
+            rtt: Duration::from_micros(self.rtt.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Point-in-time copy of the statistics of a Rumble connection.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StatsSnapshot {
+    /// Current MTU of the tunnel
+    pub mtu: u64,
+    /// Number of packets too large for a datagram that were sent over the packet stream
+    pub oversized_packets_streamed: u64,
+    /// Number of packets too large for a datagram that were dropped
+    pub oversized_packets_dropped: u64,
+    /// Number of ICMP "Packet Too Big"/"Fragmentation Needed" replies sent for oversized packets
+    pub packet_too_big_sent: u64,
+    /// Number of lost datagrams recovered by forward error correction
+    pub fec_recovered_packets: u64,
+    /// Number of migrations of the connection to a new network path
+    pub path_migrations: u64,
+    /// Number of bytes of packets sent with compression enabled, before compression
+    pub compression_input_bytes: u64,
+    /// Number of bytes of packets sent with compression enabled, after compression
+    pub compression_output_bytes: u64,
+    /// Round trip time measured by the last latency probe
+    pub rtt: Duration,
+}
+
+impl StatsSnapshot {
+    /// Returns the ratio of the packet sizes before and after compression, `1.0` if no packets
+    /// were compressed
+    pub fn compression_ratio(&self) -> f64 {
+        if self.compression_output_bytes == 0 {
+            return 1.0;
+        }
+
+        self.compression_input_bytes as f64 / self.compression_output_bytes as f64
+    }
+}
+
+impl Display for StatsSnapshot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "mtu: {}, oversized packets streamed: {}, oversized packets dropped: {}, packet too big replies: {}, fec recovered packets: {}, path migrations: {}, compression ratio: {:.2}, rtt: {:?}",
+            self.mtu,
+            self.oversized_packets_streamed,
+            self.oversized_packets_dropped,
+            self.packet_too_big_sent,
+            self.fec_recovered_packets,
+            self.path_migrations,
+            self.compression_ratio(),
+            self.rtt
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stats::{ConnectionStats, StatsSnapshot};
+    use std::time::Duration;
+
+    #[test]
+    fn test_snapshot() {
+        let stats = ConnectionStats::default();
+
+        stats.set_mtu(1380);
+        stats.record_streamed_packet();
+        stats.record_streamed_packet();
+        stats.record_dropped_packet();
+        stats.record_packet_too_big();
+        stats.record_fec_recovered(3);
+        stats.record_path_migration();
+        stats.record_compression(1000, 250);
+        stats.record_compression(500, 500);
+        stats.record_latency_reply(Duration::from_millis(20));
+
+        assert_eq!(
+            stats.snapshot(),
+            StatsSnapshot {
+                mtu: 1380,
+                oversized_packets_streamed: 2,
+                oversized_packets_dropped: 1,
+                packet_too_big_sent: 1,
+                fec_recovered_packets: 3,
+                path_migrations: 1,
+                compression_input_bytes: 1500,
+                compression_output_bytes: 750,
+                rtt: Duration::from_millis(20),
+            }
+        );
+        assert_eq!(stats.snapshot().compression_ratio(), 2.0);
+        assert_eq!(StatsSnapshot::default().compression_ratio(), 1.0);
+        assert_eq!(stats.last_tunnel_activity(), 0);
+    }
+}
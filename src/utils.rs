@@ -1,6 +1,32 @@
+pub mod buffer_pool;
+pub mod certificate_resolver;
 pub mod certificates;
+pub mod checksum;
 pub mod cli;
+pub mod clock;
+pub mod coalescing;
+pub mod compression;
+pub mod control;
+pub mod datagram;
+pub mod dns;
+pub mod fec;
+pub mod happy_eyeballs;
+pub mod icmp;
+#[cfg(unix)]
+pub mod ifreq;
 pub mod interface;
+pub mod liveness;
+pub mod mss;
+#[cfg(target_os = "linux")]
+pub mod netlink;
+pub mod offload;
+pub mod packet;
+pub mod packet_sender;
+pub mod packet_stream;
+pub mod pinning;
+pub mod qr;
+pub mod server_address;
+pub mod signal;
 pub mod socket;
 pub mod tasks;
-pub mod tracing;
\ No newline at end of file
+pub mod tracing;
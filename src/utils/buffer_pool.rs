@@ -0,0 +1,99 @@
+use bytes::{Bytes, BytesMut};
+
+/// Pool of packet buffers backed by a single reusable allocation.
+///
+/// Packets are read into the spare capacity of the pool and split off as `Bytes`. Once every
+/// packet split off from the pool has been dropped, the allocation is reclaimed and reused
+/// instead of allocating a new buffer for every packet.
+pub struct BufferPool {
+    buffer: BytesMut,
+    packet_size: usize,
+}
+
+impl BufferPool {
+    /// Creates a new instance of a `BufferPool`
+    ///
+    /// Arguments
+    /// `packet_size` - the maximum size of a single packet
+    /// `packets` - the number of packets that fit into a single allocation
+    pub fn new(packet_size: usize, packets: usize) -> Self {
+        Self {
+            buffer: BytesMut::with_capacity(packet_size * packets.max(1)),
+            packet_size,
+        }
+    }
+
+    /// Returns the maximum size of a single packet
+    pub fn packet_size(&self) -> usize {
+        self.packet_size
+    }
+
+    /// Returns a buffer with room for at least one packet.
+    ///
+    /// The returned buffer is always empty, data written into it must be claimed with `split`.
+    pub fn buffer(&mut self) -> &mut BytesMut {
+        self.buffer.clear();
+        self.buffer.reserve(self.packet_size);
+
+        &mut self.buffer
+    }
+
+    /// Splits the data written into the buffer off as a packet.
+    ///
+    /// Returns
+    /// `Bytes` - the packet
+    pub fn split(&mut self) -> Bytes {
+        self.split_mut().freeze()
+    }
+
+    /// Splits the data written into the buffer off as a mutable packet.
+    ///
+    /// Returns
+    /// `BytesMut` - the packet
+    pub fn split_mut(&mut self) -> BytesMut {
+        self.buffer.split()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::buffer_pool::BufferPool;
+    use bytes::BufMut;
+
+    #[test]
+    fn test_buffer_pool_reuses_allocation() {
+        let mut pool = BufferPool::new(4, 2);
+
+        pool.buffer().put_slice(&[1, 2, 3, 4]);
+        let first = pool.split();
+        pool.buffer().put_slice(&[5, 6, 7, 8]);
+        let second = pool.split();
+
+        let address = first.as_ptr();
+        drop(first);
+        drop(second);
+
+        // Both packets were dropped, so the pool can hand out the same memory again
+        pool.buffer().put_slice(&[9, 10]);
+        let packet = pool.split();
+
+        assert_eq!(packet.as_ref(), &[9, 10]);
+        assert_eq!(packet.as_ptr(), address);
+    }
+
+    #[test]
+    fn test_buffer_pool_keeps_live_packets_intact() {
+        let mut pool = BufferPool::new(4, 2);
+
+        let packets: Vec<_> = (0..8_u8)
+            .map(|i| {
+                pool.buffer().put_slice(&[i; 4]);
+                pool.split()
+            })
+            .collect();
+
+        for (i, packet) in packets.iter().enumerate() {
+            assert_eq!(packet.as_ref(), &[i as u8; 4]);
+        }
+    }
+}
@@ -1,10 +1,17 @@
 use anyhow::anyhow;
 use anyhow::Result;
+use ring::digest::{digest, SHA256};
 use rustls::{Certificate, PrivateKey};
 use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
 
+/// Tag of the explicit version field of a certificate
+const DER_TAG_VERSION: u8 = 0xa0;
+
+/// Tag of a DER bit string
+const DER_TAG_BIT_STRING: u8 = 0x03;
+
 /// Loads certificates from a file.
 ///
 /// Arguments:
@@ -33,9 +40,158 @@ pub fn load_private_key_from_file(path: &Path) -> Result<PrivateKey> {
     let mut reader = BufReader::new(file);
 
     let private_key_bytes = rustls_pemfile::pkcs8_private_keys(&mut reader)?
-        .get(0)
+        .first()
         .ok_or_else(|| anyhow!("No private key found in the file: {path:?}"))?
         .clone();
 
     Ok(PrivateKey(private_key_bytes))
+}
+
+/// Loads the trusted certificate authorities of the operating system.
+///
+/// Returns
+/// `Vec<Certificate>` - List of loaded certificates
+pub fn load_system_certificates() -> Result<Vec<Certificate>> {
+    let certificates = rustls_native_certs::load_native_certs()
+        .map_err(|e| anyhow!("Failed to load the system trust store: {e}"))?;
+
+    Ok(certificates
+        .into_iter()
+        .map(|certificate| Certificate(certificate.0))
+        .collect())
+}
+
+/// Computes the SHA-256 hash of the public key (SubjectPublicKeyInfo) of a certificate, which
+/// stays the same when the certificate is renewed with the same key.
+///
+/// Arguments:
+/// `certificate` - DER encoded certificate
+///
+/// Returns
+/// `[u8; 32]` - SHA-256 hash of the DER encoded SubjectPublicKeyInfo
+pub fn spki_sha256(certificate: &Certificate) -> Result<[u8; 32]> {
+    let hash = digest(&SHA256, subject_public_key_info(certificate)?);
+
+    Ok(hash
+        .as_ref()
+        .try_into()
+        .expect("SHA-256 hashes have 32 bytes"))
+}
+
+/// Extracts the public key of a certificate.
+///
+/// Arguments:
+/// `certificate` - DER encoded certificate
+///
+/// Returns
+/// `&[u8]` - the contents of the subjectPublicKey bit string, e.g. an uncompressed EC point or a
+/// DER encoded RSAPublicKey
+pub fn subject_public_key(certificate: &Certificate) -> Result<&[u8]> {
+    let (_, spki, _) = read_der_element(subject_public_key_info(certificate)?)?;
+    let (_, _, rest) = read_der_element(spki)?;
+    let (tag, public_key, _) = read_der_element(rest)?;
+
+    match public_key.split_first() {
+        Some((0, public_key)) if tag == DER_TAG_BIT_STRING => Ok(public_key),
+        _ => Err(anyhow!("Invalid public key in certificate")),
+    }
+}
+
+/// Locates the SubjectPublicKeyInfo of a certificate.
+///
+/// Returns
+/// `&[u8]` - the DER encoded SubjectPublicKeyInfo
+fn subject_public_key_info(certificate: &Certificate) -> Result<&[u8]> {
+    let (_, certificate, _) = read_der_element(&certificate.0)?;
+    let (_, tbs_certificate, _) = read_der_element(certificate)?;
+
+    let (tag, _, mut rest) = read_der_element(tbs_certificate)?;
+
+    // The version is optional, the serial number follows otherwise
+    if tag == DER_TAG_VERSION {
+        (_, _, rest) = read_der_element(rest)?;
+    }
+
+    // Signature algorithm, issuer, validity and subject precede the public key
+    for _ in 0..4 {
+        (_, _, rest) = read_der_element(rest)?;
+    }
+
+    let spki_len = rest.len() - read_der_element(rest)?.2.len();
+
+    Ok(&rest[..spki_len])
+}
+
+/// Reads a DER element.
+///
+/// Returns
+/// `u8` - the tag of the element
+/// `&[u8]` - the contents of the element
+/// `&[u8]` - the bytes following the element
+fn read_der_element(buffer: &[u8]) -> Result<(u8, &[u8], &[u8])> {
+    let truncated = || anyhow!("Truncated DER element in certificate");
+    let tag = *buffer.first().ok_or_else(truncated)?;
+    let first_len = *buffer.get(1).ok_or_else(truncated)? as usize;
+
+    let (header_len, len) = match first_len {
+        len if len < 0x80 => (2, len),
+        long_form => {
+            let len_bytes = long_form & 0x7f;
+
+            if !(1..=4).contains(&len_bytes) {
+                return Err(anyhow!("Unsupported DER length in certificate"));
+            }
+
+            let len = buffer
+                .get(2..2 + len_bytes)
+                .ok_or_else(truncated)?
+                .iter()
+                .fold(0, |len, byte| len << 8 | *byte as usize);
+
+            (2 + len_bytes, len)
+        }
+    };
+
+    let contents = buffer
+        .get(header_len..header_len + len)
+        .ok_or_else(truncated)?;
+
+    Ok((tag, contents, &buffer[header_len + len..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::certificates::{spki_sha256, subject_public_key};
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use rustls::Certificate;
+
+    /// Self-signed P-256 certificate of vpn.example.com
+    const CERTIFICATE: &str = "\
+        MIIBiDCCAS+gAwIBAgIUWGou0uWOTQ6Qr2r6P/ERq9dJYN0wCgYIKoZIzj0EAwIw\
+        GjEYMBYGA1UEAwwPdnBuLmV4YW1wbGUuY29tMB4XDTI2MTAxODE1MzM1NloXDTI2\
+        MTExNzE1MzM1NlowGjEYMBYGA1UEAwwPdnBuLmV4YW1wbGUuY29tMFkwEwYHKoZI\
+        zj0CAQYIKoZIzj0DAQcDQgAEuRL8CnKUZzyKhbl+HBZ5kHyy+KA9pYrivGZb4v+c\
+        pd4Qse6bwWAR0MzC7mzf8LYmGwnfPkz6FTPVRn3707X+6aNTMFEwHQYDVR0OBBYE\
+        FAtLF4JncY8PlH8CfhIo3wtXLDSIMB8GA1UdIwQYMBaAFAtLF4JncY8PlH8CfhIo\
+        3wtXLDSIMA8GA1UdEwEB/wQFMAMBAf8wCgYIKoZIzj0EAwIDRwAwRAIgcaVB6xA1\
+        rcFA7yOgqtQNZ9sqPKqHwqVTmt2zLWiZYOECIGYn/tN8fbJXP/mY5jJkr/j6jIFf\
+        dGdhQICBfViwd3Zw";
+
+    #[test]
+    fn test_spki_sha256() {
+        let certificate = Certificate(STANDARD.decode(CERTIFICATE).unwrap());
+
+        // openssl x509 -pubkey -noout | openssl pkey -pubin -outform der | openssl dgst -sha256
+        assert_eq!(
+            STANDARD.encode(spki_sha256(&certificate).unwrap()),
+            "wQwFzxtrPk3uoDIsf3DyvFOTILai8FXGrTnxicw0pqw="
+        );
+
+        assert!(spki_sha256(&Certificate(certificate.0[..100].to_vec())).is_err());
+
+        let public_key = subject_public_key(&certificate).unwrap();
+        assert_eq!(public_key.len(), 65);
+        assert_eq!(public_key[0], 0x04);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,122 @@
+use std::net::IpAddr;
+
+/// Adds the given data to a ones' complement sum.
+///
+/// Arguments
+/// `data` - the data to be summed up
+/// `initial` - the sum to add the data to
+///
+/// Returns
+/// `u32` - the unfolded ones' complement sum
+pub fn sum(data: &[u8], initial: u32) -> u32 {
+    let mut chunks = data.chunks_exact(2);
+    let mut sum = initial as u64;
+
+    for chunk in chunks.by_ref() {
+        sum += u16::from_be_bytes([chunk[0], chunk[1]]) as u64;
+    }
+
+    if let [last] = chunks.remainder() {
+        sum += u16::from_be_bytes([*last, 0]) as u64;
+    }
+
+    fold(sum)
+}
+
+/// Computes the ones' complement sum of the TCP/UDP pseudo header.
+///
+/// Arguments
+/// `source` - the source address
+/// `destination` - the destination address
+/// `protocol` - the transport protocol number
+/// `length` - the length of the transport header and payload
+///
+/// Returns
+/// `u32` - the unfolded ones' complement sum
+pub fn pseudo_header_sum(source: IpAddr, destination: IpAddr, protocol: u8, length: u32) -> u32 {
+    let sum = match (source, destination) {
+        (IpAddr::V4(source), IpAddr::V4(destination)) => {
+            self::sum(&destination.octets(), self::sum(&source.octets(), 0))
+        }
+        (IpAddr::V6(source), IpAddr::V6(destination)) => {
+            self::sum(&destination.octets(), self::sum(&source.octets(), 0))
+        }
+        _ => unreachable!("Source and destination addresses have the same IP version"),
+    };
+
+    fold(sum as u64 + protocol as u64 + (length >> 16) as u64 + (length & 0xffff) as u64)
+}
+
+/// Folds a ones' complement sum into 16 bits and complements it.
+///
+/// Arguments
+/// `sum` - the unfolded ones' complement sum
+///
+/// Returns
+/// `u16` - the checksum
+pub fn finish(sum: u32) -> u16 {
+    !(fold(sum as u64) as u16)
+}
+
+/// Computes the checksum of the given data.
+///
+/// Arguments
+/// `data` - the data to compute the checksum of
+///
+/// Returns
+/// `u16` - the checksum
+pub fn checksum(data: &[u8]) -> u16 {
+    finish(sum(data, 0))
+}
+
+/// Incrementally updates a checksum after a 16-bit word of the checksummed data changed (RFC 1624).
+///
+/// Arguments
+/// `checksum` - the current checksum
+/// `old` - the previous value of the word
+/// `new` - the new value of the word
+///
+/// Returns
+/// `u16` - the updated checksum
+pub fn update(checksum: u16, old: u16, new: u16) -> u16 {
+    finish(!checksum as u32 + !old as u32 + new as u32)
+}
+
+#[inline]
+fn fold(mut sum: u64) -> u32 {
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    sum as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::checksum::{checksum, update};
+
+    #[test]
+    fn test_checksum() {
+        // IPv4 header with the checksum field zeroed
+        let header = [
+            0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00, 0xc0, 0xa8,
+            0x00, 0x01, 0xc0, 0xa8, 0x00, 0xc7,
+        ];
+
+        assert_eq!(checksum(&header), 0xb861);
+    }
+
+    #[test]
+    fn test_update() {
+        let mut header = [
+            0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00, 0xc0, 0xa8,
+            0x00, 0x01, 0xc0, 0xa8, 0x00, 0xc7,
+        ];
+        let original = checksum(&header);
+
+        // Decrement the TTL
+        header[8] = 0x3f;
+
+        assert_eq!(update(original, 0x4011, 0x3f11), checksum(&header));
+    }
+}
@@ -8,4 +8,41 @@ pub struct Args {
     pub config_path: PathBuf,
     #[arg(long, default_value = "RUMBLE_")]
     pub env_prefix: String,
-}
\ No newline at end of file
+}
+
+#[derive(Parser)]
+#[command(name = "rumble-client")]
+pub struct ClientArgs {
+    #[command(flatten)]
+    pub args: Args,
+    /// Writes the config from a profile, a `rumble://` URI or a file containing one, instead of
+    /// connecting
+    #[arg(long)]
+    pub import: Option<String>,
+    /// Overwrites an existing config when importing a profile
+    #[arg(long, requires = "import")]
+    pub force: bool,
+}
+
+#[derive(Parser)]
+#[command(name = "rumble-server")]
+pub struct ServerArgs {
+    #[command(flatten)]
+    pub args: Args,
+    /// Prints the profile of a user of this tunnel instead of running the server
+    #[arg(long, requires = "user", requires = "servers")]
+    pub export: Option<String>,
+    /// The user whose profile is exported
+    #[arg(long, requires = "export")]
+    pub user: Option<String>,
+    /// Public connection strings of the server to put into the exported profile, the first one
+    /// is preferred
+    #[arg(long = "server", requires = "export")]
+    pub servers: Vec<String>,
+    /// Embeds the certificate of the server into the exported profile instead of pinning its key
+    #[arg(long, requires = "export")]
+    pub embed_certificate: bool,
+    /// Prints the exported profile as a QR code as well
+    #[arg(long, requires = "export")]
+    pub qr: bool,
+}
@@ -0,0 +1,38 @@
+use once_cell::sync::Lazy;
+use std::time::{Duration, Instant};
+
+/// Origin of the timestamps, set when the first timestamp is taken
+static TIMESTAMP_ORIGIN: Lazy<Instant> = Lazy::new(Instant::now);
+
+/// Returns a monotonic timestamp in microseconds.
+///
+/// Timestamps are only meaningful within the same process, which makes them suitable for atomics
+/// and for probes that the peer echoes back unchanged.
+pub fn timestamp() -> u64 {
+    TIMESTAMP_ORIGIN.elapsed().as_micros() as u64
+}
+
+/// Returns the time elapsed since the given timestamp.
+///
+/// Arguments
+/// `timestamp` - a timestamp returned by `timestamp()`
+pub fn elapsed_since(timestamp: u64) -> Duration {
+    TIMESTAMP_ORIGIN
+        .elapsed()
+        .saturating_sub(Duration::from_micros(timestamp))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::clock::{elapsed_since, timestamp};
+    use std::time::Duration;
+
+    #[test]
+    fn test_elapsed_since() {
+        let start = timestamp();
+        std::thread::sleep(Duration::from_millis(5));
+
+        assert!(elapsed_since(start) >= Duration::from_millis(5));
+        assert!(timestamp() > start);
+    }
+}
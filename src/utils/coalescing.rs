@@ -0,0 +1,223 @@
+use crate::utils::datagram::DatagramTransmitter;
+use anyhow::{anyhow, Result};
+use bytes::{BufMut, Bytes, BytesMut};
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::time::{timeout_at, Instant};
+
+/// First byte of a datagram carrying multiple packets.
+///
+/// IP packets always start with the IP version, so a zero byte never begins a regular packet.
+pub const COALESCED_DATAGRAM_MARKER: u8 = 0;
+
+/// Size of the length prefix of every packet in a coalesced datagram
+const PACKET_LENGTH_LEN: usize = 2;
+
+/// Bundles multiple small packets into a single datagram.
+///
+/// A single pending packet is sent as is, multiple packets are sent as a coalesced datagram
+/// consisting of the marker byte followed by the packets, each prefixed with its length as a
+/// big-endian `u16`.
+#[derive(Debug, Default)]
+pub struct Coalescer {
+    pending: Vec<Bytes>,
+    coalesced_size: usize,
+}
+
+impl Coalescer {
+    /// Adds a packet to the pending datagram.
+    ///
+    /// If the packet does not fit into the pending datagram, the pending datagram is returned
+    /// and a new one is started with the packet.
+    ///
+    /// Arguments
+    /// `packet` - the packet to be added
+    /// `max_datagram_size` - the maximum size of a datagram
+    ///
+    /// Returns
+    /// `Option<Bytes>` - the datagram to be sent before the packet, if any
+    pub fn push(&mut self, packet: Bytes, max_datagram_size: usize) -> Option<Bytes> {
+        let packet_size = PACKET_LENGTH_LEN + packet.len();
+
+        let flushed =
+            if !self.pending.is_empty() && self.coalesced_size + packet_size > max_datagram_size {
+                self.flush()
+            } else {
+                None
+            };
+
+        if self.pending.is_empty() {
+            self.coalesced_size = 1;
+        }
+
+        self.coalesced_size += packet_size;
+        self.pending.push(packet);
+
+        flushed
+    }
+
+    /// Takes the pending datagram.
+    ///
+    /// Returns
+    /// `Option<Bytes>` - the pending datagram, if there are any pending packets
+    pub fn flush(&mut self) -> Option<Bytes> {
+        if self.pending.len() <= 1 {
+            return self.pending.pop();
+        }
+
+        let mut datagram = BytesMut::with_capacity(self.coalesced_size);
+        datagram.put_u8(COALESCED_DATAGRAM_MARKER);
+
+        for packet in self.pending.drain(..) {
+            datagram.put_u16(packet.len() as u16);
+            datagram.put_slice(&packet);
+        }
+
+        Some(datagram.freeze())
+    }
+
+    /// Checks whether there are no pending packets
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+/// Splits a received datagram into the packets it carries.
+///
+/// Arguments
+/// `datagram` - the received datagram
+/// `packets` - the list to append the packets to
+pub fn split_datagram(datagram: Bytes, packets: &mut Vec<Bytes>) -> Result<()> {
+    if datagram.first() != Some(&COALESCED_DATAGRAM_MARKER) {
+        packets.push(datagram);
+        return Ok(());
+    }
+
+    let mut offset = 1;
+
+    while offset < datagram.len() {
+        let start = offset + PACKET_LENGTH_LEN;
+
+        if start > datagram.len() {
+            return Err(anyhow!("Received a truncated coalesced datagram"));
+        }
+
+        let length = u16::from_be_bytes([datagram[offset], datagram[offset + 1]]) as usize;
+
+        if start + length > datagram.len() {
+            return Err(anyhow!("Received a truncated coalesced datagram"));
+        }
+
+        packets.push(datagram.slice(start..start + length));
+        offset = start + length;
+    }
+
+    Ok(())
+}
+
+/// Sends queued packets as coalesced datagrams.
+///
+/// Packets are collected until the datagram is full or the flush deadline, measured from the
+/// first packet of the datagram, passes.
+///
+/// Arguments
+/// `transmitter` - the transmitter of datagrams to the peer
+/// `packet_queue` - the queue of packets to be sent
+/// `flush_deadline` - the longest time a packet may wait for other packets
+pub async fn send_coalesced_datagrams(
+    transmitter: DatagramTransmitter,
+    mut packet_queue: UnboundedReceiver<Bytes>,
+    flush_deadline: Duration,
+) -> Result<()> {
+    let mut coalescer = Coalescer::default();
+
+    while let Some(packet) = packet_queue.recv().await {
+        let flush_at = Instant::now() + flush_deadline;
+        let mut next_packet = Some(packet);
+
+        while let Some(packet) = next_packet.take() {
+            let max_datagram_size = transmitter
+                .max_datagram_size()
+                .ok_or_else(|| anyhow!("The peer does not support datagram transfer"))?;
+
+            if let Some(datagram) = coalescer.push(packet, max_datagram_size) {
+                transmitter.send(datagram)?;
+            }
+
+            next_packet = timeout_at(flush_at, packet_queue.recv())
+                .await
+                .ok()
+                .flatten();
+        }
+
+        if let Some(datagram) = coalescer.flush() {
+            transmitter.send(datagram)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::coalescing::{split_datagram, Coalescer, COALESCED_DATAGRAM_MARKER};
+    use bytes::Bytes;
+
+    #[test]
+    fn test_coalesce_and_split() {
+        let packets = vec![
+            Bytes::from_static(&[0x45, 1, 2]),
+            Bytes::from_static(&[0x45, 3]),
+            Bytes::from_static(&[0x60, 4, 5, 6]),
+        ];
+        let mut coalescer = Coalescer::default();
+
+        for packet in packets.iter().cloned() {
+            assert!(coalescer.push(packet, 1200).is_none());
+        }
+
+        let datagram = coalescer.flush().unwrap();
+        assert!(coalescer.is_empty());
+        assert_eq!(datagram[0], COALESCED_DATAGRAM_MARKER);
+        assert_eq!(datagram.len(), 1 + 3 * 2 + 9);
+
+        let mut split = Vec::new();
+        split_datagram(datagram, &mut split).unwrap();
+
+        assert_eq!(split, packets);
+    }
+
+    #[test]
+    fn test_single_packet_is_not_coalesced() {
+        let packet = Bytes::from_static(&[0x45, 1, 2, 3]);
+        let mut coalescer = Coalescer::default();
+
+        coalescer.push(packet.clone(), 1200);
+
+        assert_eq!(coalescer.flush(), Some(packet.clone()));
+
+        let mut split = Vec::new();
+        split_datagram(packet.clone(), &mut split).unwrap();
+
+        assert_eq!(split, vec![packet]);
+    }
+
+    #[test]
+    fn test_full_datagram_is_flushed() {
+        let mut coalescer = Coalescer::default();
+
+        assert!(coalescer.push(Bytes::from(vec![0x45; 10]), 30).is_none());
+        assert!(coalescer.push(Bytes::from(vec![0x45; 10]), 30).is_none());
+
+        let flushed = coalescer.push(Bytes::from(vec![0x45; 10]), 30).unwrap();
+        assert_eq!(flushed.len(), 25);
+        assert_eq!(coalescer.flush().unwrap().len(), 10);
+    }
+
+    #[test]
+    fn test_truncated_datagram() {
+        let datagram = Bytes::from_static(&[COALESCED_DATAGRAM_MARKER, 0, 5, 0x45]);
+
+        assert!(split_datagram(datagram, &mut Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,165 @@
+use crate::constants::ZSTD_COMPRESSION_LEVEL;
+use anyhow::{anyhow, Result};
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+
+/// First byte of a compressed packet.
+///
+/// IP packets always start with the IP version, so the marker never begins a regular packet.
+pub const COMPRESSED_PACKET_MARKER: u8 = 3;
+
+/// Size of the header of a compressed packet (marker, original length)
+const COMPRESSED_PACKET_HEADER_LEN: usize = 3;
+
+/// Compression algorithm applied to tunnel packets
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// Packets are sent uncompressed
+    #[default]
+    None,
+    /// LZ4 block compression, fast with a moderate ratio
+    Lz4,
+    /// Zstandard compression, slower with a better ratio
+    Zstd,
+}
+
+impl Compression {
+    /// Compresses a packet.
+    ///
+    /// Arguments
+    /// `packet` - the IP packet to be compressed
+    ///
+    /// Returns
+    /// `Option<Bytes>` - the compressed packet, `None` if compression is disabled or the packet
+    /// does not shrink
+    pub fn compress(&self, packet: &[u8]) -> Result<Option<Bytes>> {
+        let Ok(length) = u16::try_from(packet.len()) else {
+            return Ok(None);
+        };
+
+        let compressed = match self {
+            Compression::None => return Ok(None),
+            Compression::Lz4 => lz4_flex::block::compress(packet),
+            Compression::Zstd => zstd::bulk::compress(packet, ZSTD_COMPRESSION_LEVEL)?,
+        };
+
+        if COMPRESSED_PACKET_HEADER_LEN + compressed.len() >= packet.len() {
+            return Ok(None);
+        }
+
+        let mut data = BytesMut::with_capacity(COMPRESSED_PACKET_HEADER_LEN + compressed.len());
+        data.put_u8(COMPRESSED_PACKET_MARKER);
+        data.put_u16(length);
+        data.put_slice(&compressed);
+
+        Ok(Some(data.freeze()))
+    }
+
+    /// Decompresses a received packet.
+    ///
+    /// Packets without the compression marker are passed through unchanged.
+    ///
+    /// Arguments
+    /// `packet` - the received packet
+    ///
+    /// Returns
+    /// `Bytes` - the decompressed IP packet
+    pub fn decompress(&self, packet: Bytes) -> Result<Bytes> {
+        if packet.first() != Some(&COMPRESSED_PACKET_MARKER) {
+            return Ok(packet);
+        }
+
+        if packet.len() < COMPRESSED_PACKET_HEADER_LEN {
+            return Err(anyhow!("Received a truncated compressed packet"));
+        }
+
+        let length = u16::from_be_bytes([packet[1], packet[2]]) as usize;
+        let compressed = &packet[COMPRESSED_PACKET_HEADER_LEN..];
+
+        let decompressed = match self {
+            Compression::None => {
+                return Err(anyhow!(
+                    "Received a compressed packet without negotiated compression"
+                ))
+            }
+            Compression::Lz4 => lz4_flex::block::decompress(compressed, length)?,
+            Compression::Zstd => zstd::bulk::decompress(compressed, length)?,
+        };
+
+        if decompressed.len() != length {
+            return Err(anyhow!(
+                "Decompressed packet has {} bytes instead of {length}",
+                decompressed.len()
+            ));
+        }
+
+        Ok(decompressed.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::compression::{Compression, COMPRESSED_PACKET_MARKER};
+    use bytes::Bytes;
+
+    fn compressible_packet() -> Bytes {
+        let mut packet = vec![0x45, 0, 0x04, 0];
+        packet.extend(b"temperature=21.5;pressure=1013;".repeat(32));
+
+        packet.into()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let packet = compressible_packet();
+
+        for compression in [Compression::Lz4, Compression::Zstd] {
+            let compressed = compression.compress(&packet).unwrap().unwrap();
+
+            assert_eq!(compressed[0], COMPRESSED_PACKET_MARKER);
+            assert!(compressed.len() < packet.len());
+            assert_eq!(compression.decompress(compressed).unwrap(), packet);
+        }
+    }
+
+    #[test]
+    fn test_incompressible_packet_is_skipped() {
+        let mut state = 0x2545_f491_u32;
+        let packet: Vec<u8> = (0..1200)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+
+        for compression in [Compression::None, Compression::Lz4, Compression::Zstd] {
+            assert!(compression.compress(&packet).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_uncompressed_packet_passes_through() {
+        let packet = compressible_packet();
+
+        assert_eq!(Compression::Lz4.decompress(packet.clone()).unwrap(), packet);
+    }
+
+    #[test]
+    fn test_invalid_packets() {
+        let compressed = Compression::Lz4
+            .compress(&compressible_packet())
+            .unwrap()
+            .unwrap();
+
+        assert!(Compression::None.decompress(compressed.clone()).is_err());
+        assert!(Compression::Lz4
+            .decompress(compressed.slice(..compressed.len() / 2))
+            .is_err());
+        assert!(Compression::Lz4
+            .decompress(Bytes::from_static(&[COMPRESSED_PACKET_MARKER, 0]))
+            .is_err());
+    }
+}
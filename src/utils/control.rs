@@ -0,0 +1,298 @@
+use crate::stats::ConnectionStats;
+use crate::utils::clock::{elapsed_since, timestamp};
+use crate::utils::datagram::DatagramTransmitter;
+use anyhow::{anyhow, Result};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tracing::{debug, info, warn};
+
+const LATENCY_PROBE: u8 = 1;
+const LATENCY_REPLY: u8 = 2;
+const MTU_PROBE: u8 = 3;
+const MTU_PROBE_ACK: u8 = 4;
+const KEEPALIVE: u8 = 5;
+const NOTIFICATION: u8 = 6;
+const REDIRECT: u8 = 7;
+
+/// Size of an encoded MTU probe without padding (kind, sequence, size)
+const MTU_PROBE_LEN: usize = 7;
+
+/// Tells a client to reconnect to another server.
+///
+/// Sent as a control frame to the clients of a draining server and as the answer to the
+/// authentication of a client the server does not accept sessions from.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Redirect {
+    /// The connection string of the server to reconnect to
+    pub address: String,
+    /// The time the client has to reconnect before its connection is closed
+    pub deadline: Duration,
+}
+
+/// In-band control frame carried in a datagram next to the IP packets.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ControlFrame {
+    /// Asks the peer to echo the frame back as a latency reply
+    LatencyProbe { sequence: u32, timestamp: u64 },
+    /// Echo of a latency probe
+    LatencyReply { sequence: u32, timestamp: u64 },
+    /// Probe padded to `size` bytes, acknowledged by the peer if it arrives
+    MtuProbe { sequence: u32, size: u16 },
+    /// Acknowledgement of an MTU probe
+    MtuProbeAck { sequence: u32, size: u16 },
+    /// Keeps the tunnel alive, the sequence number reveals lost keepalives
+    Keepalive { sequence: u32 },
+    /// Message from the server to be shown to the user
+    Notification { message: String },
+    /// Tells the client to reconnect to another server
+    Redirect(Redirect),
+}
+
+impl ControlFrame {
+    /// Creates a latency probe carrying the current timestamp.
+    ///
+    /// Arguments
+    /// `sequence` - the sequence number of the probe
+    pub fn latency_probe(sequence: u32) -> Self {
+        ControlFrame::LatencyProbe {
+            sequence,
+            timestamp: timestamp(),
+        }
+    }
+
+    /// Encodes the control frame.
+    ///
+    /// Arguments
+    /// `buffer` - the buffer to append the encoded frame to
+    pub fn encode(&self, buffer: &mut BytesMut) {
+        match self {
+            ControlFrame::LatencyProbe {
+                sequence,
+                timestamp,
+            } => {
+                buffer.put_u8(LATENCY_PROBE);
+                buffer.put_u32(*sequence);
+                buffer.put_u64(*timestamp);
+            }
+            ControlFrame::LatencyReply {
+                sequence,
+                timestamp,
+            } => {
+                buffer.put_u8(LATENCY_REPLY);
+                buffer.put_u32(*sequence);
+                buffer.put_u64(*timestamp);
+            }
+            ControlFrame::MtuProbe { sequence, size } => {
+                buffer.put_u8(MTU_PROBE);
+                buffer.put_u32(*sequence);
+                buffer.put_u16(*size);
+                buffer.put_bytes(0, (*size as usize).saturating_sub(MTU_PROBE_LEN));
+            }
+            ControlFrame::MtuProbeAck { sequence, size } => {
+                buffer.put_u8(MTU_PROBE_ACK);
+                buffer.put_u32(*sequence);
+                buffer.put_u16(*size);
+            }
+            ControlFrame::Keepalive { sequence } => {
+                buffer.put_u8(KEEPALIVE);
+                buffer.put_u32(*sequence);
+            }
+            ControlFrame::Notification { message } => {
+                buffer.put_u8(NOTIFICATION);
+                buffer.put_slice(message.as_bytes());
+            }
+            ControlFrame::Redirect(redirect) => {
+                buffer.put_u8(REDIRECT);
+                buffer.put_u32(redirect.deadline.as_millis().min(u32::MAX as u128) as u32);
+                buffer.put_slice(redirect.address.as_bytes());
+            }
+        }
+    }
+
+    /// Decodes a control frame.
+    ///
+    /// Arguments
+    /// `data` - the encoded frame
+    ///
+    /// Returns
+    /// `Option<ControlFrame>` - the control frame, `None` if the kind of the frame is unknown
+    pub fn decode(mut data: Bytes) -> Result<Option<Self>> {
+        if data.is_empty() {
+            return Err(anyhow!("Received an empty control frame"));
+        }
+
+        let kind = data.get_u8();
+        let required = match kind {
+            LATENCY_PROBE | LATENCY_REPLY => 12,
+            MTU_PROBE | MTU_PROBE_ACK => 6,
+            KEEPALIVE | REDIRECT => 4,
+            _ => 0,
+        };
+
+        if data.len() < required {
+            return Err(anyhow!("Received a truncated control frame of kind {kind}"));
+        }
+
+        let frame = match kind {
+            LATENCY_PROBE => ControlFrame::LatencyProbe {
+                sequence: data.get_u32(),
+                timestamp: data.get_u64(),
+            },
+            LATENCY_REPLY => ControlFrame::LatencyReply {
+                sequence: data.get_u32(),
+                timestamp: data.get_u64(),
+            },
+            MTU_PROBE => ControlFrame::MtuProbe {
+                sequence: data.get_u32(),
+                size: data.get_u16(),
+            },
+            MTU_PROBE_ACK => ControlFrame::MtuProbeAck {
+                sequence: data.get_u32(),
+                size: data.get_u16(),
+            },
+            KEEPALIVE => ControlFrame::Keepalive {
+                sequence: data.get_u32(),
+            },
+            NOTIFICATION => ControlFrame::Notification {
+                message: String::from_utf8(data.to_vec())?,
+            },
+            REDIRECT => ControlFrame::Redirect(Redirect {
+                deadline: Duration::from_millis(data.get_u32() as u64),
+                address: String::from_utf8(data.to_vec())?,
+            }),
+            _ => return Ok(None),
+        };
+
+        Ok(Some(frame))
+    }
+}
+
+/// Handles control frames received from the peer.
+///
+/// Probes are answered right away, latency replies are recorded in the statistics, redirects are
+/// passed on, everything else is logged.
+///
+/// Arguments
+/// `transmitter` - the transmitter of datagrams to the peer
+/// `control_frames` - the queue of received control frames
+/// `stats` - statistics of the connection
+/// `redirects` - the queue of redirects to be followed, `None` if redirects are not followed
+pub async fn process_control_frames(
+    transmitter: DatagramTransmitter,
+    mut control_frames: UnboundedReceiver<ControlFrame>,
+    stats: Arc<ConnectionStats>,
+    redirects: Option<UnboundedSender<Redirect>>,
+) -> Result<()> {
+    let remote_address = transmitter.connection().remote_address();
+
+    while let Some(frame) = control_frames.recv().await {
+        match frame {
+            ControlFrame::LatencyProbe {
+                sequence,
+                timestamp,
+            } => transmitter.send_control_frame(&ControlFrame::LatencyReply {
+                sequence,
+                timestamp,
+            })?,
+            ControlFrame::LatencyReply {
+                sequence,
+                timestamp,
+            } => {
+                let rtt = elapsed_since(timestamp);
+                debug!("Latency probe {sequence} to {remote_address:?} took {rtt:?}");
+                stats.record_latency_reply(rtt);
+            }
+            ControlFrame::MtuProbe { sequence, size } => {
+                transmitter.send_control_frame(&ControlFrame::MtuProbeAck { sequence, size })?
+            }
+            ControlFrame::MtuProbeAck { sequence, size } => {
+                debug!("MTU probe {sequence} of size {size} reached {remote_address:?}")
+            }
+            ControlFrame::Keepalive { sequence } => {
+                debug!("Received keepalive {sequence} from {remote_address:?}")
+            }
+            ControlFrame::Notification { message } => {
+                info!("Notification from {remote_address:?}: {message}")
+            }
+            ControlFrame::Redirect(redirect) => match &redirects {
+                Some(redirects) => {
+                    debug!(
+                        "Received redirect to {} from {remote_address:?}",
+                        redirect.address
+                    );
+                    // The session may already be following an earlier redirect
+                    let _ = redirects.send(redirect);
+                }
+                None => warn!("Ignoring redirect from {remote_address:?}"),
+            },
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::control::{ControlFrame, Redirect};
+    use bytes::{Bytes, BytesMut};
+    use std::time::Duration;
+
+    #[test]
+    fn test_encode_and_decode() {
+        let frames = vec![
+            ControlFrame::latency_probe(1),
+            ControlFrame::LatencyReply {
+                sequence: 2,
+                timestamp: 1234,
+            },
+            ControlFrame::MtuProbe {
+                sequence: 3,
+                size: 1200,
+            },
+            ControlFrame::MtuProbeAck {
+                sequence: 3,
+                size: 1200,
+            },
+            ControlFrame::Keepalive { sequence: 4 },
+            ControlFrame::Notification {
+                message: "Server restarting in 5 minutes".to_string(),
+            },
+            ControlFrame::Redirect(Redirect {
+                address: "rumble2.example.com:55555".to_string(),
+                deadline: Duration::from_secs(30),
+            }),
+        ];
+
+        for frame in frames {
+            let mut buffer = BytesMut::new();
+            frame.encode(&mut buffer);
+
+            assert_eq!(ControlFrame::decode(buffer.freeze()).unwrap(), Some(frame));
+        }
+    }
+
+    #[test]
+    fn test_mtu_probe_is_padded() {
+        let mut buffer = BytesMut::new();
+        ControlFrame::MtuProbe {
+            sequence: 1,
+            size: 1200,
+        }
+        .encode(&mut buffer);
+
+        assert_eq!(buffer.len(), 1200);
+    }
+
+    #[test]
+    fn test_unknown_and_truncated_frames() {
+        assert_eq!(
+            ControlFrame::decode(Bytes::from_static(&[200, 1, 2])).unwrap(),
+            None
+        );
+        assert!(ControlFrame::decode(Bytes::from_static(&[1, 0, 0])).is_err());
+        assert!(ControlFrame::decode(Bytes::new()).is_err());
+    }
+}
@@ -0,0 +1,382 @@
+use crate::auth::features::Features;
+use crate::config::ConnectionConfig;
+use crate::constants::{FEC_ADAPT_INTERVAL, FEC_FLUSH_INTERVAL};
+use crate::stats::ConnectionStats;
+use crate::utils::coalescing::split_datagram;
+use crate::utils::compression::Compression;
+use crate::utils::control::ControlFrame;
+use crate::utils::fec::{FecDecoder, FecEncoder, FEC_OVERHEAD};
+use anyhow::{anyhow, Result};
+use bytes::{BufMut, Bytes, BytesMut};
+use quinn::Connection;
+use std::sync::{Arc, Mutex};
+use tokio::time::sleep;
+use tracing::{debug, warn};
+
+/// Factor applied to the measured loss rate to get the adaptive FEC ratio.
+///
+/// XOR parity recovers a single datagram per group, so groups are kept at about half the
+/// average distance between losses.
+const FEC_LOSS_FACTOR: f64 = 2.0;
+
+/// Type of a datagram carrying IP packets
+pub const DATAGRAM_TYPE_DATA: u8 = 0;
+
+/// Type of a datagram carrying a control frame
+pub const DATAGRAM_TYPE_CONTROL: u8 = 1;
+
+/// Size of the type header of typed datagrams
+const DATAGRAM_TYPE_LEN: usize = 1;
+
+/// Sends datagrams to the peer, protecting them with FEC if negotiated.
+///
+/// If the negotiated protocol version supports typed framing, every datagram starts with a type
+/// byte, so control frames can be sent next to the IP packets.
+#[derive(Clone)]
+pub struct DatagramTransmitter {
+    connection: Arc<Connection>,
+    encoder: Option<Arc<Mutex<FecEncoder>>>,
+    typed_framing: bool,
+}
+
+impl DatagramTransmitter {
+    /// Creates a new datagram transmitter.
+    ///
+    /// Arguments
+    /// `connection` - the QUIC connection
+    /// `connection_config` - the connection config
+    /// `features` - the features negotiated with the peer
+    pub fn new(
+        connection: Arc<Connection>,
+        connection_config: &ConnectionConfig,
+        features: Features,
+    ) -> Self {
+        let encoder = features
+            .fec
+            .then(|| Arc::new(Mutex::new(FecEncoder::new(connection_config.fec_ratio))));
+
+        Self {
+            connection,
+            encoder,
+            typed_framing: features.typed_framing(),
+        }
+    }
+
+    /// Returns the underlying QUIC connection
+    pub fn connection(&self) -> &Arc<Connection> {
+        &self.connection
+    }
+
+    /// Returns the maximum size of a datagram that can be sent, excluding the type and FEC
+    /// headers.
+    ///
+    /// Returns
+    /// `Option<usize>` - the maximum datagram size, `None` if the peer does not support datagrams
+    pub fn max_datagram_size(&self) -> Option<usize> {
+        let mut max_datagram_size = self.connection.max_datagram_size()?;
+
+        if self.typed_framing {
+            max_datagram_size = max_datagram_size.saturating_sub(DATAGRAM_TYPE_LEN);
+        }
+
+        match self.encoder {
+            Some(_) => Some(max_datagram_size.saturating_sub(FEC_OVERHEAD)),
+            None => Some(max_datagram_size),
+        }
+    }
+
+    /// Sends a datagram to the peer.
+    ///
+    /// Arguments
+    /// `datagram` - the datagram to be sent
+    pub fn send(&self, datagram: Bytes) -> Result<()> {
+        let Some(encoder) = &self.encoder else {
+            return self.send_typed(DATAGRAM_TYPE_DATA, datagram);
+        };
+
+        let mut encoded = Vec::with_capacity(2);
+        encoder
+            .lock()
+            .map_err(|_| anyhow!("FEC encoder lock is poisoned"))?
+            .encode(&datagram, &mut encoded);
+
+        for datagram in encoded {
+            self.send_typed(DATAGRAM_TYPE_DATA, datagram)?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends a control frame to the peer.
+    ///
+    /// Arguments
+    /// `frame` - the control frame to be sent
+    pub fn send_control_frame(&self, frame: &ControlFrame) -> Result<()> {
+        if !self.typed_framing {
+            return Err(anyhow!(
+                "Control frames are not supported by {:?}",
+                self.connection.remote_address()
+            ));
+        }
+
+        let mut datagram = BytesMut::new();
+        frame.encode(&mut datagram);
+
+        self.send_typed(DATAGRAM_TYPE_CONTROL, datagram.freeze())
+    }
+
+    /// Sends a datagram, prefixed with its type if typed framing is negotiated.
+    ///
+    /// Arguments
+    /// `datagram_type` - the type of the datagram
+    /// `datagram` - the datagram to be sent
+    fn send_typed(&self, datagram_type: u8, datagram: Bytes) -> Result<()> {
+        if !self.typed_framing {
+            self.connection.send_datagram(datagram)?;
+            return Ok(());
+        }
+
+        let mut typed = BytesMut::with_capacity(DATAGRAM_TYPE_LEN + datagram.len());
+        typed.put_u8(datagram_type);
+        typed.put_slice(&datagram);
+        self.connection.send_datagram(typed.freeze())?;
+
+        Ok(())
+    }
+
+    /// Sends the parity of FEC groups that did not fill up within the flush interval.
+    ///
+    /// Completes when the connection is closed.
+    pub async fn flush_fec_groups(self) -> Result<()> {
+        let Some(encoder) = &self.encoder else {
+            return Ok(());
+        };
+
+        let mut encoded = Vec::with_capacity(1);
+
+        loop {
+            tokio::select! {
+                _ = self.connection.closed() => return Ok(()),
+                _ = sleep(FEC_FLUSH_INTERVAL) => (),
+            }
+
+            encoder
+                .lock()
+                .map_err(|_| anyhow!("FEC encoder lock is poisoned"))?
+                .flush(FEC_FLUSH_INTERVAL, &mut encoded);
+
+            for datagram in encoded.drain(..) {
+                self.send_typed(DATAGRAM_TYPE_DATA, datagram)?;
+            }
+        }
+    }
+
+    /// Adjusts the FEC ratio to the packet loss measured by the QUIC connection.
+    ///
+    /// The ratio never drops below the configured one. Completes when the connection is closed.
+    ///
+    /// Arguments
+    /// `base_ratio` - the configured FEC redundancy ratio
+    pub async fn adapt_fec_ratio(self, base_ratio: f64) -> Result<()> {
+        let Some(encoder) = &self.encoder else {
+            return Ok(());
+        };
+
+        let mut last_path = self.connection.stats().path;
+
+        loop {
+            tokio::select! {
+                _ = self.connection.closed() => return Ok(()),
+                _ = sleep(FEC_ADAPT_INTERVAL) => (),
+            }
+
+            let path = self.connection.stats().path;
+            let sent = path.sent_packets.saturating_sub(last_path.sent_packets);
+            let lost = path.lost_packets.saturating_sub(last_path.lost_packets);
+            last_path = path;
+
+            if sent == 0 {
+                continue;
+            }
+
+            let loss_rate = lost as f64 / sent as f64;
+            let ratio = (loss_rate * FEC_LOSS_FACTOR).clamp(base_ratio, 1.0);
+
+            let mut encoder = encoder
+                .lock()
+                .map_err(|_| anyhow!("FEC encoder lock is poisoned"))?;
+            let group_size = encoder.group_size();
+            encoder.set_ratio(ratio);
+
+            if encoder.group_size() != group_size {
+                debug!(
+                    "Changed FEC group size for {:?} from {group_size} to {} at a loss rate of {loss_rate:.4}",
+                    self.connection.remote_address(),
+                    encoder.group_size()
+                );
+            }
+        }
+    }
+}
+
+/// Turns received datagrams back into IP packets.
+///
+/// Control frames are separated from data first. Data is FEC decoded, recovering lost datagrams
+/// where possible, then split into the packets it carries, which are finally decompressed.
+pub struct DatagramReceiver {
+    decoder: FecDecoder,
+    datagrams: Vec<Bytes>,
+    compression: Compression,
+    typed_framing: bool,
+    stats: Arc<ConnectionStats>,
+}
+
+impl DatagramReceiver {
+    /// Creates a new datagram receiver.
+    ///
+    /// Arguments
+    /// `features` - the features negotiated with the peer
+    /// `stats` - statistics of the connection
+    pub fn new(features: Features, stats: Arc<ConnectionStats>) -> Self {
+        Self {
+            decoder: FecDecoder::default(),
+            datagrams: Vec::new(),
+            compression: features.compression,
+            typed_framing: features.typed_framing(),
+            stats,
+        }
+    }
+
+    /// Decodes a received datagram.
+    ///
+    /// Malformed datagrams are logged and dropped, so a single bad datagram from the peer does
+    /// not end the session.
+    ///
+    /// Arguments
+    /// `datagram` - the received datagram
+    /// `packets` - the list to append the packets to
+    ///
+    /// Returns
+    /// `Option<ControlFrame>` - the control frame carried by the datagram, if any
+    pub fn receive(&mut self, datagram: Bytes, packets: &mut Vec<Bytes>) -> Option<ControlFrame> {
+        self.stats.record_datagram_received();
+
+        let received = packets.len();
+
+        match self.decode(datagram, packets) {
+            Ok(frame) => frame,
+            Err(e) => {
+                warn!("Dropping malformed datagram: {e}");
+                packets.truncate(received);
+
+                None
+            }
+        }
+    }
+
+    /// Decodes a received datagram into packets or a control frame.
+    fn decode(
+        &mut self,
+        mut datagram: Bytes,
+        packets: &mut Vec<Bytes>,
+    ) -> Result<Option<ControlFrame>> {
+        if self.typed_framing {
+            let Some(&datagram_type) = datagram.first() else {
+                return Err(anyhow!("Received an empty datagram"));
+            };
+
+            datagram = datagram.slice(DATAGRAM_TYPE_LEN..);
+
+            match datagram_type {
+                DATAGRAM_TYPE_DATA => (),
+                DATAGRAM_TYPE_CONTROL => return ControlFrame::decode(datagram),
+                _ => {
+                    debug!("Ignoring datagram of unknown type {datagram_type}");
+                    return Ok(None);
+                }
+            }
+        }
+
+        let received = packets.len();
+        let recovered = self.decoder.decode(datagram, &mut self.datagrams)?;
+
+        if recovered > 0 {
+            self.stats.record_fec_recovered(recovered as u64);
+        }
+
+        for datagram in self.datagrams.drain(..) {
+            let start = packets.len();
+            split_datagram(datagram, packets)?;
+
+            for packet in packets[start..].iter_mut() {
+                *packet = self.compression.decompress(std::mem::take(packet))?;
+            }
+        }
+
+        if packets.len() > received {
+            self.stats.record_tunnel_activity();
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::features::{Features, TYPED_FRAMING_VERSION};
+    use crate::stats::ConnectionStats;
+    use crate::utils::control::ControlFrame;
+    use crate::utils::datagram::{DatagramReceiver, DATAGRAM_TYPE_CONTROL, DATAGRAM_TYPE_DATA};
+    use bytes::{BufMut, Bytes, BytesMut};
+    use std::sync::Arc;
+
+    fn typed_receiver() -> DatagramReceiver {
+        let features = Features {
+            protocol_version: TYPED_FRAMING_VERSION,
+            ..Features::default()
+        };
+
+        DatagramReceiver::new(features, Arc::new(ConnectionStats::default()))
+    }
+
+    #[test]
+    fn test_dispatch_on_type() {
+        let mut receiver = typed_receiver();
+        let mut packets = Vec::new();
+
+        let data = Bytes::from_static(&[DATAGRAM_TYPE_DATA, 0x45, 1, 2, 3]);
+        assert_eq!(receiver.receive(data, &mut packets), None);
+        assert_eq!(packets, vec![Bytes::from_static(&[0x45, 1, 2, 3])]);
+
+        let frame = ControlFrame::Keepalive { sequence: 7 };
+        let mut control = BytesMut::new();
+        control.put_u8(DATAGRAM_TYPE_CONTROL);
+        frame.encode(&mut control);
+        assert_eq!(
+            receiver.receive(control.freeze(), &mut packets),
+            Some(frame)
+        );
+
+        let unknown = Bytes::from_static(&[0xff, 0x45, 1]);
+        assert_eq!(receiver.receive(unknown, &mut packets), None);
+        assert_eq!(packets.len(), 1);
+
+        // Malformed datagrams are dropped without ending the session
+        let empty = Bytes::new();
+        assert_eq!(receiver.receive(empty, &mut packets), None);
+        let truncated = Bytes::from_static(&[DATAGRAM_TYPE_CONTROL]);
+        assert_eq!(receiver.receive(truncated, &mut packets), None);
+        assert_eq!(packets.len(), 1);
+    }
+
+    #[test]
+    fn test_untyped_datagrams() {
+        let mut receiver =
+            DatagramReceiver::new(Features::default(), Arc::new(ConnectionStats::default()));
+        let mut packets = Vec::new();
+        let packet = Bytes::from_static(&[0x45, 1, 2, 3]);
+
+        assert_eq!(receiver.receive(packet.clone(), &mut packets), None);
+        assert_eq!(packets, vec![packet]);
+    }
+}
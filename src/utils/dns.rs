@@ -0,0 +1,122 @@
+use crate::constants::DNS_TIMEOUT;
+use anyhow::Result;
+use hickory_resolver::error::ResolveErrorKind;
+use hickory_resolver::system_conf::read_system_conf;
+use hickory_resolver::TokioAsyncResolver;
+use rand::Rng;
+
+/// SRV record of a service, see RFC 2782
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SrvRecord {
+    /// Priority of the target, lower values are preferred
+    pub priority: u16,
+    /// Relative weight of targets with the same priority
+    pub weight: u16,
+    /// Port of the service on the target
+    pub port: u16,
+    /// Domain name of the target
+    pub target: String,
+}
+
+/// Looks up the SRV records of a service using the resolver config of the host.
+///
+/// Arguments
+/// `name` - the name of the service, e.g. `_rumble._udp.example.com`
+///
+/// Returns
+/// `Vec<SrvRecord>` - the records in the order they should be tried, empty if the service does
+/// not exist
+pub async fn lookup_srv(name: &str) -> Result<Vec<SrvRecord>> {
+    let (config, mut options) = read_system_conf()?;
+    options.timeout = DNS_TIMEOUT;
+
+    let resolver = TokioAsyncResolver::tokio(config, options);
+
+    let lookup = match resolver.srv_lookup(name).await {
+        Ok(lookup) => lookup,
+        Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+            return Ok(Vec::new())
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let records = lookup
+        .iter()
+        .map(|record| SrvRecord {
+            priority: record.priority(),
+            weight: record.weight(),
+            port: record.port(),
+            target: record.target().to_utf8().trim_end_matches('.').to_string(),
+        })
+        .collect::<Vec<_>>();
+
+    // A target of "." means the service is decidedly not available
+    if records.iter().any(|record| record.target.is_empty()) {
+        return Ok(Vec::new());
+    }
+
+    Ok(order_records(records))
+}
+
+/// Orders SRV records by priority, records of the same priority are shuffled according to their
+/// weights as described in RFC 2782.
+fn order_records(mut records: Vec<SrvRecord>) -> Vec<SrvRecord> {
+    let mut rng = rand::thread_rng();
+    let mut ordered = Vec::with_capacity(records.len());
+    records.sort_by_key(|record| record.priority);
+
+    while let Some(priority) = records.first().map(|record| record.priority) {
+        let count = records
+            .iter()
+            .take_while(|record| record.priority == priority)
+            .count();
+        let mut group: Vec<SrvRecord> = records.drain(..count).collect();
+
+        while !group.is_empty() {
+            let total: u32 = group.iter().map(|record| record.weight as u32).sum();
+            let mut pick = rng.gen_range(0..=total);
+
+            let index = group
+                .iter()
+                .position(|record| {
+                    let weight = record.weight as u32;
+
+                    if pick <= weight {
+                        return true;
+                    }
+
+                    pick -= weight;
+                    false
+                })
+                .unwrap_or(0);
+
+            ordered.push(group.remove(index));
+        }
+    }
+
+    ordered
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::dns::{order_records, SrvRecord};
+
+    #[test]
+    fn test_order_records() {
+        let record = |priority, weight, target: &str| SrvRecord {
+            priority,
+            weight,
+            port: 55555,
+            target: target.to_string(),
+        };
+
+        let ordered = order_records(vec![
+            record(20, 0, "c"),
+            record(10, 1, "a"),
+            record(10, 1, "b"),
+        ]);
+
+        let targets: Vec<&str> = ordered.iter().map(|r| r.target.as_str()).collect();
+        assert!(targets == ["a", "b", "c"] || targets == ["b", "a", "c"]);
+    }
+}
@@ -0,0 +1,515 @@
+use anyhow::{anyhow, Result};
+use bytes::{BufMut, Bytes, BytesMut};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// First byte of a datagram carrying FEC protected data.
+///
+/// IP packets always start with the IP version, so the marker never begins a regular packet.
+pub const FEC_DATA_MARKER: u8 = 1;
+
+/// First byte of a datagram carrying the XOR parity of an FEC group
+pub const FEC_PARITY_MARKER: u8 = 2;
+
+/// Size of the header of an FEC data datagram (marker, group, index)
+const FEC_DATA_HEADER_LEN: usize = 4;
+
+/// Size of the header of an FEC parity datagram (marker, group, group size, length parity)
+const FEC_PARITY_HEADER_LEN: usize = 6;
+
+/// Number of bytes the FEC headers take away from the maximum datagram size
+pub const FEC_OVERHEAD: usize = FEC_PARITY_HEADER_LEN;
+
+/// Largest number of datagrams protected by a single parity datagram
+pub const MAX_FEC_GROUP_SIZE: usize = 64;
+
+/// Number of recent groups the decoder keeps to recover lost datagrams
+const FEC_DECODER_GROUPS: usize = 16;
+
+/// Converts a redundancy ratio into the number of datagrams protected by one parity datagram.
+///
+/// Arguments
+/// `ratio` - the number of parity datagrams per data datagram
+///
+/// Returns
+/// `usize` - the group size
+pub fn group_size(ratio: f64) -> usize {
+    if ratio.is_nan() || ratio <= 0.0 {
+        return MAX_FEC_GROUP_SIZE;
+    }
+
+    ((1.0 / ratio).round() as usize).clamp(1, MAX_FEC_GROUP_SIZE)
+}
+
+/// XOR parity forward error correction encoder.
+///
+/// Datagrams are sent in groups, every group is followed by a parity datagram holding the XOR of
+/// the datagrams and their lengths. A single lost datagram per group can be recovered. Groups
+/// that do not fill up in time are flushed, so sparse traffic is protected as well.
+#[derive(Debug)]
+pub struct FecEncoder {
+    group: u16,
+    index: u8,
+    group_started: Option<Instant>,
+    current_group_size: usize,
+    next_group_size: usize,
+    parity: BytesMut,
+    length_parity: u16,
+}
+
+impl FecEncoder {
+    /// Creates a new FEC encoder.
+    ///
+    /// Arguments
+    /// `ratio` - the number of parity datagrams per data datagram
+    pub fn new(ratio: f64) -> Self {
+        Self {
+            group: 0,
+            index: 0,
+            group_started: None,
+            current_group_size: group_size(ratio),
+            next_group_size: group_size(ratio),
+            parity: BytesMut::new(),
+            length_parity: 0,
+        }
+    }
+
+    /// Changes the redundancy ratio, the change takes effect with the next group.
+    ///
+    /// Arguments
+    /// `ratio` - the number of parity datagrams per data datagram
+    pub fn set_ratio(&mut self, ratio: f64) {
+        self.next_group_size = group_size(ratio);
+    }
+
+    /// Returns the number of datagrams protected by one parity datagram, starting with the next
+    /// group
+    pub fn group_size(&self) -> usize {
+        self.next_group_size
+    }
+
+    /// Encodes a datagram, appending the datagrams to be sent.
+    ///
+    /// Arguments
+    /// `datagram` - the datagram to be protected
+    /// `encoded` - the list to append the data datagram and the parity datagram, if the group is
+    /// complete, to
+    pub fn encode(&mut self, datagram: &[u8], encoded: &mut Vec<Bytes>) {
+        let mut data = BytesMut::with_capacity(FEC_DATA_HEADER_LEN + datagram.len());
+        data.put_u8(FEC_DATA_MARKER);
+        data.put_u16(self.group);
+        data.put_u8(self.index);
+        data.put_slice(datagram);
+        encoded.push(data.freeze());
+
+        if self.parity.len() < datagram.len() {
+            self.parity.resize(datagram.len(), 0);
+        }
+
+        for (parity, byte) in self.parity.iter_mut().zip(datagram) {
+            *parity ^= byte;
+        }
+
+        self.length_parity ^= datagram.len() as u16;
+        self.index += 1;
+        self.group_started.get_or_insert_with(Instant::now);
+
+        if self.index as usize >= self.current_group_size {
+            self.finish_group(encoded);
+        }
+    }
+
+    /// Finishes the current group early if it is incomplete and older than the given age.
+    ///
+    /// Arguments
+    /// `max_age` - the time since the first datagram of the group after which it is flushed
+    /// `encoded` - the list to append the parity datagram to
+    pub fn flush(&mut self, max_age: Duration, encoded: &mut Vec<Bytes>) {
+        if self
+            .group_started
+            .is_some_and(|started| started.elapsed() >= max_age)
+        {
+            self.finish_group(encoded);
+        }
+    }
+
+    /// Appends the parity datagram of the current group and starts the next group.
+    ///
+    /// Arguments
+    /// `encoded` - the list to append the parity datagram to
+    fn finish_group(&mut self, encoded: &mut Vec<Bytes>) {
+        // The parity carries the actual number of datagrams, so partial groups decode as well
+        let mut parity = BytesMut::with_capacity(FEC_PARITY_HEADER_LEN + self.parity.len());
+        parity.put_u8(FEC_PARITY_MARKER);
+        parity.put_u16(self.group);
+        parity.put_u8(self.index);
+        parity.put_u16(self.length_parity);
+        parity.put_slice(&self.parity);
+        encoded.push(parity.freeze());
+
+        self.group = self.group.wrapping_add(1);
+        self.index = 0;
+        self.group_started = None;
+        self.current_group_size = self.next_group_size;
+        self.parity.clear();
+        self.length_parity = 0;
+    }
+}
+
+/// Datagrams of an FEC group received so far.
+#[derive(Debug)]
+struct FecGroup {
+    group: u16,
+    datagrams: Vec<Option<Bytes>>,
+    received: usize,
+    parity: Option<(usize, u16, Bytes)>,
+    complete: bool,
+}
+
+impl FecGroup {
+    fn new(group: u16) -> Self {
+        Self {
+            group,
+            datagrams: Vec::new(),
+            received: 0,
+            parity: None,
+            complete: false,
+        }
+    }
+
+    /// Recovers the missing datagram if all but one datagram and the parity were received.
+    ///
+    /// The recovered datagram takes the slot of the missing one, so the original is dropped if it
+    /// arrives late.
+    fn recover(&mut self) -> Option<Bytes> {
+        let (size, length_parity, parity) = self.parity.clone()?;
+
+        if self.complete || self.received + 1 != size {
+            self.complete |= self.received == size;
+            return None;
+        }
+
+        let mut recovered = BytesMut::from(parity.as_ref());
+        let mut length = length_parity;
+
+        for datagram in self.datagrams.iter().flatten() {
+            for (byte, other) in recovered.iter_mut().zip(datagram.iter()) {
+                *byte ^= other;
+            }
+
+            length ^= datagram.len() as u16;
+        }
+
+        self.complete = true;
+
+        if length as usize > recovered.len() {
+            return None;
+        }
+
+        recovered.truncate(length as usize);
+        let recovered = recovered.freeze();
+
+        if self.datagrams.len() < size {
+            self.datagrams.resize(size, None);
+        }
+
+        if let Some(slot) = self
+            .datagrams
+            .iter_mut()
+            .find(|datagram| datagram.is_none())
+        {
+            *slot = Some(recovered.clone());
+            self.received += 1;
+        }
+
+        Some(recovered)
+    }
+}
+
+/// XOR parity forward error correction decoder.
+#[derive(Debug, Default)]
+pub struct FecDecoder {
+    groups: VecDeque<FecGroup>,
+}
+
+impl FecDecoder {
+    /// Decodes a received datagram.
+    ///
+    /// Datagrams without FEC headers are passed through unchanged.
+    ///
+    /// Arguments
+    /// `datagram` - the received datagram
+    /// `decoded` - the list to append the received and recovered datagrams to
+    ///
+    /// Returns
+    /// `usize` - the number of recovered datagrams
+    pub fn decode(&mut self, datagram: Bytes, decoded: &mut Vec<Bytes>) -> Result<usize> {
+        match datagram.first() {
+            Some(&FEC_DATA_MARKER) => {
+                if datagram.len() < FEC_DATA_HEADER_LEN {
+                    return Err(anyhow!("Received a truncated FEC datagram"));
+                }
+
+                let group = u16::from_be_bytes([datagram[1], datagram[2]]);
+                let index = datagram[3] as usize;
+                let data = datagram.slice(FEC_DATA_HEADER_LEN..);
+                let fec_group = self.group(group);
+
+                if fec_group.datagrams.len() <= index {
+                    fec_group.datagrams.resize(index + 1, None);
+                }
+
+                if fec_group.datagrams[index].is_some() {
+                    return Ok(0);
+                }
+
+                fec_group.datagrams[index] = Some(data.clone());
+                fec_group.received += 1;
+                decoded.push(data);
+
+                Ok(self.recover(group, decoded))
+            }
+            Some(&FEC_PARITY_MARKER) => {
+                if datagram.len() < FEC_PARITY_HEADER_LEN {
+                    return Err(anyhow!("Received a truncated FEC parity datagram"));
+                }
+
+                let group = u16::from_be_bytes([datagram[1], datagram[2]]);
+                let size = datagram[3] as usize;
+                let length_parity = u16::from_be_bytes([datagram[4], datagram[5]]);
+                let parity = datagram.slice(FEC_PARITY_HEADER_LEN..);
+
+                self.group(group)
+                    .parity
+                    .get_or_insert((size, length_parity, parity));
+
+                Ok(self.recover(group, decoded))
+            }
+            _ => {
+                decoded.push(datagram);
+
+                Ok(0)
+            }
+        }
+    }
+
+    /// Returns the group with the given ID, starting a new one if it is not known.
+    fn group(&mut self, group: u16) -> &mut FecGroup {
+        let position = match self
+            .groups
+            .iter()
+            .position(|fec_group| fec_group.group == group)
+        {
+            Some(position) => position,
+            None => {
+                if self.groups.len() >= FEC_DECODER_GROUPS {
+                    self.groups.pop_front();
+                }
+
+                self.groups.push_back(FecGroup::new(group));
+                self.groups.len() - 1
+            }
+        };
+
+        &mut self.groups[position]
+    }
+
+    /// Tries to recover the missing datagram of the given group.
+    fn recover(&mut self, group: u16, decoded: &mut Vec<Bytes>) -> usize {
+        match self.group(group).recover() {
+            Some(recovered) => {
+                decoded.push(recovered);
+                1
+            }
+            None => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::fec::{group_size, FecDecoder, FecEncoder, MAX_FEC_GROUP_SIZE};
+    use bytes::Bytes;
+    use std::time::Duration;
+
+    /// In-memory link dropping datagrams according to a deterministic pseudo-random sequence.
+    struct LossyLink {
+        state: u64,
+        loss_rate: f64,
+    }
+
+    impl LossyLink {
+        fn new(seed: u64, loss_rate: f64) -> Self {
+            Self {
+                state: seed,
+                loss_rate,
+            }
+        }
+
+        fn delivers(&mut self) -> bool {
+            // xorshift64
+            self.state ^= self.state << 13;
+            self.state ^= self.state >> 7;
+            self.state ^= self.state << 17;
+
+            (self.state % 10_000) as f64 / 10_000.0 >= self.loss_rate
+        }
+    }
+
+    fn datagram(index: usize) -> Bytes {
+        let length = 40 + index * 37 % 1200;
+
+        (0..length)
+            .map(|byte| (byte * 31 + index) as u8)
+            .collect::<Vec<u8>>()
+            .into()
+    }
+
+    /// Sends datagrams over the link, returning the sent and the delivered datagrams.
+    fn transfer(
+        count: usize,
+        ratio: f64,
+        mut drop: impl FnMut(usize) -> bool,
+    ) -> (Vec<Bytes>, Vec<Bytes>, usize) {
+        let mut encoder = FecEncoder::new(ratio);
+        let mut decoder = FecDecoder::default();
+        let mut sent = Vec::new();
+        let mut encoded = Vec::new();
+        let mut delivered = Vec::new();
+        let mut recovered = 0;
+
+        for index in 0..count {
+            sent.push(datagram(index));
+            encoder.encode(&datagram(index), &mut encoded);
+        }
+
+        for (position, datagram) in encoded.into_iter().enumerate() {
+            if !drop(position) {
+                recovered += decoder.decode(datagram, &mut delivered).unwrap();
+            }
+        }
+
+        (sent, delivered, recovered)
+    }
+
+    fn sorted(mut datagrams: Vec<Bytes>) -> Vec<Bytes> {
+        datagrams.sort();
+        datagrams
+    }
+
+    #[test]
+    fn test_group_size() {
+        assert_eq!(group_size(0.25), 4);
+        assert_eq!(group_size(1.0), 1);
+        assert_eq!(group_size(0.0), MAX_FEC_GROUP_SIZE);
+    }
+
+    #[test]
+    fn test_lossless_link() {
+        let (sent, delivered, recovered) = transfer(100, 0.25, |_| false);
+
+        assert_eq!(delivered, sent);
+        assert_eq!(recovered, 0);
+    }
+
+    #[test]
+    fn test_single_loss_per_group_is_recovered() {
+        // Every group consists of 4 data datagrams followed by a parity datagram
+        let (sent, delivered, recovered) = transfer(100, 0.25, |position| position % 5 == 2);
+
+        assert_eq!(recovered, 25);
+        assert_eq!(sorted(delivered), sorted(sent));
+    }
+
+    #[test]
+    fn test_lost_parity() {
+        let (sent, delivered, recovered) = transfer(100, 0.25, |position| position % 5 == 4);
+
+        assert_eq!(recovered, 0);
+        assert_eq!(delivered, sent);
+    }
+
+    #[test]
+    fn test_multiple_losses_per_group() {
+        let (sent, delivered, recovered) =
+            transfer(100, 0.25, |position| position % 5 == 0 || position % 5 == 1);
+
+        assert_eq!(recovered, 0);
+        assert_eq!(delivered.len(), sent.len() - 50);
+        assert!(delivered.iter().all(|datagram| sent.contains(datagram)));
+    }
+
+    #[test]
+    fn test_late_datagram_after_recovery() {
+        let mut encoder = FecEncoder::new(0.25);
+        let mut decoder = FecDecoder::default();
+        let mut encoded = Vec::new();
+        let mut delivered = Vec::new();
+
+        for index in 0..4 {
+            encoder.encode(&datagram(index), &mut encoded);
+        }
+
+        // The third datagram arrives after the parity it was already recovered from
+        let late = encoded.remove(2);
+        let mut recovered = 0;
+
+        for datagram in encoded {
+            recovered += decoder.decode(datagram, &mut delivered).unwrap();
+        }
+
+        assert_eq!(recovered, 1);
+        assert_eq!(decoder.decode(late, &mut delivered).unwrap(), 0);
+        assert_eq!(sorted(delivered), sorted((0..4).map(datagram).collect()));
+    }
+
+    #[test]
+    fn test_flushed_partial_group_is_recovered() {
+        let mut encoder = FecEncoder::new(0.25);
+        let mut decoder = FecDecoder::default();
+        let mut encoded = Vec::new();
+        let mut delivered = Vec::new();
+
+        for index in 0..2 {
+            encoder.encode(&datagram(index), &mut encoded);
+        }
+
+        // Nothing is flushed before the group is old enough
+        encoder.flush(Duration::from_secs(60), &mut encoded);
+        assert_eq!(encoded.len(), 2);
+
+        encoder.flush(Duration::ZERO, &mut encoded);
+        assert_eq!(encoded.len(), 3);
+
+        // Flushing an empty group sends nothing
+        encoder.flush(Duration::ZERO, &mut encoded);
+        assert_eq!(encoded.len(), 3);
+
+        encoded.remove(1);
+        let mut recovered = 0;
+
+        for datagram in encoded {
+            recovered += decoder.decode(datagram, &mut delivered).unwrap();
+        }
+
+        assert_eq!(recovered, 1);
+        assert_eq!(sorted(delivered), sorted((0..2).map(datagram).collect()));
+    }
+
+    #[test]
+    fn test_random_loss() {
+        let mut link = LossyLink::new(0x2545_f491_4f6c_dd1d, 0.05);
+        let (sent, delivered, recovered) = transfer(2000, 0.2, |_| !link.delivers());
+
+        let mut plain_link = LossyLink::new(0x2545_f491_4f6c_dd1d, 0.05);
+        let lost_without_fec = (0..2000).filter(|_| !plain_link.delivers()).count();
+
+        assert!(recovered > 0);
+        assert!(sent.len() - delivered.len() < lost_without_fec);
+        assert!(delivered.iter().all(|datagram| sent.contains(datagram)));
+
+        let mut unique = sorted(delivered.clone());
+        unique.dedup();
+        assert_eq!(unique.len(), delivered.len());
+    }
+}
@@ -0,0 +1,262 @@
+use crate::utils::server_address::ServerAddress;
+use anyhow::{anyhow, Result};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tokio::time::{sleep, Instant};
+use tracing::{debug, info, warn};
+
+/// Resolved address of a server the client can connect to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Candidate {
+    /// The connection string the address was resolved from
+    pub connection_string: String,
+    /// The name the certificate of the server is verified against
+    pub server_name: String,
+    /// The resolved address
+    pub address: SocketAddr,
+}
+
+/// Resolves the addresses of the servers.
+///
+/// The candidates are ordered by server, the addresses of each server alternate between IPv6 and
+/// IPv4 so that a broken address family does not delay the connection much.
+///
+/// Arguments
+/// `servers` - the connection strings of the servers, in order of preference
+/// `server_name` - the name to verify the certificates against instead of the hosts of the
+/// connection strings
+///
+/// Returns
+/// `Vec<Candidate>` - the resolved candidates, servers that cannot be resolved are skipped
+pub async fn resolve_candidates(
+    servers: &[String],
+    server_name: Option<&str>,
+) -> Result<Vec<Candidate>> {
+    let mut candidates = Vec::new();
+
+    for server in servers {
+        let addresses = match server.parse::<ServerAddress>() {
+            Ok(server_address) => match server_address.resolve().await {
+                Ok(addresses) => addresses
+                    .into_iter()
+                    .map(|address| Candidate {
+                        connection_string: server.clone(),
+                        server_name: server_name
+                            .map(str::to_string)
+                            .unwrap_or_else(|| server_address.server_name()),
+                        address,
+                    })
+                    .collect::<Vec<_>>(),
+                Err(e) => {
+                    warn!("Failed to resolve '{server}': {e}");
+                    continue;
+                }
+            },
+            Err(e) => {
+                warn!("{e}");
+                continue;
+            }
+        };
+
+        candidates.extend(addresses);
+    }
+
+    if candidates.is_empty() {
+        return Err(anyhow!("None of the servers {servers:?} could be resolved"));
+    }
+
+    Ok(candidates)
+}
+
+/// Orders addresses so that IPv6 and IPv4 addresses alternate, starting with the family of the
+/// first address.
+///
+/// Arguments
+/// `addresses` - the addresses in the order they were resolved
+///
+/// Returns
+/// `Vec<SocketAddr>` - the reordered addresses
+pub fn interleave_families(addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let Some(first) = addresses.first() else {
+        return addresses;
+    };
+
+    let first_is_ipv6 = first.is_ipv6();
+    let (mut preferred, mut other): (Vec<_>, Vec<_>) = addresses
+        .into_iter()
+        .partition(|address| address.is_ipv6() == first_is_ipv6);
+
+    let mut interleaved = Vec::with_capacity(preferred.len() + other.len());
+    preferred.reverse();
+    other.reverse();
+
+    while !preferred.is_empty() || !other.is_empty() {
+        interleaved.extend(preferred.pop());
+        interleaved.extend(other.pop());
+    }
+
+    interleaved
+}
+
+/// Races connection attempts to the candidates.
+///
+/// The attempts are started in order, each one `delay` after the previous one or right after the
+/// previous one failed. The first attempt to succeed wins and the others are cancelled, so the
+/// server answering the fastest is picked among the attempts in flight.
+///
+/// Arguments
+/// `candidates` - the candidates in order of preference
+/// `delay` - the time to wait for an attempt before starting the next one
+/// `connect` - starts a connection attempt to a candidate
+///
+/// Returns
+/// `Candidate` - the candidate connected to
+/// `T` - the connection
+pub async fn race<T, F, Fut>(
+    candidates: Vec<Candidate>,
+    delay: Duration,
+    mut connect: F,
+) -> Result<(Candidate, T)>
+where
+    T: Send + 'static,
+    F: FnMut(Candidate) -> Fut,
+    Fut: Future<Output = Result<T>> + Send + 'static,
+{
+    let started = Instant::now();
+    let mut pending = candidates.into_iter();
+    let mut attempts = JoinSet::new();
+    let mut last_error = anyhow!("No server to connect to");
+
+    loop {
+        if attempts.is_empty() {
+            let Some(candidate) = pending.next() else {
+                return Err(last_error);
+            };
+
+            start_attempt(&mut attempts, candidate, &mut connect);
+        }
+
+        tokio::select! {
+            Some(result) = attempts.join_next() => match result? {
+                (candidate, Ok(connection)) => {
+                    info!(
+                        "Connected to {} ({}) after {:?}",
+                        candidate.connection_string,
+                        candidate.address,
+                        started.elapsed()
+                    );
+
+                    return Ok((candidate, connection));
+                }
+                (candidate, Err(e)) => {
+                    warn!(
+                        "Failed to connect to {} ({}): {e}",
+                        candidate.connection_string, candidate.address
+                    );
+                    last_error = e;
+
+                    if let Some(candidate) = pending.next() {
+                        start_attempt(&mut attempts, candidate, &mut connect);
+                    }
+                }
+            },
+            _ = sleep(delay), if pending.len() > 0 => {
+                if let Some(candidate) = pending.next() {
+                    start_attempt(&mut attempts, candidate, &mut connect);
+                }
+            }
+        }
+    }
+}
+
+/// Starts a connection attempt to a candidate.
+fn start_attempt<T, F, Fut>(
+    attempts: &mut JoinSet<(Candidate, Result<T>)>,
+    candidate: Candidate,
+    connect: &mut F,
+) where
+    T: Send + 'static,
+    F: FnMut(Candidate) -> Fut,
+    Fut: Future<Output = Result<T>> + Send + 'static,
+{
+    debug!(
+        "Connecting to {} ({})",
+        candidate.connection_string, candidate.address
+    );
+    let attempt = connect(candidate.clone());
+
+    attempts.spawn(async move { (candidate, attempt.await) });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::happy_eyeballs::{interleave_families, race, Candidate};
+    use anyhow::anyhow;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+    use tokio::time::sleep;
+
+    fn candidate(address: &str) -> Candidate {
+        Candidate {
+            connection_string: address.to_string(),
+            server_name: "localhost".to_string(),
+            address: address.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_interleave_families() {
+        let addresses: Vec<SocketAddr> =
+            ["[::1]:1", "[::2]:1", "[::3]:1", "10.0.0.1:1", "10.0.0.2:1"]
+                .iter()
+                .map(|address| address.parse().unwrap())
+                .collect();
+
+        let expected: Vec<SocketAddr> =
+            ["[::1]:1", "10.0.0.1:1", "[::2]:1", "10.0.0.2:1", "[::3]:1"]
+                .iter()
+                .map(|address| address.parse().unwrap())
+                .collect();
+
+        assert_eq!(interleave_families(addresses), expected);
+        assert!(interleave_families(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_race() {
+        let candidates = vec![
+            candidate("10.0.0.1:1"),
+            candidate("10.0.0.2:1"),
+            candidate("10.0.0.3:1"),
+        ];
+
+        // The first candidate fails, the second one is slower than the third one
+        let (winner, latency) = tokio_test::block_on(race(
+            candidates.clone(),
+            Duration::from_millis(10),
+            |candidate| async move {
+                match candidate.address.to_string().as_str() {
+                    "10.0.0.1:1" => Err(anyhow!("Connection refused")),
+                    "10.0.0.2:1" => {
+                        sleep(Duration::from_millis(500)).await;
+                        Ok(500)
+                    }
+                    _ => Ok(0),
+                }
+            },
+        ))
+        .unwrap();
+
+        assert_eq!(winner, candidates[2]);
+        assert_eq!(latency, 0);
+
+        let result: anyhow::Result<(Candidate, ())> =
+            tokio_test::block_on(race(candidates, Duration::from_millis(10), |_| async {
+                Err(anyhow!("Connection refused"))
+            }));
+
+        assert!(result.is_err());
+    }
+}
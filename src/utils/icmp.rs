@@ -0,0 +1,203 @@
+use crate::utils::packet::{addresses, read_u16, IP_PROTOCOL_ICMP, IP_PROTOCOL_ICMPV6};
+use bytes::Bytes;
+use etherparse::icmpv4::DestUnreachableHeader;
+use etherparse::{Icmpv4Type, Icmpv6Type, PacketBuilder};
+use std::net::IpAddr;
+
+/// Minimum MTU of an IPv4 link
+const IPV4_MIN_MTU: usize = 68;
+
+/// Minimum MTU of an IPv6 link
+const IPV6_MIN_MTU: usize = 1280;
+
+/// Maximum size of an ICMPv4 error message
+const ICMPV4_MAX_ERROR_SIZE: usize = 576;
+
+/// Size of the IPv4 and ICMPv4 headers of an error message
+const ICMPV4_HEADERS_LEN: usize = 28;
+
+/// Size of the IPv6 and ICMPv6 headers of an error message
+const ICMPV6_HEADERS_LEN: usize = 48;
+
+/// TTL/hop limit of the generated messages
+const ICMP_TTL: u8 = 64;
+
+/// ICMPv4 error message types that must not be answered with another error
+const ICMPV4_ERROR_TYPES: [u8; 5] = [3, 4, 5, 11, 12];
+
+/// Builds an ICMP reply telling the sender of a packet that it exceeds the usable MTU.
+///
+/// IPv4 packets are answered with "Fragmentation Needed" (type 3, code 4), IPv6 packets with
+/// "Packet Too Big" (type 2). The reply is addressed to the sender of the original packet and
+/// appears to come from its destination, since the reply is injected into the local TUN
+/// interface and a local source address would be rejected as martian.
+///
+/// No reply is built for IPv4 packets without the DF bit set, non-initial fragments and ICMP
+/// error messages.
+///
+/// Arguments
+/// `packet` - the oversized IP packet
+/// `mtu` - the largest packet size that can currently be sent
+///
+/// Returns
+/// `Option<Bytes>` - the ICMP reply, if the packet should be answered with one
+pub fn packet_too_big(packet: &[u8], mtu: usize) -> Option<Bytes> {
+    let mut reply = Vec::new();
+
+    let version = packet.first()?;
+
+    match version >> 4 {
+        4 => {
+            let header_len = (version & 0x0f) as usize * 4;
+
+            if packet.len() < header_len.max(20) {
+                return None;
+            }
+
+            let fragment = read_u16(packet, 6);
+            let dont_fragment = fragment & 0x4000 != 0;
+            let initial_fragment = fragment & 0x1fff == 0;
+
+            if !dont_fragment
+                || !initial_fragment
+                || (packet[9] == IP_PROTOCOL_ICMP
+                    && packet
+                        .get(header_len)
+                        .is_none_or(|icmp_type| ICMPV4_ERROR_TYPES.contains(icmp_type)))
+            {
+                return None;
+            }
+
+            let (IpAddr::V4(source), IpAddr::V4(destination)) = addresses(packet, true).ok()?
+            else {
+                return None;
+            };
+
+            if source.is_unspecified() || source.is_broadcast() || source.is_multicast() {
+                return None;
+            }
+
+            let next_hop_mtu = mtu.clamp(IPV4_MIN_MTU, u16::MAX as usize) as u16;
+            let quoted = &packet[..packet.len().min(ICMPV4_MAX_ERROR_SIZE - ICMPV4_HEADERS_LEN)];
+
+            PacketBuilder::ipv4(destination.octets(), source.octets(), ICMP_TTL)
+                .icmpv4(Icmpv4Type::DestinationUnreachable(
+                    DestUnreachableHeader::FragmentationNeeded { next_hop_mtu },
+                ))
+                .write(&mut reply, quoted)
+                .ok()?;
+        }
+        6 => {
+            if packet.len() < 40
+                || (packet[6] == IP_PROTOCOL_ICMPV6
+                    && packet.get(40).is_none_or(|icmp_type| *icmp_type < 128))
+            {
+                return None;
+            }
+
+            let (IpAddr::V6(source), IpAddr::V6(destination)) = addresses(packet, false).ok()?
+            else {
+                return None;
+            };
+
+            if source.is_unspecified() || source.is_multicast() {
+                return None;
+            }
+
+            let mtu = mtu.clamp(IPV6_MIN_MTU, u32::MAX as usize) as u32;
+            let quoted = &packet[..packet.len().min(IPV6_MIN_MTU - ICMPV6_HEADERS_LEN)];
+
+            PacketBuilder::ipv6(destination.octets(), source.octets(), ICMP_TTL)
+                .icmpv6(Icmpv6Type::PacketTooBig { mtu })
+                .write(&mut reply, quoted)
+                .ok()?;
+        }
+        _ => return None,
+    }
+
+    Some(reply.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::icmp::packet_too_big;
+    use etherparse::icmpv4::DestUnreachableHeader;
+    use etherparse::{
+        Icmpv4Type, Icmpv6Type, InternetSlice, PacketBuilder, SlicedPacket, TransportSlice,
+    };
+
+    fn ipv4_packet(dont_fragment: bool, size: usize) -> Vec<u8> {
+        let builder = PacketBuilder::ipv4([10, 0, 0, 2], [1, 1, 1, 1], 64).udp(5000, 53);
+        let payload = vec![0_u8; size - builder.size(0)];
+
+        let mut packet = Vec::new();
+        builder.write(&mut packet, &payload).unwrap();
+
+        if dont_fragment {
+            packet[6] |= 0x40;
+        } else {
+            packet[6] &= !0x40;
+        }
+
+        packet[10..12].fill(0);
+        let checksum = crate::utils::checksum::checksum(&packet[..20]);
+        packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+
+        packet
+    }
+
+    #[test]
+    fn test_fragmentation_needed() {
+        let packet = ipv4_packet(true, 1500);
+        let reply = packet_too_big(&packet, 1380).unwrap();
+        let sliced = SlicedPacket::from_ip(&reply).unwrap();
+
+        match sliced.ip {
+            Some(InternetSlice::Ipv4(header, _)) => {
+                assert_eq!(header.source(), [1, 1, 1, 1]);
+                assert_eq!(header.destination(), [10, 0, 0, 2]);
+            }
+            _ => panic!("Expected an IPv4 reply"),
+        }
+
+        match sliced.transport {
+            Some(TransportSlice::Icmpv4(icmp)) => assert_eq!(
+                icmp.icmp_type(),
+                Icmpv4Type::DestinationUnreachable(DestUnreachableHeader::FragmentationNeeded {
+                    next_hop_mtu: 1380
+                })
+            ),
+            _ => panic!("Expected an ICMPv4 reply"),
+        }
+
+        assert!(reply.len() <= 576);
+        assert_eq!(reply[28..], packet[..reply.len() - 28]);
+    }
+
+    #[test]
+    fn test_no_reply_without_dont_fragment() {
+        assert!(packet_too_big(&ipv4_packet(false, 1500), 1380).is_none());
+    }
+
+    #[test]
+    fn test_packet_too_big() {
+        let builder = PacketBuilder::ipv6([1; 16], [2; 16], 64).tcp(40000, 443, 1, 65535);
+        let mut packet = Vec::new();
+        builder.write(&mut packet, &[0; 1400]).unwrap();
+
+        let reply = packet_too_big(&packet, 1300).unwrap();
+        let sliced = SlicedPacket::from_ip(&reply).unwrap();
+
+        match sliced.transport {
+            Some(TransportSlice::Icmpv6(icmp)) => {
+                assert_eq!(icmp.icmp_type(), Icmpv6Type::PacketTooBig { mtu: 1300 })
+            }
+            _ => panic!("Expected an ICMPv6 reply"),
+        }
+
+        assert_eq!(reply.len(), 1280);
+
+        // ICMP errors are never answered with another error
+        assert!(packet_too_big(&reply, 1000).is_none());
+    }
+}
@@ -0,0 +1,71 @@
+use anyhow::{anyhow, Context as _, Result};
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+/// Opens a socket that interface requests can be issued on.
+///
+/// Returns
+/// `OwnedFd` - the control socket
+pub fn control_socket() -> Result<OwnedFd> {
+    // SAFETY: a socket is created and its file descriptor is immediately owned
+    let control = unsafe {
+        let fd = check(libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0))?;
+        OwnedFd::from_raw_fd(fd)
+    };
+
+    Ok(control)
+}
+
+/// Creates an interface request for the interface with the given name.
+///
+/// Arguments
+/// `name` - the name of the interface
+///
+/// Returns
+/// `libc::ifreq` - the zeroed interface request carrying the name
+pub fn interface_request(name: &str) -> Result<libc::ifreq> {
+    if name.len() >= libc::IFNAMSIZ {
+        return Err(anyhow!("Interface name '{name}' is too long"));
+    }
+
+    // SAFETY: `ifreq` is a plain C struct for which all zeroes is a valid value
+    let mut request: libc::ifreq = unsafe { std::mem::zeroed() };
+
+    for (target, byte) in request.ifr_name.iter_mut().zip(name.bytes()) {
+        *target = byte as libc::c_char;
+    }
+
+    Ok(request)
+}
+
+/// Sets the MTU of a network interface.
+///
+/// Arguments
+/// `name` - the name of the interface
+/// `mtu` - the new MTU
+pub fn set_interface_mtu(name: &str, mtu: u32) -> Result<()> {
+    let control = control_socket()?;
+    let mut request = interface_request(name)?;
+    request.ifr_ifru.ifru_mtu = mtu as libc::c_int;
+
+    // SAFETY: the request is initialized for SIOCSIFMTU
+    unsafe {
+        check(libc::ioctl(
+            control.as_raw_fd(),
+            libc::SIOCSIFMTU as _,
+            &request,
+        ))
+        .context("SIOCSIFMTU")?;
+    }
+
+    Ok(())
+}
+
+/// Converts the return value of a libc call into a result.
+pub fn check(result: libc::c_int) -> io::Result<libc::c_int> {
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(result)
+    }
+}
@@ -1,18 +1,258 @@
-use anyhow::Result;
+use crate::constants::{
+    BUFFER_POOL_PACKETS, INTERFACE_BATCH_SIZE, OFFLOAD_BUFFER_POOL_PACKETS, OFFLOAD_BUFFER_SIZE,
+};
+use crate::utils::buffer_pool::BufferPool;
+#[cfg(target_os = "linux")]
+use crate::utils::offload::device::OffloadDevice;
+use crate::utils::offload::gro::coalesce;
+use crate::utils::offload::gso::segment;
+use crate::utils::offload::virtio::{VirtioNetHeader, VIRTIO_NET_HDR_LEN};
+use anyhow::{anyhow, Result};
 use bytes::{Bytes, BytesMut};
 use ipnet::IpNet;
-use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
-use tun::{AsyncDevice, Configuration};
+use std::future::poll_fn;
+use std::io::{self, ErrorKind, IoSlice};
+#[cfg(target_os = "linux")]
+use std::os::fd::{AsRawFd, BorrowedFd, OwnedFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf, ReadHalf, WriteHalf};
+use tracing::warn;
+use tun::{AsyncDevice, Configuration, Device};
+
+/// TUN interface, optionally opened with segmentation offload.
+pub enum Interface {
+    Tun(AsyncDevice),
+    #[cfg(target_os = "linux")]
+    Offload(OffloadDevice),
+    /// Interface adopted from another process, the flag tells whether it uses offload
+    #[cfg(target_os = "linux")]
+    Adopted(OffloadDevice, bool),
+}
+
+impl Interface {
+    /// Returns the name of the interface
+    pub fn name(&self) -> String {
+        match self {
+            Interface::Tun(device) => device.get_ref().name().to_owned(),
+            #[cfg(target_os = "linux")]
+            Interface::Offload(device) => device.name().to_owned(),
+            #[cfg(target_os = "linux")]
+            Interface::Adopted(device, _) => device.name().to_owned(),
+        }
+    }
+
+    /// Checks whether packets on the interface are prepended with a virtio-net header
+    pub fn has_offload(&self) -> bool {
+        match self {
+            Interface::Tun(_) => false,
+            #[cfg(target_os = "linux")]
+            Interface::Offload(_) => true,
+            #[cfg(target_os = "linux")]
+            Interface::Adopted(_, offload) => *offload,
+        }
+    }
+
+    /// Duplicates the file descriptor of the interface, so it can be handed over to another
+    /// process.
+    #[cfg(target_os = "linux")]
+    pub fn try_clone_fd(&self) -> Result<OwnedFd> {
+        match self {
+            Interface::Tun(device) => {
+                // SAFETY: the file descriptor stays open while the device is borrowed
+                let fd = unsafe { BorrowedFd::borrow_raw(device.get_ref().as_raw_fd()) };
+                Ok(fd.try_clone_to_owned()?)
+            }
+            Interface::Offload(device) | Interface::Adopted(device, _) => device.try_clone_fd(),
+        }
+    }
+}
+
+impl AsyncRead for Interface {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Interface::Tun(device) => Pin::new(device).poll_read(cx, buf),
+            #[cfg(target_os = "linux")]
+            Interface::Offload(device) => Pin::new(device).poll_read(cx, buf),
+            #[cfg(target_os = "linux")]
+            Interface::Adopted(device, _) => Pin::new(device).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Interface {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Interface::Tun(device) => Pin::new(device).poll_write(cx, buf),
+            #[cfg(target_os = "linux")]
+            Interface::Offload(device) => Pin::new(device).poll_write(cx, buf),
+            #[cfg(target_os = "linux")]
+            Interface::Adopted(device, _) => Pin::new(device).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Interface::Tun(device) => Pin::new(device).poll_write_vectored(cx, bufs),
+            #[cfg(target_os = "linux")]
+            Interface::Offload(device) => Pin::new(device).poll_write_vectored(cx, bufs),
+            #[cfg(target_os = "linux")]
+            Interface::Adopted(device, _) => Pin::new(device).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            Interface::Tun(device) => device.is_write_vectored(),
+            #[cfg(target_os = "linux")]
+            Interface::Offload(device) => device.is_write_vectored(),
+            #[cfg(target_os = "linux")]
+            Interface::Adopted(device, _) => device.is_write_vectored(),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Interface::Tun(device) => Pin::new(device).poll_flush(cx),
+            #[cfg(target_os = "linux")]
+            Interface::Offload(device) => Pin::new(device).poll_flush(cx),
+            #[cfg(target_os = "linux")]
+            Interface::Adopted(device, _) => Pin::new(device).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Interface::Tun(device) => Pin::new(device).poll_shutdown(cx),
+            #[cfg(target_os = "linux")]
+            Interface::Offload(device) => Pin::new(device).poll_shutdown(cx),
+            #[cfg(target_os = "linux")]
+            Interface::Adopted(device, _) => Pin::new(device).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Read half of a TUN interface that splits offloaded super-packets into regular packets.
+pub struct InterfaceReader {
+    interface: ReadHalf<Interface>,
+    buffer_pool: BufferPool,
+    offload: bool,
+    segments: Vec<BytesMut>,
+}
+
+impl InterfaceReader {
+    /// Reads a batch of packets from the TUN interface.
+    ///
+    /// Arguments
+    /// `batch` - the batch to append the packets to
+    ///
+    /// Returns
+    /// `usize` - the number of packets read
+    pub async fn read_batch(&mut self, batch: &mut Vec<Bytes>) -> Result<usize> {
+        if !self.offload {
+            return read_batch_from_interface(
+                &mut self.interface,
+                &mut self.buffer_pool,
+                batch,
+                INTERFACE_BATCH_SIZE,
+            )
+            .await;
+        }
+
+        let segments = &mut self.segments;
+        read_packets(
+            &mut self.interface,
+            &mut self.buffer_pool,
+            INTERFACE_BATCH_SIZE,
+            |packet| segments.push(packet),
+        )
+        .await?;
+
+        let packets = batch.len();
+
+        for mut packet in self.segments.drain(..) {
+            let header = match VirtioNetHeader::decode(&packet) {
+                Ok(header) => header,
+                Err(e) => {
+                    warn!("Dropping packet with malformed virtio-net header: {e}");
+                    continue;
+                }
+            };
+            let packet = packet.split_off(VIRTIO_NET_HDR_LEN);
+
+            if let Err(e) = segment(&header, packet, batch) {
+                warn!("Dropping offloaded packet: {e}");
+            }
+        }
+
+        Ok(batch.len() - packets)
+    }
+}
+
+/// Write half of a TUN interface that coalesces packets into offloaded super-packets.
+pub struct InterfaceWriter {
+    interface: WriteHalf<Interface>,
+    offload: bool,
+}
+
+impl InterfaceWriter {
+    /// Writes a batch of packets to the TUN interface, draining the batch.
+    ///
+    /// With segmentation offload, TCP packets of the same flow are coalesced into super-packets
+    /// written with a single syscall. Otherwise every packet takes its own write.
+    ///
+    /// Arguments
+    /// `batch` - the packets to be written to the TUN interface
+    pub async fn write_batch(&mut self, batch: &mut Vec<Bytes>) -> Result<()> {
+        if !self.offload {
+            return write_batch_to_interface(&mut self.interface, batch).await;
+        }
+
+        for (header, packet) in coalesce(batch) {
+            write_with_header(&mut self.interface, &header.encode(), &packet).await?;
+        }
+
+        Ok(())
+    }
+}
 
 /// Sets up a new TUN interface.
 ///
 /// Arguments
 /// `interface_address` - an address and network mask to be used by the interface
 /// `mtu` - MTU of the interface
+/// `offload` - whether to open the interface with segmentation offload
 ///
 /// Returns
-/// `AsyncDevice` - TUN interface
-pub fn set_up_interface(interface_address: IpNet, mtu: u32) -> Result<AsyncDevice> {
+/// `Interface` - TUN interface
+pub fn set_up_interface(interface_address: IpNet, mtu: u32, offload: bool) -> Result<Interface> {
+    #[cfg(target_os = "linux")]
+    if offload {
+        return Ok(Interface::Offload(OffloadDevice::new(
+            interface_address,
+            mtu,
+        )?));
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    if offload {
+        warn!(
+            "Segmentation offload is only supported on Linux, falling back to a regular interface"
+        );
+    }
+
     let mut config = Configuration::default();
 
     config
@@ -29,32 +269,157 @@ pub fn set_up_interface(interface_address: IpNet, mtu: u32) -> Result<AsyncDevic
 
     let interface = tun::create_as_async(&config)?;
 
-    Ok(interface)
+    Ok(Interface::Tun(interface))
+}
+
+/// Adopts a TUN interface handed over by another process.
+///
+/// Arguments
+/// `fd` - the file descriptor of the TUN interface
+/// `name` - the name of the interface
+/// `offload` - whether the interface was opened with segmentation offload
+///
+/// Returns
+/// `Interface` - TUN interface
+#[cfg(target_os = "linux")]
+pub fn adopt_interface(fd: OwnedFd, name: String, offload: bool) -> Result<Interface> {
+    Ok(Interface::Adopted(OffloadDevice::adopt(fd, name)?, offload))
+}
+
+/// Splits the TUN interface into a reader and a writer.
+///
+/// Arguments
+/// `interface` - TUN interface
+/// `mtu` - MTU of the interface
+///
+/// Returns
+/// `(InterfaceReader, InterfaceWriter)` - the read and write halves of the interface
+pub fn split_interface(interface: Interface, mtu: usize) -> (InterfaceReader, InterfaceWriter) {
+    let offload = interface.has_offload();
+    let (read, write) = tokio::io::split(interface);
+
+    let buffer_pool = if offload {
+        BufferPool::new(OFFLOAD_BUFFER_SIZE, OFFLOAD_BUFFER_POOL_PACKETS)
+    } else {
+        BufferPool::new(mtu, BUFFER_POOL_PACKETS)
+    };
+
+    let reader = InterfaceReader {
+        interface: read,
+        buffer_pool,
+        offload,
+        segments: Vec::with_capacity(INTERFACE_BATCH_SIZE),
+    };
+    let writer = InterfaceWriter {
+        interface: write,
+        offload,
+    };
+
+    (reader, writer)
 }
 
 /// Reads a packet from the TUN interface.
 ///
 /// Arguments
 /// `interface` - a read half of the TUN interface
-/// `buf_size` - the size of the buffer to be used for reading from the TUN interface
+/// `buffer_pool` - the buffer pool to read the packet into
 ///
 /// Returns
 /// `Bytes` - the packet read from the TUN interface
 #[inline]
-pub async fn read_from_interface(
-    interface: &mut ReadHalf<AsyncDevice>,
-    buf_size: usize,
+pub async fn read_from_interface<R: AsyncRead + Unpin>(
+    interface: &mut R,
+    buffer_pool: &mut BufferPool,
 ) -> Result<Bytes> {
-    let mut buf = BytesMut::with_capacity(buf_size);
-    interface.read_buf(&mut buf).await?;
+    let mut batch = Vec::with_capacity(1);
+    read_batch_from_interface(interface, buffer_pool, &mut batch, 1).await?;
 
-    #[cfg(target_os = "macos")]
-    let data = truncate_packet_info_header(buf);
+    Ok(batch.pop().expect("Batch contains at least one packet"))
+}
 
-    #[cfg(not(target_os = "macos"))]
-    let data = buf.into();
+/// Reads a batch of packets from the TUN interface.
+///
+/// Waits for at least one packet and then keeps reading packets that are already queued on the
+/// interface without waiting, until `max_packets` packets have been read.
+///
+/// Arguments
+/// `interface` - a read half of the TUN interface
+/// `buffer_pool` - the buffer pool to read the packets into
+/// `batch` - the batch to append the packets to
+/// `max_packets` - the maximum number of packets to read
+///
+/// Returns
+/// `usize` - the number of packets read
+pub async fn read_batch_from_interface<R: AsyncRead + Unpin>(
+    interface: &mut R,
+    buffer_pool: &mut BufferPool,
+    batch: &mut Vec<Bytes>,
+    max_packets: usize,
+) -> Result<usize> {
+    let packets = batch.len();
 
-    Ok(data)
+    read_packets(interface, buffer_pool, max_packets, |packet| {
+        let packet = packet.freeze();
+
+        #[cfg(target_os = "macos")]
+        let packet = truncate_packet_info_header(packet);
+
+        batch.push(packet)
+    })
+    .await?;
+
+    Ok(batch.len() - packets)
+}
+
+/// Reads up to `max_packets` packets, waiting only for the first one.
+///
+/// Arguments
+/// `interface` - a read half of the TUN interface
+/// `buffer_pool` - the buffer pool to read the packets into
+/// `max_packets` - the maximum number of packets to read
+/// `on_packet` - called with every packet read
+async fn read_packets<R: AsyncRead + Unpin>(
+    interface: &mut R,
+    buffer_pool: &mut BufferPool,
+    max_packets: usize,
+    mut on_packet: impl FnMut(BytesMut),
+) -> Result<()> {
+    let mut packets = 0;
+
+    poll_fn(|cx| {
+        while packets < max_packets {
+            let packet_size = buffer_pool.packet_size();
+            let buf = buffer_pool.buffer();
+
+            // Read into the spare capacity, zeroing the buffer first would cost a pass per packet
+            let mut read_buf = ReadBuf::uninit(&mut buf.spare_capacity_mut()[..packet_size]);
+            let bytes_read = match Pin::new(&mut *interface).poll_read(cx, &mut read_buf) {
+                Poll::Ready(Ok(())) => read_buf.filled().len(),
+                Poll::Ready(Err(e)) if packets == 0 => return Poll::Ready(Err(e)),
+                Poll::Pending if packets == 0 => return Poll::Pending,
+                // Report the packets read so far, errors will resurface on the next read
+                _ => break,
+            };
+
+            if bytes_read == 0 {
+                if packets == 0 {
+                    return Poll::Ready(Err(ErrorKind::UnexpectedEof.into()));
+                }
+
+                break;
+            }
+
+            // SAFETY: the reader initialized the first `bytes_read` bytes of the spare capacity
+            unsafe { buf.set_len(bytes_read) };
+            on_packet(buffer_pool.split_mut());
+            packets += 1;
+        }
+
+        Poll::Ready(Ok(()))
+    })
+    .await?;
+
+    Ok(())
 }
 
 /// Writes a packet to the TUN interface.
@@ -63,46 +428,93 @@ pub async fn read_from_interface(
 /// `interface` - a write half of the TUN interface
 /// `data` - the packet to be written to the TUN interface
 #[inline]
-pub async fn write_to_interface(interface: &mut WriteHalf<AsyncDevice>, data: Bytes) -> Result<()> {
+pub async fn write_to_interface<W: AsyncWrite + Unpin>(
+    interface: &mut W,
+    data: Bytes,
+) -> Result<()> {
     #[cfg(target_os = "macos")]
-    let packet_data = prepend_packet_info_header(data)?;
+    write_with_packet_info_header(interface, &data).await?;
 
     #[cfg(not(target_os = "macos"))]
-    let packet_data = data;
+    interface.write_all(&data).await?;
+
+    Ok(())
+}
 
-    interface.write_all(&packet_data).await?;
+/// Writes a batch of packets to the TUN interface, draining the batch.
+///
+/// The TUN driver accepts a single packet per write and has no batched write, so this still
+/// takes one syscall per packet. It only saves waiting for new packets in between. Fewer
+/// syscalls need segmentation offload, where [`InterfaceWriter`] coalesces packets into
+/// super-packets.
+///
+/// Arguments
+/// `interface` - a write half of the TUN interface
+/// `batch` - the packets to be written to the TUN interface
+pub async fn write_batch_to_interface<W: AsyncWrite + Unpin>(
+    interface: &mut W,
+    batch: &mut Vec<Bytes>,
+) -> Result<()> {
+    for packet in batch.drain(..) {
+        write_to_interface(interface, packet).await?;
+    }
 
     Ok(())
 }
 
-/// Adds packet info header to the packet
+/// Writes a packet prepended with a header using vectored IO.
 ///
 /// Arguments
-/// `data` - the packet to be prepended
+/// `interface` - a write half of the TUN interface
+/// `header` - the header to prepend
+/// `data` - the packet to be written to the TUN interface
+#[inline]
+async fn write_with_header<W: AsyncWrite + Unpin>(
+    interface: &mut W,
+    header: &[u8],
+    data: &[u8],
+) -> Result<()> {
+    let packet_length = header.len() + data.len();
+    let bytes_written = interface
+        .write_vectored(&[IoSlice::new(header), IoSlice::new(data)])
+        .await?;
+
+    if bytes_written != packet_length {
+        return Err(anyhow!(
+            "Partial write of {bytes_written} out of {packet_length} bytes to the TUN interface"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Writes a packet prepended with the packet info header.
 ///
-/// Returns
-/// `Bytes` - the prepended packet
+/// Arguments
+/// `interface` - a write half of the TUN interface
+/// `data` - the packet to be written to the TUN interface
 #[cfg(target_os = "macos")]
 #[inline]
-fn prepend_packet_info_header(data: Bytes) -> Result<Bytes> {
+async fn write_with_packet_info_header<W: AsyncWrite + Unpin>(
+    interface: &mut W,
+    data: &[u8],
+) -> Result<()> {
     use crate::constants::DARWIN_PI_HEADER_IPV4;
     use crate::constants::DARWIN_PI_HEADER_IPV6;
-    use anyhow::anyhow;
     use etherparse::IpHeader;
     use etherparse::PacketHeaders;
 
-    let packet_headers = PacketHeaders::from_ip_slice(&data)?;
+    let packet_headers = PacketHeaders::from_ip_slice(data)?;
     let ip_header = packet_headers
         .ip
         .ok_or_else(|| anyhow!("Received packet with invalid IP header"))?;
 
     let pi_header = match ip_header {
-        IpHeader::Version4(_, _) => Bytes::from_static(DARWIN_PI_HEADER_IPV4.as_ref()),
-        IpHeader::Version6(_, _) => Bytes::from_static(DARWIN_PI_HEADER_IPV6.as_ref()),
+        IpHeader::Version4(_, _) => DARWIN_PI_HEADER_IPV4.as_ref(),
+        IpHeader::Version6(_, _) => DARWIN_PI_HEADER_IPV6.as_ref(),
     };
 
-    // TODO: Do not copy
-    Ok([pi_header.as_ref(), data.as_ref()].concat().into())
+    write_with_header(interface, pi_header, data).await
 }
 
 /// Truncates the packet info header from the packet.
@@ -114,8 +526,8 @@ fn prepend_packet_info_header(data: Bytes) -> Result<Bytes> {
 /// `Bytes` - the truncated packet
 #[cfg(target_os = "macos")]
 #[inline]
-fn truncate_packet_info_header(data: BytesMut) -> Bytes {
+fn truncate_packet_info_header(data: Bytes) -> Bytes {
     use crate::constants::DARWIN_PI_HEADER_LENGTH;
 
-    Bytes::from(data).slice(DARWIN_PI_HEADER_LENGTH..)
-}
\ No newline at end of file
+    data.slice(DARWIN_PI_HEADER_LENGTH..)
+}
@@ -0,0 +1,146 @@
+use crate::constants::{CLOSE_CODE_DEAD_PEER, CLOSE_CODE_IDLE};
+use crate::stats::ConnectionStats;
+use crate::utils::clock::{elapsed_since, timestamp};
+use crate::utils::control::ControlFrame;
+use crate::utils::datagram::DatagramTransmitter;
+use anyhow::{anyhow, Result};
+use quinn::{Connection, VarInt};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::{info, warn};
+
+/// Outcome of checking the answers to liveness probes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Liveness {
+    /// The peer answers probes
+    Alive,
+    /// The peer neither answers probes nor sends anything else
+    Dead,
+    /// Datagrams from the peer arrive, but probes are not answered, so the path to the peer is
+    /// broken
+    OneWay,
+}
+
+/// Determines the liveness of the peer.
+///
+/// Arguments
+/// `since_reply` - the time since the last latency reply or the start of probing
+/// `since_received` - the time since the last datagram of any kind was received
+/// `timeout` - the time without replies after which the peer is not alive
+pub fn liveness(since_reply: Duration, since_received: Duration, timeout: Duration) -> Liveness {
+    if since_reply < timeout {
+        Liveness::Alive
+    } else if since_received < timeout {
+        Liveness::OneWay
+    } else {
+        Liveness::Dead
+    }
+}
+
+/// Sends latency probes to the peer and closes the connection if they stop being answered.
+///
+/// Arguments
+/// `transmitter` - the transmitter of datagrams to the peer
+/// `interval` - the interval of the probes, completes right away if probing is disabled
+/// `timeout` - the time without replies after which the peer is considered dead
+/// `stats` - statistics of the connection, updated with the replies to the probes
+pub async fn probe_liveness(
+    transmitter: DatagramTransmitter,
+    interval: Option<Duration>,
+    timeout: Duration,
+    stats: Arc<ConnectionStats>,
+) -> Result<()> {
+    let Some(interval) = interval else {
+        return Ok(());
+    };
+
+    let connection = transmitter.connection().clone();
+    let start = timestamp();
+    let mut sequence = 0_u32;
+
+    loop {
+        tokio::select! {
+            _ = connection.closed() => return Ok(()),
+            _ = sleep(interval) => (),
+        }
+
+        transmitter.send_control_frame(&ControlFrame::latency_probe(sequence))?;
+        sequence = sequence.wrapping_add(1);
+
+        let since_reply = elapsed_since(stats.last_latency_reply().max(start));
+        let since_received = elapsed_since(stats.last_datagram_received().max(start));
+
+        let reason = match liveness(since_reply, since_received, timeout) {
+            Liveness::Alive => continue,
+            Liveness::Dead => "Peer stopped responding",
+            Liveness::OneWay => "Peer stopped receiving",
+        };
+
+        warn!(
+            "{reason} to liveness probes for {since_reply:?}, closing connection to {:?}",
+            connection.remote_address()
+        );
+        connection.close(VarInt::from_u32(CLOSE_CODE_DEAD_PEER), reason.as_bytes());
+
+        return Err(anyhow!(
+            "{reason}: {:?} did not answer liveness probes for {since_reply:?}",
+            connection.remote_address()
+        ));
+    }
+}
+
+/// Closes the connection once no tunnel traffic has passed through it for the idle timeout.
+///
+/// Arguments
+/// `connection` - the QUIC connection
+/// `idle_timeout` - the time without tunnel traffic after which the connection is closed,
+/// completes right away if unset
+/// `stats` - statistics of the connection
+pub async fn enforce_idle_timeout(
+    connection: Arc<Connection>,
+    idle_timeout: Option<Duration>,
+    stats: Arc<ConnectionStats>,
+) -> Result<()> {
+    let Some(idle_timeout) = idle_timeout else {
+        return Ok(());
+    };
+
+    let start = timestamp();
+
+    loop {
+        let idle = elapsed_since(stats.last_tunnel_activity().max(start));
+
+        if idle >= idle_timeout {
+            info!(
+                "Disconnecting {:?} after {idle:?} without tunnel traffic",
+                connection.remote_address()
+            );
+            connection.close(VarInt::from_u32(CLOSE_CODE_IDLE), b"Session idle");
+
+            return Ok(());
+        }
+
+        tokio::select! {
+            _ = connection.closed() => return Ok(()),
+            _ = sleep(idle_timeout - idle) => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::liveness::{liveness, Liveness};
+    use std::time::Duration;
+
+    #[test]
+    fn test_liveness() {
+        let timeout = Duration::from_secs(15);
+        let recent = Duration::from_secs(1);
+        let stale = Duration::from_secs(20);
+
+        assert_eq!(liveness(recent, recent, timeout), Liveness::Alive);
+        assert_eq!(liveness(stale, recent, timeout), Liveness::OneWay);
+        assert_eq!(liveness(stale, stale, timeout), Liveness::Dead);
+    }
+}
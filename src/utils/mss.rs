@@ -0,0 +1,182 @@
+use crate::utils::checksum;
+use crate::utils::packet::{
+    read_u16, write_u16, IP_PROTOCOL_TCP, TCP_CHECKSUM_OFFSET, TCP_FLAGS_OFFSET, TCP_FLAG_SYN,
+};
+use bytes::{Bytes, BytesMut};
+
+/// Kind of the TCP end of option list option
+const TCP_OPTION_END: u8 = 0;
+
+/// Kind of the TCP no-operation option
+const TCP_OPTION_NOP: u8 = 1;
+
+/// Kind of the TCP maximum segment size option
+const TCP_OPTION_MSS: u8 = 2;
+
+/// Length of the TCP maximum segment size option
+const TCP_OPTION_MSS_LEN: usize = 4;
+
+/// Size of a TCP header without options
+const TCP_HEADER_LEN: usize = 20;
+
+/// Clamps the MSS option of TCP SYN and SYN-ACK packets to fit the given MTU.
+///
+/// Packets that are not TCP SYN packets or that already advertise a small enough MSS are
+/// returned unchanged. The TCP checksum of rewritten packets is updated incrementally.
+///
+/// Arguments
+/// `packet` - the IP packet
+/// `mtu` - the effective MTU of the tunnel
+///
+/// Returns
+/// `Bytes` - the packet with the MSS clamped
+pub fn clamp_mss(packet: Bytes, mtu: usize) -> Bytes {
+    let Some((transport_offset, option_offset, mss)) = find_mss(&packet) else {
+        return packet;
+    };
+
+    let max_mss = mtu.saturating_sub(transport_offset + TCP_HEADER_LEN) as u16;
+
+    if mss <= max_mss {
+        return packet;
+    }
+
+    let mut packet = BytesMut::from(packet.as_ref());
+    write_u16(&mut packet, option_offset + 2, max_mss);
+
+    // Words at odd offsets contribute to the ones' complement sum byte-swapped
+    let (old, new) = if (option_offset + 2 - transport_offset) % 2 == 0 {
+        (mss, max_mss)
+    } else {
+        (mss.swap_bytes(), max_mss.swap_bytes())
+    };
+
+    let checksum_offset = transport_offset + TCP_CHECKSUM_OFFSET;
+    let tcp_checksum = checksum::update(read_u16(&packet, checksum_offset), old, new);
+    write_u16(&mut packet, checksum_offset, tcp_checksum);
+
+    packet.freeze()
+}
+
+/// Finds the MSS option of a TCP SYN packet.
+///
+/// Arguments
+/// `packet` - the IP packet
+///
+/// Returns
+/// `Option<(usize, usize, u16)>` - the offsets of the TCP header and the MSS option, and the MSS
+fn find_mss(packet: &[u8]) -> Option<(usize, usize, u16)> {
+    let version = packet.first()?;
+
+    let transport_offset = match version >> 4 {
+        4 => {
+            let header_len = (version & 0x0f) as usize * 4;
+            let initial_fragment = packet.len() >= 20 && read_u16(packet, 6) & 0x1fff == 0;
+
+            if !initial_fragment || packet[9] != IP_PROTOCOL_TCP {
+                return None;
+            }
+
+            header_len
+        }
+        6 if packet.len() >= 40 && packet[6] == IP_PROTOCOL_TCP => 40,
+        _ => return None,
+    };
+
+    let tcp = packet.get(transport_offset..)?;
+
+    if tcp.len() < TCP_HEADER_LEN || tcp[TCP_FLAGS_OFFSET] & TCP_FLAG_SYN == 0 {
+        return None;
+    }
+
+    let options = tcp.get(TCP_HEADER_LEN..(tcp[12] >> 4) as usize * 4)?;
+    let mut offset = 0;
+
+    while offset < options.len() {
+        match options[offset] {
+            TCP_OPTION_END => return None,
+            TCP_OPTION_NOP => offset += 1,
+            kind => {
+                let length = *options.get(offset + 1)? as usize;
+
+                if length < 2 {
+                    return None;
+                }
+
+                if kind == TCP_OPTION_MSS && length == TCP_OPTION_MSS_LEN {
+                    let option = options.get(offset..offset + TCP_OPTION_MSS_LEN)?;
+                    let option_offset = transport_offset + TCP_HEADER_LEN + offset;
+
+                    return Some((transport_offset, option_offset, read_u16(option, 2)));
+                }
+
+                offset += length;
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::checksum;
+    use crate::utils::mss::clamp_mss;
+    use bytes::Bytes;
+    use etherparse::{PacketBuilder, TcpOptionElement};
+
+    fn syn_packet(options: &[TcpOptionElement]) -> Bytes {
+        let builder = PacketBuilder::ipv4([10, 0, 0, 2], [1, 1, 1, 1], 64)
+            .tcp(40000, 443, 1, 65535)
+            .syn()
+            .options(options)
+            .unwrap();
+
+        let mut packet = Vec::new();
+        builder.write(&mut packet, &[]).unwrap();
+
+        packet.into()
+    }
+
+    fn tcp_checksum_is_valid(packet: &[u8]) -> bool {
+        let sum = checksum::pseudo_header_sum(
+            [10, 0, 0, 2].into(),
+            [1, 1, 1, 1].into(),
+            6,
+            (packet.len() - 20) as u32,
+        );
+
+        checksum::finish(checksum::sum(&packet[20..], sum)) == 0
+    }
+
+    #[test]
+    fn test_clamp_mss() {
+        let packet = syn_packet(&[TcpOptionElement::MaximumSegmentSize(1460)]);
+        let clamped = clamp_mss(packet, 1280);
+
+        assert_eq!(clamped[42..44], 1240_u16.to_be_bytes());
+        assert!(tcp_checksum_is_valid(&clamped));
+    }
+
+    #[test]
+    fn test_clamp_mss_at_odd_offset() {
+        let packet = syn_packet(&[
+            TcpOptionElement::Noop,
+            TcpOptionElement::MaximumSegmentSize(1460),
+            TcpOptionElement::Noop,
+            TcpOptionElement::Noop,
+            TcpOptionElement::Noop,
+        ]);
+        let clamped = clamp_mss(packet, 1280);
+
+        assert_eq!(clamped[43..45], 1240_u16.to_be_bytes());
+        assert!(tcp_checksum_is_valid(&clamped));
+    }
+
+    #[test]
+    fn test_small_mss_is_kept() {
+        let packet = syn_packet(&[TcpOptionElement::MaximumSegmentSize(1000)]);
+
+        assert_eq!(clamp_mss(packet.clone(), 1280), packet);
+    }
+}
@@ -0,0 +1,269 @@
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::ffi::CString;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::Duration;
+use tokio::io::unix::AsyncFd;
+use tokio::time::timeout;
+use tracing::debug;
+
+/// Size of the buffer netlink messages are read into
+const NETLINK_BUFFER_SIZE: usize = 32768;
+
+/// Size of a netlink message header
+const NETLINK_HEADER_LEN: usize = std::mem::size_of::<libc::nlmsghdr>();
+
+/// Size of the `ifaddrmsg` header of address messages
+const IFADDRMSG_LEN: usize = 8;
+
+/// Size of the `rtmsg` header of route messages
+const RTMSG_LEN: usize = 12;
+
+/// Size of a route attribute header
+const RTATTR_HEADER_LEN: usize = 4;
+
+/// Watches the addresses and routes of the host for changes of the network the client is
+/// connected through, such as moving from Wi-Fi to LTE.
+pub struct NetworkMonitor {
+    socket: AsyncFd<OwnedFd>,
+    ignored_interfaces: HashSet<u32>,
+    buffer: Vec<u8>,
+}
+
+impl NetworkMonitor {
+    /// Subscribes to IPv4 and IPv6 address and route changes.
+    pub fn new() -> Result<Self> {
+        // SAFETY: plain socket creation, the descriptor is owned right away
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                libc::NETLINK_ROUTE,
+            )
+        };
+
+        if fd < 0 {
+            return Err(io::Error::last_os_error()).context("create netlink socket");
+        }
+
+        // SAFETY: the descriptor was just created and is not owned by anything else
+        let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        // SAFETY: an all-zero `sockaddr_nl` is valid
+        let mut address: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        address.nl_groups = (libc::RTMGRP_IPV4_IFADDR
+            | libc::RTMGRP_IPV6_IFADDR
+            | libc::RTMGRP_IPV4_ROUTE
+            | libc::RTMGRP_IPV6_ROUTE) as u32;
+
+        // SAFETY: the address outlives the call and its size is passed along
+        let result = unsafe {
+            libc::bind(
+                socket.as_raw_fd(),
+                &address as *const libc::sockaddr_nl as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+
+        if result < 0 {
+            return Err(io::Error::last_os_error()).context("bind netlink socket");
+        }
+
+        // SAFETY: the socket owns the descriptor, which stays open while registered
+        let socket = unsafe { AsyncFd::register(socket)? };
+
+        Ok(Self {
+            socket,
+            ignored_interfaces: HashSet::new(),
+            buffer: vec![0; NETLINK_BUFFER_SIZE],
+        })
+    }
+
+    /// Ignores changes of an interface, e.g. the TUN interface of the tunnel itself.
+    ///
+    /// Arguments
+    /// `name` - the name of the interface
+    pub fn ignore_interface(&mut self, name: &str) -> Result<()> {
+        let name = CString::new(name)?;
+        // SAFETY: the name is a valid C string
+        let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+
+        if index == 0 {
+            return Err(io::Error::last_os_error()).context("if_nametoindex");
+        }
+
+        self.ignored_interfaces.insert(index);
+
+        Ok(())
+    }
+
+    /// Waits for the network to change.
+    ///
+    /// Changes usually come in bursts, e.g. an address followed by its routes, so this only
+    /// returns once no further change was seen for `settle_time`.
+    ///
+    /// Arguments
+    /// `settle_time` - the time without changes after which the network is considered settled
+    pub async fn changed(&mut self, settle_time: Duration) -> Result<()> {
+        while !self.receive_change().await? {}
+
+        while let Ok(change) = timeout(settle_time, self.receive_change()).await {
+            change?;
+        }
+
+        Ok(())
+    }
+
+    /// Receives a batch of netlink messages.
+    ///
+    /// Returns
+    /// `true` if a message concerns an interface that is not ignored
+    async fn receive_change(&mut self) -> Result<bool> {
+        loop {
+            let mut guard = self.socket.readable().await?;
+            let buffer = &mut self.buffer;
+
+            let received = guard.try_io(|socket| {
+                // SAFETY: the buffer outlives the call and its size is passed along
+                let received = unsafe {
+                    libc::recv(
+                        socket.as_raw_fd(),
+                        buffer.as_mut_ptr() as *mut libc::c_void,
+                        buffer.len(),
+                        0,
+                    )
+                };
+
+                if received < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                Ok(received as usize)
+            });
+
+            match received {
+                Ok(Ok(length)) => {
+                    let interfaces = changed_interfaces(&self.buffer[..length]);
+                    debug!("Network change on interfaces {interfaces:?}");
+
+                    return Ok(interfaces.iter().any(|interface| match interface {
+                        Some(index) => !self.ignored_interfaces.contains(index),
+                        None => true,
+                    }));
+                }
+                // Messages were dropped, something changed
+                Ok(Err(e)) if e.raw_os_error() == Some(libc::ENOBUFS) => return Ok(true),
+                Ok(Err(e)) => return Err(e).context("receive netlink message"),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+/// Extracts the interfaces concerned by the address and route messages in a netlink datagram.
+///
+/// Arguments
+/// `buffer` - the received datagram
+///
+/// Returns
+/// `Vec<Option<u32>>` - the index of the interface of every message, `None` for routes without
+/// an output interface
+fn changed_interfaces(mut buffer: &[u8]) -> Vec<Option<u32>> {
+    let mut interfaces = Vec::new();
+
+    while buffer.len() >= NETLINK_HEADER_LEN {
+        let length = read_u32(buffer, 0) as usize;
+        let message_type = read_u16(buffer, 4);
+
+        if length < NETLINK_HEADER_LEN || length > buffer.len() {
+            break;
+        }
+
+        let payload = &buffer[NETLINK_HEADER_LEN..length];
+
+        match message_type {
+            libc::RTM_NEWADDR | libc::RTM_DELADDR if payload.len() >= IFADDRMSG_LEN => {
+                interfaces.push(Some(read_u32(payload, 4)));
+            }
+            libc::RTM_NEWROUTE | libc::RTM_DELROUTE if payload.len() >= RTMSG_LEN => {
+                interfaces.push(route_interface(&payload[RTMSG_LEN..]));
+            }
+            _ => (),
+        }
+
+        buffer = &buffer[align(length).min(buffer.len())..];
+    }
+
+    interfaces
+}
+
+/// Finds the output interface in the attributes of a route message.
+fn route_interface(mut attributes: &[u8]) -> Option<u32> {
+    while attributes.len() >= RTATTR_HEADER_LEN {
+        let length = read_u16(attributes, 0) as usize;
+        let attribute_type = read_u16(attributes, 2);
+
+        if length < RTATTR_HEADER_LEN || length > attributes.len() {
+            break;
+        }
+
+        if attribute_type == libc::RTA_OIF && length >= RTATTR_HEADER_LEN + 4 {
+            return Some(read_u32(attributes, RTATTR_HEADER_LEN));
+        }
+
+        attributes = &attributes[align(length).min(attributes.len())..];
+    }
+
+    None
+}
+
+/// Rounds a length up to the 4 byte alignment of netlink messages and attributes
+fn align(length: usize) -> usize {
+    (length + 3) & !3
+}
+
+fn read_u16(buffer: &[u8], offset: usize) -> u16 {
+    u16::from_ne_bytes([buffer[offset], buffer[offset + 1]])
+}
+
+fn read_u32(buffer: &[u8], offset: usize) -> u32 {
+    u32::from_ne_bytes(
+        buffer[offset..offset + 4]
+            .try_into()
+            .expect("Slice has 4 bytes"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::netlink::changed_interfaces;
+
+    fn message(message_type: u16, payload: &[u8]) -> Vec<u8> {
+        let mut message = Vec::new();
+        message.extend_from_slice(&(16 + payload.len() as u32).to_ne_bytes());
+        message.extend_from_slice(&message_type.to_ne_bytes());
+        message.extend_from_slice(&[0; 10]);
+        message.extend_from_slice(payload);
+        message
+    }
+
+    #[test]
+    fn test_changed_interfaces() {
+        let mut address = vec![libc::AF_INET as u8, 24, 0, 0];
+        address.extend_from_slice(&3_u32.to_ne_bytes());
+
+        let mut route = vec![0; 12];
+        route.extend_from_slice(&8_u16.to_ne_bytes());
+        route.extend_from_slice(&libc::RTA_OIF.to_ne_bytes());
+        route.extend_from_slice(&7_u32.to_ne_bytes());
+
+        let mut buffer = message(libc::RTM_NEWADDR, &address);
+        buffer.extend(message(libc::RTM_DELROUTE, &route));
+        buffer.extend(message(libc::RTM_NEWROUTE, &[0; 12]));
+        buffer.extend(message(libc::RTM_NEWLINK, &[0; 16]));
+
+        assert_eq!(changed_interfaces(&buffer), vec![Some(3), Some(7), None]);
+    }
+}
@@ -0,0 +1,5 @@
+#[cfg(target_os = "linux")]
+pub mod device;
+pub mod gro;
+pub mod gso;
+pub mod virtio;
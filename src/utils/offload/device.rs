@@ -0,0 +1,261 @@
+use crate::utils::ifreq::{check, control_socket, interface_request};
+use crate::utils::offload::virtio::VIRTIO_NET_HDR_LEN;
+use anyhow::{anyhow, Context as _, Result};
+use ipnet::IpNet;
+use std::ffi::CStr;
+use std::fs::{File, OpenOptions};
+use std::io::{self, IoSlice, Read, Write};
+use std::net::{IpAddr, Ipv4Addr};
+use std::os::fd::{AsFd, AsRawFd, OwnedFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+const TUNSETIFF: libc::c_ulong = 0x400454ca;
+const TUNSETOFFLOAD: libc::c_ulong = 0x400454d0;
+const TUNSETVNETHDRSZ: libc::c_ulong = 0x400454d8;
+
+const TUN_F_CSUM: libc::c_ulong = 0x01;
+const TUN_F_TSO4: libc::c_ulong = 0x02;
+const TUN_F_TSO6: libc::c_ulong = 0x04;
+
+/// TUN interface opened with a virtio-net header and TSO/checksum offload enabled.
+///
+/// Every packet read from or written to the device is prepended with a virtio-net header, unless
+/// the device is adopted from an interface opened without it.
+pub struct OffloadDevice {
+    inner: AsyncFd<File>,
+    name: String,
+}
+
+impl OffloadDevice {
+    /// Creates and configures a new offload-enabled TUN interface.
+    ///
+    /// Arguments
+    /// `interface_address` - an address and network mask to be used by the interface
+    /// `mtu` - MTU of the interface
+    ///
+    /// Returns
+    /// `OffloadDevice` - TUN interface
+    pub fn new(interface_address: IpNet, mtu: u32) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
+            .open("/dev/net/tun")
+            .context("open /dev/net/tun")?;
+
+        let mut request = interface_request("")?;
+        request.ifr_ifru.ifru_flags = (libc::IFF_TUN | libc::IFF_NO_PI | libc::IFF_VNET_HDR) as _;
+
+        // SAFETY: the file descriptor is valid and the request matches what TUNSETIFF expects
+        unsafe {
+            check(libc::ioctl(file.as_raw_fd(), TUNSETIFF as _, &mut request))
+                .context("TUNSETIFF")?;
+
+            let header_size = VIRTIO_NET_HDR_LEN as libc::c_int;
+            check(libc::ioctl(
+                file.as_raw_fd(),
+                TUNSETVNETHDRSZ as _,
+                &header_size,
+            ))
+            .context("TUNSETVNETHDRSZ")?;
+
+            check(libc::ioctl(
+                file.as_raw_fd(),
+                TUNSETOFFLOAD as _,
+                TUN_F_CSUM | TUN_F_TSO4 | TUN_F_TSO6,
+            ))
+            .context("TUNSETOFFLOAD")?;
+        }
+
+        // SAFETY: the kernel returns a NUL terminated interface name
+        let name = unsafe { CStr::from_ptr(request.ifr_name.as_ptr()) }
+            .to_string_lossy()
+            .into_owned();
+
+        configure_interface(&name, interface_address, mtu)?;
+
+        // SAFETY: the file owns the descriptor, which stays open while registered
+        let inner = unsafe { AsyncFd::register(file)? };
+
+        Ok(Self { inner, name })
+    }
+
+    /// Adopts an open TUN interface, e.g. one handed over by another process.
+    ///
+    /// Packets are read and written as they are, with a virtio-net header only if the interface
+    /// was opened with one.
+    ///
+    /// Arguments
+    /// `fd` - the file descriptor of the TUN interface
+    /// `name` - the name of the interface
+    pub fn adopt(fd: OwnedFd, name: String) -> Result<Self> {
+        // SAFETY: the file descriptor is valid and owned by us
+        unsafe {
+            let flags = check(libc::fcntl(fd.as_raw_fd(), libc::F_GETFL)).context("F_GETFL")?;
+            check(libc::fcntl(
+                fd.as_raw_fd(),
+                libc::F_SETFL,
+                flags | libc::O_NONBLOCK,
+            ))
+            .context("F_SETFL")?;
+        }
+
+        // SAFETY: the file owns the descriptor, which stays open while registered
+        let inner = unsafe { AsyncFd::register(File::from(fd))? };
+
+        Ok(Self { inner, name })
+    }
+
+    /// Returns the name of the interface
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Duplicates the file descriptor of the interface.
+    pub fn try_clone_fd(&self) -> Result<OwnedFd> {
+        Ok(self.inner.get_ref().as_fd().try_clone_to_owned()?)
+    }
+}
+
+impl AsyncRead for OffloadDevice {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            let mut guard = ready!(self.inner.poll_read_ready(cx))?;
+            let unfilled = buf.initialize_unfilled();
+
+            match guard.try_io(|inner| inner.get_ref().read(unfilled)) {
+                Ok(result) => return Poll::Ready(result.map(|n| buf.advance(n))),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+impl AsyncWrite for OffloadDevice {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            let mut guard = ready!(self.inner.poll_write_ready(cx))?;
+
+            match guard.try_io(|inner| inner.get_ref().write(buf)) {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            let mut guard = ready!(self.inner.poll_write_ready(cx))?;
+
+            match guard.try_io(|inner| inner.get_ref().write_vectored(bufs)) {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Assigns the address, network mask and MTU to the interface and brings it up.
+///
+/// Arguments
+/// `name` - the name of the interface
+/// `interface_address` - an address and network mask to be used by the interface
+/// `mtu` - MTU of the interface
+fn configure_interface(name: &str, interface_address: IpNet, mtu: u32) -> Result<()> {
+    let (address, netmask) = match (interface_address.addr(), interface_address.netmask()) {
+        (IpAddr::V4(address), IpAddr::V4(netmask)) => (address, netmask),
+        _ => return Err(anyhow!("Offload interfaces only support IPv4 addresses")),
+    };
+
+    let control = control_socket()?;
+
+    // SAFETY: every request is initialized for the ioctl it is passed to
+    unsafe {
+        let mut request = interface_request(name)?;
+        request.ifr_ifru.ifru_addr = socket_address(address);
+        check(libc::ioctl(
+            control.as_raw_fd(),
+            libc::SIOCSIFADDR as _,
+            &request,
+        ))
+        .context("SIOCSIFADDR")?;
+
+        let mut request = interface_request(name)?;
+        request.ifr_ifru.ifru_netmask = socket_address(netmask);
+        check(libc::ioctl(
+            control.as_raw_fd(),
+            libc::SIOCSIFNETMASK as _,
+            &request,
+        ))
+        .context("SIOCSIFNETMASK")?;
+
+        let mut request = interface_request(name)?;
+        request.ifr_ifru.ifru_mtu = mtu as libc::c_int;
+        check(libc::ioctl(
+            control.as_raw_fd(),
+            libc::SIOCSIFMTU as _,
+            &request,
+        ))
+        .context("SIOCSIFMTU")?;
+
+        let mut request = interface_request(name)?;
+        check(libc::ioctl(
+            control.as_raw_fd(),
+            libc::SIOCGIFFLAGS as _,
+            &mut request,
+        ))
+        .context("SIOCGIFFLAGS")?;
+        request.ifr_ifru.ifru_flags |= (libc::IFF_UP | libc::IFF_RUNNING) as libc::c_short;
+        check(libc::ioctl(
+            control.as_raw_fd(),
+            libc::SIOCSIFFLAGS as _,
+            &request,
+        ))
+        .context("SIOCSIFFLAGS")?;
+    }
+
+    Ok(())
+}
+
+/// Converts an IPv4 address into a `sockaddr` usable in interface requests.
+fn socket_address(address: Ipv4Addr) -> libc::sockaddr {
+    let address = libc::sockaddr_in {
+        sin_family: libc::AF_INET as libc::sa_family_t,
+        sin_port: 0,
+        sin_addr: libc::in_addr {
+            s_addr: u32::from_ne_bytes(address.octets()),
+        },
+        sin_zero: [0; 8],
+    };
+
+    // SAFETY: `sockaddr_in` and `sockaddr` have the same size
+    unsafe { std::mem::transmute(address) }
+}
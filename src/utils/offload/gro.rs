@@ -0,0 +1,375 @@
+use crate::utils::checksum;
+use crate::utils::offload::virtio::{
+    VirtioNetHeader, VIRTIO_NET_HDR_F_NEEDS_CSUM, VIRTIO_NET_HDR_GSO_TCPV4,
+    VIRTIO_NET_HDR_GSO_TCPV6,
+};
+use crate::utils::packet::{
+    addresses, read_u16, read_u32, write_u16, IP_PROTOCOL_TCP, TCP_CHECKSUM_OFFSET,
+    TCP_FLAGS_OFFSET, TCP_FLAG_ACK, TCP_FLAG_PSH,
+};
+use bytes::{Bytes, BytesMut};
+use std::net::IpAddr;
+
+/// Maximum size of a coalesced packet
+const MAX_COALESCED_SIZE: usize = u16::MAX as usize;
+
+/// TCP segment that is eligible for coalescing.
+struct TcpSegment {
+    source: IpAddr,
+    destination: IpAddr,
+    ports: u32,
+    transport_offset: usize,
+    headers_len: usize,
+    sequence: u32,
+    flags: u8,
+    payload_len: usize,
+}
+
+impl TcpSegment {
+    /// Parses a packet into a TCP segment if the packet can be coalesced.
+    ///
+    /// Only plain IPv4/IPv6 TCP packets without IP options, extension headers or fragmentation
+    /// carrying payload with no other flags than ACK and PSH are eligible.
+    fn parse(packet: &[u8]) -> Option<Self> {
+        let is_ipv4 = packet.first()? >> 4 == 4;
+
+        let transport_offset = if is_ipv4 {
+            if packet.len() < 20
+                || packet[0] & 0x0f != 5
+                || packet[9] != IP_PROTOCOL_TCP
+                || read_u16(packet, 6) & 0x3fff != 0
+                || read_u16(packet, 2) as usize != packet.len()
+            {
+                return None;
+            }
+
+            20
+        } else {
+            if packet.len() < 40
+                || packet[6] != IP_PROTOCOL_TCP
+                || read_u16(packet, 4) as usize + 40 != packet.len()
+            {
+                return None;
+            }
+
+            40
+        };
+
+        if packet.len() < transport_offset + 20 {
+            return None;
+        }
+
+        let headers_len = transport_offset + (packet[transport_offset + 12] >> 4) as usize * 4;
+        let flags = packet[transport_offset + TCP_FLAGS_OFFSET];
+
+        if packet.len() <= headers_len || flags & !TCP_FLAG_PSH != TCP_FLAG_ACK {
+            return None;
+        }
+
+        let (source, destination) = addresses(packet, is_ipv4).ok()?;
+
+        Some(Self {
+            source,
+            destination,
+            ports: read_u32(packet, transport_offset),
+            transport_offset,
+            headers_len,
+            sequence: read_u32(packet, transport_offset + 4),
+            flags,
+            payload_len: packet.len() - headers_len,
+        })
+    }
+}
+
+/// Packet being coalesced from one or more TCP segments of the same flow.
+struct CoalescedPacket {
+    packet: BytesMut,
+    segment: TcpSegment,
+    gso_size: usize,
+    segments: usize,
+    next_sequence: u32,
+    closed: bool,
+}
+
+impl CoalescedPacket {
+    /// Checks whether the given segment continues this packet and can be appended to it.
+    fn can_append(&self, packet: &[u8], segment: &TcpSegment) -> bool {
+        let offset = self.segment.transport_offset;
+
+        !self.closed
+            && segment.sequence == self.next_sequence
+            && segment.payload_len <= self.gso_size
+            && self.packet.len() + segment.payload_len <= MAX_COALESCED_SIZE
+            && segment.headers_len == self.segment.headers_len
+            // Same ACK number, TCP options, TTL/hop limit and TOS/traffic class
+            && packet[offset + 8..offset + 12] == self.packet[offset + 8..offset + 12]
+            && packet[offset + 20..segment.headers_len]
+                == self.packet[offset + 20..segment.headers_len]
+            && same_ip_parameters(packet, &self.packet, offset == 20)
+    }
+
+    /// Appends the payload of the given segment.
+    fn append(&mut self, packet: &[u8], segment: &TcpSegment) {
+        self.packet
+            .extend_from_slice(&packet[segment.headers_len..]);
+        self.packet[self.segment.transport_offset + TCP_FLAGS_OFFSET] |= segment.flags;
+
+        // Keep the most recent window
+        let window = self.segment.transport_offset + 14;
+        self.packet[window..window + 2].copy_from_slice(&packet[window..window + 2]);
+
+        self.segments += 1;
+        self.next_sequence = segment.sequence.wrapping_add(segment.payload_len as u32);
+        self.closed = segment.payload_len < self.gso_size || segment.flags & TCP_FLAG_PSH != 0;
+    }
+
+    /// Finalizes the packet, returning it along with the virtio-net header describing it.
+    fn finish(mut self) -> (VirtioNetHeader, Bytes) {
+        if self.segments == 1 {
+            return (VirtioNetHeader::default(), self.packet.freeze());
+        }
+
+        let offset = self.segment.transport_offset;
+        let length = self.packet.len();
+        let is_ipv4 = offset == 20;
+
+        if is_ipv4 {
+            write_u16(&mut self.packet, 2, length as u16);
+            write_u16(&mut self.packet, 10, 0);
+            let header_checksum = checksum::checksum(&self.packet[..offset]);
+            write_u16(&mut self.packet, 10, header_checksum);
+        } else {
+            write_u16(&mut self.packet, 4, (length - 40) as u16);
+        }
+
+        // With a partial checksum the checksum field holds the folded pseudo header sum
+        let pseudo_header_sum = checksum::pseudo_header_sum(
+            self.segment.source,
+            self.segment.destination,
+            IP_PROTOCOL_TCP,
+            (length - offset) as u32,
+        );
+        write_u16(
+            &mut self.packet,
+            offset + TCP_CHECKSUM_OFFSET,
+            !checksum::finish(pseudo_header_sum),
+        );
+
+        let header = VirtioNetHeader {
+            flags: VIRTIO_NET_HDR_F_NEEDS_CSUM,
+            gso_type: if is_ipv4 {
+                VIRTIO_NET_HDR_GSO_TCPV4
+            } else {
+                VIRTIO_NET_HDR_GSO_TCPV6
+            },
+            hdr_len: self.segment.headers_len as u16,
+            gso_size: self.gso_size as u16,
+            csum_start: offset as u16,
+            csum_offset: TCP_CHECKSUM_OFFSET as u16,
+        };
+
+        (header, self.packet.freeze())
+    }
+}
+
+/// Packet waiting to be written to an offload-enabled TUN interface.
+enum PendingPacket {
+    Plain(Bytes),
+    Coalesced(CoalescedPacket),
+}
+
+/// Coalesces consecutive TCP segments of the same flow into GSO super-packets.
+///
+/// Packets that cannot be coalesced are passed through unchanged. The relative order of the
+/// packets within each flow is preserved.
+///
+/// Arguments
+/// `batch` - the packets to be coalesced, the batch is drained
+///
+/// Returns
+/// `Vec<(VirtioNetHeader, Bytes)>` - the packets to write along with their virtio-net headers
+pub fn coalesce(batch: &mut Vec<Bytes>) -> Vec<(VirtioNetHeader, Bytes)> {
+    let mut pending: Vec<PendingPacket> = Vec::with_capacity(batch.len());
+
+    for packet in batch.drain(..) {
+        let segment = match TcpSegment::parse(&packet) {
+            Some(segment) => segment,
+            None => {
+                // The packet might belong to a flow being coalesced, nothing may be merged past it
+                close_all(&mut pending);
+                pending.push(PendingPacket::Plain(packet));
+                continue;
+            }
+        };
+
+        let flow = pending.iter_mut().rev().find_map(|pending| match pending {
+            PendingPacket::Coalesced(coalesced)
+                if coalesced.segment.source == segment.source
+                    && coalesced.segment.destination == segment.destination
+                    && coalesced.segment.ports == segment.ports =>
+            {
+                Some(coalesced)
+            }
+            _ => None,
+        });
+
+        match flow {
+            Some(coalesced) if coalesced.can_append(&packet, &segment) => {
+                coalesced.append(&packet, &segment);
+                continue;
+            }
+            // Segments must not be merged into a packet preceding an unrelated segment
+            Some(coalesced) => coalesced.closed = true,
+            None => (),
+        }
+
+        let next_sequence = segment.sequence.wrapping_add(segment.payload_len as u32);
+        let closed = segment.flags & TCP_FLAG_PSH != 0;
+
+        pending.push(PendingPacket::Coalesced(CoalescedPacket {
+            packet: BytesMut::from(packet.as_ref()),
+            gso_size: segment.payload_len,
+            segment,
+            segments: 1,
+            next_sequence,
+            closed,
+        }));
+    }
+
+    pending
+        .into_iter()
+        .map(|pending| match pending {
+            PendingPacket::Plain(packet) => (VirtioNetHeader::default(), packet),
+            PendingPacket::Coalesced(coalesced) => coalesced.finish(),
+        })
+        .collect()
+}
+
+/// Stops all pending packets from being coalesced any further.
+fn close_all(pending: &mut [PendingPacket]) {
+    for pending in pending.iter_mut() {
+        if let PendingPacket::Coalesced(coalesced) = pending {
+            coalesced.closed = true;
+        }
+    }
+}
+
+/// Checks whether the TTL/hop limit and TOS/traffic class of two packets are the same.
+fn same_ip_parameters(first: &[u8], second: &[u8], is_ipv4: bool) -> bool {
+    if is_ipv4 {
+        first[1] == second[1] && first[8] == second[8]
+    } else {
+        first[..2] == second[..2] && first[7] == second[7]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::checksum;
+    use crate::utils::offload::gro::coalesce;
+    use crate::utils::offload::gso::segment;
+    use crate::utils::offload::virtio::{VirtioNetHeader, VIRTIO_NET_HDR_GSO_TCPV4};
+    use bytes::{Bytes, BytesMut};
+    use etherparse::PacketBuilder;
+
+    fn tcp_packet(sequence: u32, payload: &[u8], psh: bool) -> Bytes {
+        let builder =
+            PacketBuilder::ipv4([10, 0, 0, 1], [10, 0, 0, 2], 64).tcp(40000, 443, sequence, 65535);
+        let builder = builder.ack(1000);
+        let builder = if psh { builder.psh() } else { builder };
+
+        let mut packet = Vec::with_capacity(builder.size(payload.len()));
+        builder.write(&mut packet, payload).unwrap();
+
+        packet.into()
+    }
+
+    #[test]
+    fn test_coalesce_and_segment_round_trip() {
+        let mut batch = vec![
+            tcp_packet(1, &[1; 100], false),
+            tcp_packet(101, &[2; 100], false),
+            tcp_packet(201, &[3; 50], true),
+        ];
+        let original = batch.clone();
+
+        let coalesced = coalesce(&mut batch);
+        assert_eq!(coalesced.len(), 1);
+
+        let (header, packet) = &coalesced[0];
+        assert_eq!(header.gso_type, VIRTIO_NET_HDR_GSO_TCPV4);
+        assert_eq!(header.gso_size, 100);
+        assert_eq!(packet.len(), 40 + 250);
+
+        let mut segments = Vec::new();
+        segment(header, BytesMut::from(packet.as_ref()), &mut segments).unwrap();
+
+        assert_eq!(segments.len(), original.len());
+
+        // The IP identification differs between the segments, TCP headers and payload do not
+        for (segment, original) in segments.iter().zip(original.iter()) {
+            assert_eq!(segment.len(), original.len());
+            assert_eq!(segment[20..], original[20..]);
+        }
+    }
+
+    #[test]
+    fn test_coalesce_keeps_gaps_separate() {
+        let mut batch = vec![
+            tcp_packet(1, &[1; 100], false),
+            tcp_packet(301, &[2; 100], false),
+        ];
+
+        let coalesced = coalesce(&mut batch);
+
+        assert_eq!(coalesced.len(), 2);
+        assert!(coalesced
+            .iter()
+            .all(|(header, _)| *header == VirtioNetHeader::default()));
+    }
+
+    #[test]
+    fn test_segmented_checksums_are_valid() {
+        let mut batch = vec![
+            tcp_packet(1, &[7; 1000], false),
+            tcp_packet(1001, &[8; 1000], false),
+        ];
+        let coalesced = coalesce(&mut batch);
+        let (header, packet) = &coalesced[0];
+
+        let mut segments = Vec::new();
+        segment(header, BytesMut::from(packet.as_ref()), &mut segments).unwrap();
+
+        for segment in segments {
+            assert_eq!(checksum::checksum(&segment[..20]), 0);
+
+            let sum = checksum::pseudo_header_sum(
+                [10, 0, 0, 1].into(),
+                [10, 0, 0, 2].into(),
+                6,
+                (segment.len() - 20) as u32,
+            );
+            assert_eq!(checksum::finish(checksum::sum(&segment[20..], sum)), 0);
+        }
+    }
+
+    #[test]
+    fn test_coalesce_passes_truncated_packets_through() {
+        let truncated = tcp_packet(1, &[1; 100], false);
+        let mut batch = vec![
+            Bytes::new(),
+            Bytes::from_static(&[0x45]),
+            Bytes::from_static(&[0x45, 0, 0, 40, 0, 0, 0]),
+            truncated.slice(..19),
+        ];
+        let original = batch.clone();
+
+        let coalesced = coalesce(&mut batch);
+
+        assert_eq!(coalesced.len(), original.len());
+        for ((header, packet), original) in coalesced.iter().zip(original.iter()) {
+            assert_eq!(*header, VirtioNetHeader::default());
+            assert_eq!(packet, original);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use crate::utils::checksum;
+use crate::utils::offload::virtio::{
+    VirtioNetHeader, VIRTIO_NET_HDR_GSO_NONE, VIRTIO_NET_HDR_GSO_TCPV4, VIRTIO_NET_HDR_GSO_TCPV6,
+};
+use crate::utils::packet::{
+    addresses, read_u16, read_u32, write_u16, write_u32, IP_PROTOCOL_TCP, TCP_CHECKSUM_OFFSET,
+    TCP_FLAGS_OFFSET, TCP_FLAG_CWR, TCP_FLAG_FIN, TCP_FLAG_PSH,
+};
+use anyhow::{anyhow, Result};
+use bytes::{Bytes, BytesMut};
+
+/// Splits a packet read from an offload-enabled TUN interface into regular IP packets.
+///
+/// GSO super-packets are segmented into packets of `gso_size` payload bytes, packets with a
+/// partial checksum have their checksum completed.
+///
+/// Arguments
+/// `header` - the virtio-net header of the packet
+/// `packet` - the packet without the virtio-net header
+/// `segments` - the list to append the resulting packets to
+pub fn segment(
+    header: &VirtioNetHeader,
+    mut packet: BytesMut,
+    segments: &mut Vec<Bytes>,
+) -> Result<()> {
+    match header.gso_type {
+        VIRTIO_NET_HDR_GSO_NONE => {
+            if header.needs_checksum() {
+                complete_checksum(
+                    &mut packet,
+                    header.csum_start as usize,
+                    header.csum_offset as usize,
+                )?;
+            }
+
+            segments.push(packet.freeze());
+
+            Ok(())
+        }
+        VIRTIO_NET_HDR_GSO_TCPV4 | VIRTIO_NET_HDR_GSO_TCPV6 => segment_tcp(
+            &packet,
+            header.csum_start as usize,
+            header.gso_size as usize,
+            segments,
+        ),
+        gso_type => Err(anyhow!("Unsupported GSO type: {gso_type}")),
+    }
+}
+
+/// Completes a partial checksum left by the kernel.
+///
+/// The checksum field contains the pseudo header sum, the rest of the sum is computed over the
+/// data starting at `csum_start`.
+///
+/// Arguments
+/// `packet` - the packet
+/// `csum_start` - the offset to start computing the checksum from
+/// `csum_offset` - the offset of the checksum field relative to `csum_start`
+fn complete_checksum(packet: &mut [u8], csum_start: usize, csum_offset: usize) -> Result<()> {
+    let field = csum_start + csum_offset;
+
+    if field + 2 > packet.len() {
+        return Err(anyhow!(
+            "Checksum offset {field} is out of bounds for a packet of size {}",
+            packet.len()
+        ));
+    }
+
+    let checksum = checksum::finish(checksum::sum(&packet[csum_start..], 0));
+    packet[field..field + 2].copy_from_slice(&checksum.to_be_bytes());
+
+    Ok(())
+}
+
+/// Segments a TCP super-packet.
+///
+/// Arguments
+/// `packet` - the TCP super-packet
+/// `transport_offset` - the offset of the TCP header
+/// `gso_size` - the payload size of every segment but the last
+/// `segments` - the list to append the resulting packets to
+fn segment_tcp(
+    packet: &[u8],
+    transport_offset: usize,
+    gso_size: usize,
+    segments: &mut Vec<Bytes>,
+) -> Result<()> {
+    if gso_size == 0 || packet.len() < transport_offset + 20 {
+        return Err(anyhow!("Received a malformed TCP GSO packet"));
+    }
+
+    let is_ipv4 = packet[0] >> 4 == 4;
+    let headers_len = transport_offset + (packet[transport_offset + 12] >> 4) as usize * 4;
+
+    if packet.len() < headers_len {
+        return Err(anyhow!("Received a malformed TCP GSO packet"));
+    }
+
+    let (source, destination) = addresses(packet, is_ipv4)?;
+    let sequence = read_u32(packet, transport_offset + 4);
+    let identification = read_u16(packet, 4);
+    let payload = &packet[headers_len..];
+    let segment_count = payload.len().div_ceil(gso_size);
+
+    let mut buffer = BytesMut::with_capacity(payload.len() + segment_count * headers_len);
+
+    for (index, chunk) in payload.chunks(gso_size).enumerate() {
+        buffer.extend_from_slice(&packet[..headers_len]);
+        buffer.extend_from_slice(chunk);
+
+        let segment = &mut buffer[..];
+        let length = segment.len();
+
+        if is_ipv4 {
+            write_u16(segment, 2, length as u16);
+            write_u16(segment, 4, identification.wrapping_add(index as u16));
+            write_u16(segment, 10, 0);
+            let header_checksum = checksum::checksum(&segment[..transport_offset]);
+            write_u16(segment, 10, header_checksum);
+        } else {
+            write_u16(segment, 4, (length - 40) as u16);
+        }
+
+        let offset = (index * gso_size) as u32;
+        write_u32(segment, transport_offset + 4, sequence.wrapping_add(offset));
+
+        if index + 1 < segment_count {
+            segment[transport_offset + TCP_FLAGS_OFFSET] &= !(TCP_FLAG_FIN | TCP_FLAG_PSH);
+        }
+
+        if index > 0 {
+            segment[transport_offset + TCP_FLAGS_OFFSET] &= !TCP_FLAG_CWR;
+        }
+
+        let tcp_length = (length - transport_offset) as u32;
+        write_u16(segment, transport_offset + TCP_CHECKSUM_OFFSET, 0);
+        let sum = checksum::pseudo_header_sum(source, destination, IP_PROTOCOL_TCP, tcp_length);
+        let tcp_checksum = checksum::finish(checksum::sum(&segment[transport_offset..], sum));
+        write_u16(
+            segment,
+            transport_offset + TCP_CHECKSUM_OFFSET,
+            tcp_checksum,
+        );
+
+        segments.push(buffer.split().freeze());
+    }
+
+    Ok(())
+}
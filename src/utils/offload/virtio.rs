@@ -0,0 +1,100 @@
+use anyhow::{anyhow, Result};
+
+/// Size of the virtio-net header prepended to every packet on an offload-enabled TUN interface
+pub const VIRTIO_NET_HDR_LEN: usize = 10;
+
+/// The checksum of the packet has to be completed starting at `csum_start`
+pub const VIRTIO_NET_HDR_F_NEEDS_CSUM: u8 = 1;
+
+/// The packet is not a GSO packet
+pub const VIRTIO_NET_HDR_GSO_NONE: u8 = 0;
+
+/// The packet is a TCPv4 GSO packet
+pub const VIRTIO_NET_HDR_GSO_TCPV4: u8 = 1;
+
+/// The packet is a TCPv6 GSO packet
+pub const VIRTIO_NET_HDR_GSO_TCPV6: u8 = 4;
+
+/// virtio-net header describing the offloads applied to a packet.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VirtioNetHeader {
+    pub flags: u8,
+    pub gso_type: u8,
+    pub hdr_len: u16,
+    pub gso_size: u16,
+    pub csum_start: u16,
+    pub csum_offset: u16,
+}
+
+impl VirtioNetHeader {
+    /// Parses a virtio-net header from the start of the given data.
+    ///
+    /// Arguments
+    /// `data` - data starting with a virtio-net header
+    ///
+    /// Returns
+    /// `VirtioNetHeader` - the parsed header
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        if data.len() < VIRTIO_NET_HDR_LEN {
+            return Err(anyhow!(
+                "Packet of size {} is too short to contain a virtio-net header",
+                data.len()
+            ));
+        }
+
+        let field = |offset: usize| u16::from_ne_bytes([data[offset], data[offset + 1]]);
+
+        Ok(Self {
+            flags: data[0],
+            gso_type: data[1],
+            hdr_len: field(2),
+            gso_size: field(4),
+            csum_start: field(6),
+            csum_offset: field(8),
+        })
+    }
+
+    /// Encodes the header into its wire representation.
+    ///
+    /// Returns
+    /// `[u8; VIRTIO_NET_HDR_LEN]` - the encoded header
+    pub fn encode(&self) -> [u8; VIRTIO_NET_HDR_LEN] {
+        let mut header = [0_u8; VIRTIO_NET_HDR_LEN];
+
+        header[0] = self.flags;
+        header[1] = self.gso_type;
+        header[2..4].copy_from_slice(&self.hdr_len.to_ne_bytes());
+        header[4..6].copy_from_slice(&self.gso_size.to_ne_bytes());
+        header[6..8].copy_from_slice(&self.csum_start.to_ne_bytes());
+        header[8..10].copy_from_slice(&self.csum_offset.to_ne_bytes());
+
+        header
+    }
+
+    /// Checks whether the checksum of the packet has to be completed
+    pub fn needs_checksum(&self) -> bool {
+        self.flags & VIRTIO_NET_HDR_F_NEEDS_CSUM != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::offload::virtio::{
+        VirtioNetHeader, VIRTIO_NET_HDR_F_NEEDS_CSUM, VIRTIO_NET_HDR_GSO_TCPV4,
+    };
+
+    #[test]
+    fn test_header_round_trip() {
+        let header = VirtioNetHeader {
+            flags: VIRTIO_NET_HDR_F_NEEDS_CSUM,
+            gso_type: VIRTIO_NET_HDR_GSO_TCPV4,
+            hdr_len: 40,
+            gso_size: 1360,
+            csum_start: 20,
+            csum_offset: 16,
+        };
+
+        assert_eq!(VirtioNetHeader::decode(&header.encode()).unwrap(), header);
+        assert!(VirtioNetHeader::decode(&[0_u8; 4]).is_err());
+    }
+}
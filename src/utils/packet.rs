@@ -0,0 +1,94 @@
+use anyhow::{anyhow, Result};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// IP protocol number of ICMP
+pub const IP_PROTOCOL_ICMP: u8 = 1;
+
+/// IP protocol number of TCP
+pub const IP_PROTOCOL_TCP: u8 = 6;
+
+/// IP protocol number of ICMPv6
+pub const IP_PROTOCOL_ICMPV6: u8 = 58;
+
+/// Offset of the flags field in the TCP header
+pub const TCP_FLAGS_OFFSET: usize = 13;
+
+/// Offset of the checksum field in the TCP header
+pub const TCP_CHECKSUM_OFFSET: usize = 16;
+
+/// TCP FIN flag
+pub const TCP_FLAG_FIN: u8 = 0x01;
+
+/// TCP SYN flag
+pub const TCP_FLAG_SYN: u8 = 0x02;
+
+/// TCP RST flag
+pub const TCP_FLAG_RST: u8 = 0x04;
+
+/// TCP PSH flag
+pub const TCP_FLAG_PSH: u8 = 0x08;
+
+/// TCP ACK flag
+pub const TCP_FLAG_ACK: u8 = 0x10;
+
+/// TCP CWR flag
+pub const TCP_FLAG_CWR: u8 = 0x80;
+
+/// Reads the source and destination addresses from an IP packet.
+///
+/// Arguments
+/// `packet` - the IP packet
+/// `is_ipv4` - whether the packet is an IPv4 packet
+///
+/// Returns
+/// `(IpAddr, IpAddr)` - the source and destination addresses
+pub fn addresses(packet: &[u8], is_ipv4: bool) -> Result<(IpAddr, IpAddr)> {
+    if is_ipv4 && packet.len() >= 20 {
+        let source: [u8; 4] = packet[12..16].try_into()?;
+        let destination: [u8; 4] = packet[16..20].try_into()?;
+
+        Ok((
+            Ipv4Addr::from(source).into(),
+            Ipv4Addr::from(destination).into(),
+        ))
+    } else if !is_ipv4 && packet.len() >= 40 {
+        let source: [u8; 16] = packet[8..24].try_into()?;
+        let destination: [u8; 16] = packet[24..40].try_into()?;
+
+        Ok((
+            Ipv6Addr::from(source).into(),
+            Ipv6Addr::from(destination).into(),
+        ))
+    } else {
+        Err(anyhow!("Packet is too short to contain an IP header"))
+    }
+}
+
+/// Reads a big-endian `u16` at the given offset
+#[inline]
+pub fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+/// Reads a big-endian `u32` at the given offset
+#[inline]
+pub fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+/// Writes a big-endian `u16` at the given offset
+#[inline]
+pub fn write_u16(data: &mut [u8], offset: usize, value: u16) {
+    data[offset..offset + 2].copy_from_slice(&value.to_be_bytes());
+}
+
+/// Writes a big-endian `u32` at the given offset
+#[inline]
+pub fn write_u32(data: &mut [u8], offset: usize, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+}
@@ -0,0 +1,229 @@
+use crate::auth::features::Features;
+use crate::config::{ConnectionConfig, OversizedPacketPolicy};
+use crate::constants::PACKET_STREAM_QUEUE_SIZE;
+use crate::stats::ConnectionStats;
+use crate::utils::coalescing::send_coalesced_datagrams;
+use crate::utils::compression::Compression;
+use crate::utils::control::ControlFrame;
+use crate::utils::datagram::DatagramTransmitter;
+use crate::utils::icmp::packet_too_big;
+use crate::utils::mss::clamp_mss;
+use crate::utils::packet_stream::send_packet_stream;
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use std::future::{pending, Future};
+use std::sync::Arc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{channel, unbounded_channel, Sender, UnboundedSender};
+use tokio::try_join;
+use tracing::{debug, warn};
+
+/// Sends IP packets read from the TUN interface to the peer.
+///
+/// Packets are sent as datagrams, optionally compressed, coalesced and protected by FEC. Packets
+/// that do not fit into a datagram are handled according to the oversized packet policy.
+pub struct PacketSender {
+    transmitter: DatagramTransmitter,
+    stream_queue: Sender<Bytes>,
+    coalescing_queue: Option<UnboundedSender<Bytes>>,
+    interface_queue: UnboundedSender<Bytes>,
+    oversized_packets: OversizedPacketPolicy,
+    mss_clamp: Option<usize>,
+    compression: Compression,
+    stats: Arc<ConnectionStats>,
+}
+
+impl PacketSender {
+    /// Creates a new packet sender.
+    ///
+    /// Arguments
+    /// `transmitter` - the transmitter of datagrams to the peer
+    /// `connection_config` - the connection config
+    /// `features` - the features negotiated with the peer
+    /// `interface_queue` - the queue of packets to be written to the local TUN interface
+    /// `stats` - statistics of the connection
+    ///
+    /// Returns
+    /// `(PacketSender, impl Future)` - the packet sender and the future sending queued packets,
+    /// the future completes once the packet sender is dropped
+    pub fn new(
+        transmitter: DatagramTransmitter,
+        connection_config: &ConnectionConfig,
+        features: Features,
+        interface_queue: UnboundedSender<Bytes>,
+        stats: Arc<ConnectionStats>,
+    ) -> (Self, impl Future<Output = Result<()>>) {
+        let (stream_queue, stream_receiver) = channel(PACKET_STREAM_QUEUE_SIZE);
+        let (coalescing_queue, coalescing_receiver) = unbounded_channel();
+
+        let connection = transmitter.connection().clone();
+        let sender = Self {
+            transmitter: transmitter.clone(),
+            stream_queue,
+            coalescing_queue: features.coalescing.then_some(coalescing_queue),
+            interface_queue,
+            oversized_packets: match connection_config.oversized_packets {
+                // The peer would never accept the stream, its flow control window would fill up
+                OversizedPacketPolicy::Stream if !features.packet_stream => {
+                    OversizedPacketPolicy::Icmp
+                }
+                policy => policy,
+            },
+            mss_clamp: connection_config.mss_clamp(),
+            compression: features.compression,
+            stats,
+        };
+
+        let coalescing_deadline = connection_config.coalescing_deadline;
+        let adaptive_fec_ratio = connection_config
+            .fec_adaptive
+            .then_some(connection_config.fec_ratio);
+        let task = async move {
+            let send_queued_packets = async {
+                try_join!(
+                    send_packet_stream(connection, stream_receiver),
+                    send_coalesced_datagrams(
+                        transmitter.clone(),
+                        coalescing_receiver,
+                        coalescing_deadline
+                    ),
+                )
+            };
+            let adapt_fec_ratio = async {
+                match adaptive_fec_ratio {
+                    Some(base_ratio) => transmitter.clone().adapt_fec_ratio(base_ratio).await,
+                    None => pending().await,
+                }
+            };
+
+            tokio::select! {
+                result = send_queued_packets => result.map(|_| ()),
+                result = adapt_fec_ratio => result,
+                result = transmitter.clone().flush_fec_groups() => result,
+            }
+        };
+
+        (sender, task)
+    }
+
+    /// Sends a packet to the peer.
+    ///
+    /// Arguments
+    /// `packet` - the IP packet to be sent
+    pub fn send(&self, packet: Bytes) -> Result<()> {
+        self.stats.record_tunnel_activity();
+
+        let max_datagram_size = self
+            .transmitter
+            .max_datagram_size()
+            .ok_or_else(|| anyhow!("The peer does not support datagram transfer"))?;
+
+        let packet = match self.mss_clamp {
+            Some(mtu) => clamp_mss(packet, mtu.min(max_datagram_size)),
+            None => packet,
+        };
+
+        let datagram = self.compress(&packet)?;
+
+        if datagram.len() <= max_datagram_size {
+            debug!(
+                "Sending {} bytes to {:?}",
+                datagram.len(),
+                self.transmitter.connection().remote_address()
+            );
+
+            if self.compression != Compression::None {
+                self.stats.record_compression(packet.len(), datagram.len());
+            }
+
+            return self.send_datagram(datagram);
+        }
+
+        match self.oversized_packets {
+            OversizedPacketPolicy::Stream => {
+                debug!(
+                    "Sending packet of size {} over the packet stream due to maximum datagram size being {}",
+                    packet.len(),
+                    max_datagram_size
+                );
+                match self.stream_queue.try_send(packet) {
+                    Ok(()) => self.stats.record_streamed_packet(),
+                    Err(TrySendError::Full(packet)) => {
+                        debug!(
+                            "Dropping packet of size {} as the packet stream is congested",
+                            packet.len()
+                        );
+                        self.stats.record_dropped_packet();
+                    }
+                    Err(TrySendError::Closed(_)) => {
+                        return Err(anyhow!("The packet stream is closed"));
+                    }
+                }
+            }
+            OversizedPacketPolicy::Icmp => match packet_too_big(&packet, max_datagram_size) {
+                Some(reply) => {
+                    debug!(
+                        "Replying to packet of size {} with ICMP packet too big due to maximum datagram size being {}",
+                        packet.len(),
+                        max_datagram_size
+                    );
+                    self.stats.record_packet_too_big();
+                    self.interface_queue.send(reply)?;
+                }
+                None => {
+                    debug!(
+                        "Dropping packet of size {} without ICMP reply",
+                        packet.len()
+                    );
+                    self.stats.record_dropped_packet();
+                }
+            },
+            OversizedPacketPolicy::Drop => {
+                warn!(
+                    "Dropping packet of size {} due to maximum datagram size being {}",
+                    packet.len(),
+                    max_datagram_size
+                );
+                self.stats.record_dropped_packet();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compresses a packet with the negotiated algorithm.
+    ///
+    /// Arguments
+    /// `packet` - the IP packet to be compressed
+    ///
+    /// Returns
+    /// `Bytes` - the compressed packet, or the packet itself if it does not shrink
+    fn compress(&self, packet: &Bytes) -> Result<Bytes> {
+        Ok(self
+            .compression
+            .compress(packet)?
+            .unwrap_or_else(|| packet.clone()))
+    }
+
+    /// Sends a control frame to the peer.
+    ///
+    /// Arguments
+    /// `frame` - the control frame to be sent
+    pub fn send_control_frame(&self, frame: &ControlFrame) -> Result<()> {
+        self.transmitter.send_control_frame(frame)
+    }
+
+    /// Sends a datagram to the peer, coalescing it with other datagrams and protecting it with FEC
+    /// if negotiated.
+    ///
+    /// Arguments
+    /// `data` - the data to be sent
+    pub fn send_datagram(&self, data: Bytes) -> Result<()> {
+        match &self.coalescing_queue {
+            Some(coalescing_queue) => coalescing_queue.send(data)?,
+            None => self.transmitter.send(data)?,
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use anyhow::{anyhow, Result};
+use bytes::{Bytes, BytesMut};
+use quinn::{Connection, RecvStream};
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
+use tokio::sync::mpsc::{Receiver, UnboundedSender};
+use tracing::debug;
+
+/// Sends packets that do not fit into a datagram over a dedicated unidirectional QUIC stream.
+///
+/// The stream is opened when the first packet is queued. Every packet is prefixed with its
+/// length as a big-endian `u16`.
+///
+/// Arguments
+/// `connection` - the QUIC connection
+/// `packet_queue` - the queue of packets to be sent
+pub async fn send_packet_stream(
+    connection: Arc<Connection>,
+    mut packet_queue: Receiver<Bytes>,
+) -> Result<()> {
+    let mut stream = None;
+
+    while let Some(packet) = packet_queue.recv().await {
+        let length: u16 = packet
+            .len()
+            .try_into()
+            .map_err(|_| anyhow!("Packet of size {} is too large", packet.len()))?;
+
+        if stream.is_none() {
+            debug!("Opening packet stream to {:?}", connection.remote_address());
+            stream = Some(connection.open_uni().await?);
+        }
+
+        let send_stream = stream.as_mut().expect("Packet stream is open");
+
+        send_stream.write_all(&length.to_be_bytes()).await?;
+        send_stream.write_all(&packet).await?;
+    }
+
+    Ok(())
+}
+
+/// Receives packets sent over unidirectional QUIC streams by the peer.
+///
+/// Arguments
+/// `connection` - the QUIC connection
+/// `packet_sink` - the queue to send the received packets to
+pub async fn receive_packet_streams(
+    connection: Arc<Connection>,
+    packet_sink: UnboundedSender<Bytes>,
+) -> Result<()> {
+    loop {
+        let stream = connection.accept_uni().await?;
+        debug!(
+            "Accepted packet stream from {:?}",
+            connection.remote_address()
+        );
+
+        receive_packets(stream, &packet_sink).await?;
+    }
+}
+
+/// Reads length-prefixed packets from the stream until it is finished.
+///
+/// Arguments
+/// `stream` - the receiving stream
+/// `packet_sink` - the queue to send the received packets to
+async fn receive_packets(
+    mut stream: RecvStream,
+    packet_sink: &UnboundedSender<Bytes>,
+) -> Result<()> {
+    loop {
+        let length = match stream.read_u16().await {
+            Ok(length) => length as usize,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut packet = BytesMut::zeroed(length);
+        stream.read_exact(&mut packet).await?;
+
+        debug!("Received {length} bytes over the packet stream");
+        packet_sink.send(packet.freeze())?;
+    }
+}
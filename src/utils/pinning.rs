@@ -0,0 +1,199 @@
+use crate::utils::certificates::spki_sha256;
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::{Certificate, CertificateError, RootCertStore, ServerName};
+use std::time::SystemTime;
+use tracing::warn;
+
+/// Optional prefix of pins, as used by HTTP public key pinning
+const PIN_PREFIX: &str = "sha256/";
+
+/// Verifies servers by the SHA-256 hash of their public key.
+///
+/// If trust anchors are given, the chain has to be valid for the server name and the pin may
+/// match the key of any certificate of the validated chain, so that the key of an intermediate
+/// CA can be pinned as well. Otherwise the key of the server is its only identity: the pin has
+/// to match the end entity certificate, and names and validity periods are not checked. Several
+/// pins allow rotating keys without locking clients out.
+pub struct PinnedServerVerifier {
+    pins: Vec<[u8; 32]>,
+    webpki: Option<WebPkiVerifier>,
+}
+
+impl PinnedServerVerifier {
+    /// Creates a new verifier.
+    ///
+    /// Arguments
+    /// `pins` - base64 encoded SHA-256 hashes of the SubjectPublicKeyInfo of accepted keys,
+    /// optionally prefixed with `sha256/`
+    /// `roots` - the trust anchors the chain has to be valid for, if any
+    pub fn new(pins: &[String], roots: Option<RootCertStore>) -> Result<Self> {
+        let pins = pins
+            .iter()
+            .map(|pin| parse_pin(pin))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            pins,
+            webpki: roots.map(|roots| WebPkiVerifier::new(roots, None)),
+        })
+    }
+
+    /// Checks whether a certificate of the chain has a pinned key.
+    fn matches(&self, chain: &[&Certificate]) -> Result<bool, rustls::Error> {
+        for certificate in chain {
+            let hash = spki_sha256(certificate)
+                .map_err(|_| rustls::Error::InvalidCertificate(CertificateError::BadEncoding))?;
+
+            if self.pins.contains(&hash) {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+impl ServerCertVerifier for PinnedServerVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if let Some(webpki) = &self.webpki {
+            webpki.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                scts,
+                ocsp_response,
+                now,
+            )?;
+        }
+
+        // Unvalidated intermediates prove nothing, anyone can send the certificate of the server
+        let chain: Vec<&Certificate> = match &self.webpki {
+            Some(_) => std::iter::once(end_entity)
+                .chain(intermediates.iter())
+                .collect(),
+            None => vec![end_entity],
+        };
+
+        if self.matches(&chain)? {
+            return Ok(ServerCertVerified::assertion());
+        }
+
+        if let Ok(hash) = spki_sha256(end_entity) {
+            warn!(
+                "The key of the server is not pinned, its pin is {}",
+                format_pin(&hash)
+            );
+        }
+
+        Err(rustls::Error::InvalidCertificate(
+            CertificateError::ApplicationVerificationFailure,
+        ))
+    }
+}
+
+/// Parses a pin.
+///
+/// Arguments
+/// `pin` - base64 encoded SHA-256 hash, optionally prefixed with `sha256/`
+///
+/// Returns
+/// `[u8; 32]` - the hash
+pub fn parse_pin(pin: &str) -> Result<[u8; 32]> {
+    let encoded = pin.strip_prefix(PIN_PREFIX).unwrap_or(pin);
+
+    STANDARD
+        .decode(encoded)
+        .ok()
+        .and_then(|hash| hash.try_into().ok())
+        .ok_or_else(|| anyhow!("Pin '{pin}' is not a base64 encoded SHA-256 hash"))
+}
+
+/// Formats the pin of a public key hash.
+///
+/// Arguments
+/// `hash` - SHA-256 hash of the DER encoded SubjectPublicKeyInfo
+///
+/// Returns
+/// `String` - the base64 encoded hash prefixed with `sha256/`
+pub fn format_pin(hash: &[u8; 32]) -> String {
+    format!("{PIN_PREFIX}{}", STANDARD.encode(hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::pinning::{parse_pin, PinnedServerVerifier};
+    use rustls::client::ServerCertVerifier;
+    use rustls::{Certificate, ServerName};
+    use std::time::SystemTime;
+
+    /// Minimal certificate whose SubjectPublicKeyInfo is `SEQUENCE { NULL }`
+    fn certificate() -> Certificate {
+        Certificate(vec![
+            0x30, 0x12, 0x30, 0x10, 0xa0, 0x00, 0x02, 0x00, 0x30, 0x00, 0x30, 0x00, 0x30, 0x00,
+            0x30, 0x00, 0x30, 0x02, 0x05, 0x00,
+        ])
+    }
+
+    /// Minimal certificate whose SubjectPublicKeyInfo is `SEQUENCE { BOOLEAN }`
+    fn other_certificate() -> Certificate {
+        Certificate(vec![
+            0x30, 0x13, 0x30, 0x11, 0xa0, 0x00, 0x02, 0x00, 0x30, 0x00, 0x30, 0x00, 0x30, 0x00,
+            0x30, 0x00, 0x30, 0x03, 0x01, 0x01, 0xff,
+        ])
+    }
+
+    fn verify(verifier: &PinnedServerVerifier) -> bool {
+        verify_chain(verifier, certificate(), &[])
+    }
+
+    fn verify_chain(
+        verifier: &PinnedServerVerifier,
+        end_entity: Certificate,
+        intermediates: &[Certificate],
+    ) -> bool {
+        verifier
+            .verify_server_cert(
+                &end_entity,
+                intermediates,
+                &ServerName::try_from("vpn.example.com").unwrap(),
+                &mut std::iter::empty(),
+                &[],
+                SystemTime::now(),
+            )
+            .is_ok()
+    }
+
+    #[test]
+    fn test_pinned_server_verifier() {
+        // echo -n 30020500 | xxd -r -p | openssl dgst -sha256 -binary | base64
+        let pin = "sha256/yJNGE5QvV0MPSLUbATDZkjxL/jbmFmurzdt778ZGjwg=".to_string();
+        let other = "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=".to_string();
+
+        assert!(verify(
+            &PinnedServerVerifier::new(&[other.clone(), pin.clone()], None).unwrap()
+        ));
+        assert!(!verify(&PinnedServerVerifier::new(&[other], None).unwrap()));
+
+        // Without trust anchors the chain is not validated, so only the end entity may match
+        let verifier = PinnedServerVerifier::new(&[pin], None).unwrap();
+        assert!(!verify_chain(
+            &verifier,
+            other_certificate(),
+            &[certificate()]
+        ));
+
+        assert!(parse_pin("sha256/abc").is_err());
+        assert!(PinnedServerVerifier::new(&["not a pin".to_string()], None).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use anyhow::{anyhow, Result};
+use qrcode::{Color, EcLevel};
+
+/// Number of light modules around the code that scanners need to find it
+const QUIET_ZONE: isize = 4;
+
+/// QR code with error correction level L, used to hand client profiles to phones
+pub struct QrCode {
+    code: qrcode::QrCode,
+}
+
+impl QrCode {
+    /// Encodes data into the smallest QR code it fits into.
+    ///
+    /// Arguments
+    /// `data` - the data to encode
+    ///
+    /// Returns
+    /// `QrCode` - the QR code
+    pub fn encode(data: &[u8]) -> Result<Self> {
+        let code = qrcode::QrCode::with_error_correction_level(data, EcLevel::L)
+            .map_err(|e| anyhow!("Failed to encode {} bytes as QR code: {e}", data.len()))?;
+
+        Ok(Self { code })
+    }
+
+    /// Returns the number of modules per side.
+    pub fn size(&self) -> usize {
+        self.code.width()
+    }
+
+    /// Returns whether the module at the given column and row is dark.
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        self.code[(x, y)] == Color::Dark
+    }
+
+    /// Renders the code for a terminal with light text on a dark background, two rows of modules
+    /// per line.
+    ///
+    /// Returns
+    /// `String` - the lines of the rendered code
+    pub fn render(&self) -> String {
+        let size = self.size() as isize;
+        let dark = |x: isize, y: isize| {
+            (0..size).contains(&x) && (0..size).contains(&y) && self.is_dark(x as usize, y as usize)
+        };
+
+        let mut rendered = String::new();
+
+        for y in (-QUIET_ZONE..size + QUIET_ZONE).step_by(2) {
+            for x in -QUIET_ZONE..size + QUIET_ZONE {
+                rendered.push(match (dark(x, y), dark(x, y + 1)) {
+                    (false, false) => '█',
+                    (false, true) => '▀',
+                    (true, false) => '▄',
+                    (true, true) => ' ',
+                });
+            }
+
+            rendered.push('\n');
+        }
+
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::qr::QrCode;
+
+    #[test]
+    fn test_encode() {
+        let code = QrCode::encode(b"rumble://user@vpn.example.com").unwrap();
+        assert_eq!(code.size(), 25);
+
+        // Finder patterns in three corners
+        for (x, y) in [(0, 0), (code.size() - 7, 0), (0, code.size() - 7)] {
+            assert!(code.is_dark(x, y));
+            assert!(!code.is_dark(x + 1, y + 1));
+            assert!(code.is_dark(x + 3, y + 3));
+        }
+
+        let rendered = code.render();
+        assert_eq!(rendered.lines().count(), 17);
+        assert!(rendered.lines().all(|line| line.chars().count() == 25 + 8));
+
+        assert!(QrCode::encode(&[0; 3000]).is_err());
+    }
+}
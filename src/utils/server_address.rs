@@ -0,0 +1,259 @@
+use crate::constants::{DEFAULT_PORT, SRV_SERVICE};
+use crate::utils::dns::lookup_srv;
+use crate::utils::happy_eyeballs::interleave_families;
+use anyhow::{anyhow, Result};
+use std::fmt::{Display, Formatter};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+use tokio::net::lookup_host;
+use tracing::debug;
+
+/// Host part of a server address
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Host {
+    /// An IPv4 or IPv6 literal
+    Ip(IpAddr),
+    /// A domain name
+    Domain(String),
+}
+
+/// Address of a Rumble server, parsed from a connection string such as `vpn.example.com`,
+/// `vpn.example.com:55555`, `192.0.2.1:55555` or `[2001:db8::1]:55555`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerAddress {
+    /// The host of the server
+    pub host: Host,
+    /// The port of the server, if set explicitly
+    pub port: Option<u16>,
+}
+
+impl ServerAddress {
+    /// Returns the name the certificate of the server is verified against.
+    pub fn server_name(&self) -> String {
+        match &self.host {
+            Host::Ip(address) => address.to_string(),
+            Host::Domain(domain) => domain.trim_end_matches('.').to_string(),
+        }
+    }
+
+    /// Resolves the addresses of the server.
+    ///
+    /// Domains without an explicit port are looked up as `_rumble._udp` SRV records first and
+    /// fall back to their A and AAAA records with the default port.
+    ///
+    /// Returns
+    /// `Vec<SocketAddr>` - the addresses in the order they should be tried
+    pub async fn resolve(&self) -> Result<Vec<SocketAddr>> {
+        let domain = match &self.host {
+            Host::Ip(address) => {
+                return Ok(vec![SocketAddr::new(
+                    *address,
+                    self.port.unwrap_or(DEFAULT_PORT),
+                )])
+            }
+            Host::Domain(domain) => domain.as_str(),
+        };
+
+        if let Some(port) = self.port {
+            return resolve_host(domain, port).await;
+        }
+
+        let service = format!("{SRV_SERVICE}.{domain}");
+
+        match lookup_srv(&service).await {
+            Ok(records) if !records.is_empty() => {
+                let mut addresses = Vec::new();
+
+                for record in records {
+                    debug!("SRV record of {service}: {}:{}", record.target, record.port);
+
+                    match resolve_host(&record.target, record.port).await {
+                        Ok(resolved) => addresses.extend(resolved),
+                        Err(e) => debug!("Failed to resolve SRV target {}: {e}", record.target),
+                    }
+                }
+
+                if addresses.is_empty() {
+                    return Err(anyhow!(
+                        "None of the SRV targets of {service} could be resolved"
+                    ));
+                }
+
+                Ok(addresses)
+            }
+            Ok(_) => resolve_host(domain, DEFAULT_PORT).await,
+            Err(e) => {
+                debug!("SRV lookup of {service} failed, using the default port: {e}");
+                resolve_host(domain, DEFAULT_PORT).await
+            }
+        }
+    }
+}
+
+impl FromStr for ServerAddress {
+    type Err = anyhow::Error;
+
+    fn from_str(connection_string: &str) -> Result<Self> {
+        let invalid =
+            |reason: &str| anyhow!("Invalid connection string '{connection_string}': {reason}");
+
+        if let Some(rest) = connection_string.strip_prefix('[') {
+            let (address, rest) = rest
+                .split_once(']')
+                .ok_or_else(|| invalid("missing closing bracket"))?;
+
+            let address: Ipv6Addr = address
+                .parse()
+                .map_err(|_| invalid("invalid IPv6 address"))?;
+
+            let port = match rest {
+                "" => None,
+                rest => Some(
+                    parse_port(
+                        rest.strip_prefix(':')
+                            .ok_or_else(|| invalid("expected a port after the address"))?,
+                    )
+                    .ok_or_else(|| invalid("invalid port"))?,
+                ),
+            };
+
+            return Ok(Self {
+                host: Host::Ip(address.into()),
+                port,
+            });
+        }
+
+        // IPv6 literals without brackets cannot have a port
+        if let Ok(address) = connection_string.parse::<IpAddr>() {
+            return Ok(Self {
+                host: Host::Ip(address),
+                port: None,
+            });
+        }
+
+        let (host, port) = match connection_string.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                Some(parse_port(port).ok_or_else(|| invalid("invalid port"))?),
+            ),
+            None => (connection_string, None),
+        };
+
+        if host.contains(':') {
+            return Err(invalid(
+                "IPv6 addresses with a port must be enclosed in brackets",
+            ));
+        }
+
+        if let Ok(address) = host.parse::<IpAddr>() {
+            return Ok(Self {
+                host: Host::Ip(address),
+                port,
+            });
+        }
+
+        if !is_valid_domain(host) {
+            return Err(invalid("invalid host name"));
+        }
+
+        Ok(Self {
+            host: Host::Domain(host.to_string()),
+            port,
+        })
+    }
+}
+
+impl Display for ServerAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (&self.host, self.port) {
+            (Host::Ip(IpAddr::V6(address)), Some(port)) => write!(f, "[{address}]:{port}"),
+            (Host::Ip(address), Some(port)) => write!(f, "{address}:{port}"),
+            (Host::Ip(address), None) => write!(f, "{address}"),
+            (Host::Domain(domain), Some(port)) => write!(f, "{domain}:{port}"),
+            (Host::Domain(domain), None) => write!(f, "{domain}"),
+        }
+    }
+}
+
+/// Resolves the A and AAAA records of a host.
+///
+/// Returns
+/// `Vec<SocketAddr>` - the addresses, alternating between IPv6 and IPv4
+async fn resolve_host(host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+    let addresses = lookup_host((host, port)).await?.collect();
+
+    Ok(interleave_families(addresses))
+}
+
+/// Parses a non-zero port.
+fn parse_port(port: &str) -> Option<u16> {
+    port.parse().ok().filter(|port| *port != 0)
+}
+
+/// Checks whether a host name consists of valid DNS labels.
+fn is_valid_domain(domain: &str) -> bool {
+    let domain = domain.strip_suffix('.').unwrap_or(domain);
+
+    !domain.is_empty()
+        && domain.len() <= 253
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::server_address::{Host, ServerAddress};
+    use std::net::IpAddr;
+
+    fn ip(address: &str) -> Host {
+        Host::Ip(address.parse::<IpAddr>().unwrap())
+    }
+
+    #[test]
+    fn test_parse_server_address() {
+        let cases = [
+            ("[2001:db8::1]:55555", ip("2001:db8::1"), Some(55555)),
+            ("[2001:db8::1]", ip("2001:db8::1"), None),
+            ("2001:db8::1", ip("2001:db8::1"), None),
+            ("192.0.2.1:443", ip("192.0.2.1"), Some(443)),
+            ("192.0.2.1", ip("192.0.2.1"), None),
+            (
+                "vpn.example.com:55555",
+                Host::Domain("vpn.example.com".to_string()),
+                Some(55555),
+            ),
+            ("localhost", Host::Domain("localhost".to_string()), None),
+        ];
+
+        for (connection_string, host, port) in cases {
+            let address: ServerAddress = connection_string.parse().unwrap();
+
+            assert_eq!(address, ServerAddress { host, port });
+            assert_eq!(address.to_string().parse::<ServerAddress>().unwrap(), address);
+        }
+
+        let address: ServerAddress = "[2001:db8::1]:1".parse().unwrap();
+        assert_eq!(address.server_name(), "2001:db8::1");
+
+        for invalid in [
+            "",
+            "[2001:db8::1",
+            "[2001:db8::1]55555",
+            "[vpn.example.com]:1",
+            "2001:db8::1:55555:x",
+            "vpn.example.com:0",
+            "vpn.example.com:port",
+            "vpn example.com",
+            "-vpn.example.com",
+        ] {
+            assert!(invalid.parse::<ServerAddress>().is_err(), "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use anyhow::Result;
+
+/// Waits for a signal requesting the process to shut down.
+///
+/// Returns
+/// `&str` - the name of the received signal
+#[cfg(unix)]
+pub async fn shutdown_signal() -> Result<&'static str> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+
+    let name = tokio::select! {
+        _ = terminate.recv() => "SIGTERM",
+        _ = interrupt.recv() => "SIGINT",
+    };
+
+    Ok(name)
+}
+
+/// Waits for a signal requesting the process to shut down.
+///
+/// Returns
+/// `&str` - the name of the received signal
+#[cfg(not(unix))]
+pub async fn shutdown_signal() -> Result<&'static str> {
+    tokio::signal::ctrl_c().await?;
+
+    Ok("Ctrl-C")
+}
+
+/// Listens for signals requesting the process to reload its config.
+pub struct ReloadSignal {
+    #[cfg(unix)]
+    hangup: tokio::signal::unix::Signal,
+}
+
+impl ReloadSignal {
+    /// Starts listening for SIGHUP.
+    pub fn new() -> Result<Self> {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+
+            Ok(Self {
+                hangup: signal(SignalKind::hangup())?,
+            })
+        }
+
+        #[cfg(not(unix))]
+        Ok(Self {})
+    }
+
+    /// Waits for the next signal requesting a reload, never completes on platforms without SIGHUP.
+    pub async fn recv(&mut self) {
+        #[cfg(unix)]
+        self.hangup.recv().await;
+
+        #[cfg(not(unix))]
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Listens for signals requesting the server to drain.
+pub struct DrainSignal {
+    #[cfg(unix)]
+    user_defined: tokio::signal::unix::Signal,
+}
+
+impl DrainSignal {
+    /// Starts listening for SIGUSR1.
+    pub fn new() -> Result<Self> {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+
+            Ok(Self {
+                user_defined: signal(SignalKind::user_defined1())?,
+            })
+        }
+
+        #[cfg(not(unix))]
+        Ok(Self {})
+    }
+
+    /// Waits for the next signal requesting a drain, never completes on platforms without SIGUSR1.
+    pub async fn recv(&mut self) {
+        #[cfg(unix)]
+        self.user_defined.recv().await;
+
+        #[cfg(not(unix))]
+        std::future::pending::<()>().await;
+    }
+}
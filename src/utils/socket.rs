@@ -17,6 +17,47 @@ pub fn bind_socket(
     addr: SocketAddr,
     send_buffer_size: usize,
     recv_buffer_size: usize,
+) -> Result<std::net::UdpSocket> {
+    bind_socket_with_options(addr, send_buffer_size, recv_buffer_size, false)
+}
+
+/// Binds a group of UDP sockets to the same address with `SO_REUSEPORT`.
+///
+/// The sockets are bound in order, so their indices match the order in which they joined the
+/// group. If the port is `0`, all sockets share the port picked for the first one.
+///
+/// Arguments
+/// `addr` - the address to bind the sockets to
+/// `count` - the number of sockets
+/// `send_buffer_size` - the size of the send buffer
+/// `recv_buffer_size` - the size of the receive buffer
+///
+/// Returns
+/// `Vec<std::net::UdpSocket>` - the bound sockets
+#[cfg(unix)]
+pub fn bind_reuse_port_sockets(
+    mut addr: SocketAddr,
+    count: usize,
+    send_buffer_size: usize,
+    recv_buffer_size: usize,
+) -> Result<Vec<std::net::UdpSocket>> {
+    let mut sockets = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        let socket = bind_socket_with_options(addr, send_buffer_size, recv_buffer_size, true)?;
+        addr = socket.local_addr()?;
+        sockets.push(socket);
+    }
+
+    Ok(sockets)
+}
+
+/// Binds a UDP socket, optionally joining the `SO_REUSEPORT` group of the address.
+fn bind_socket_with_options(
+    addr: SocketAddr,
+    send_buffer_size: usize,
+    recv_buffer_size: usize,
+    reuse_port: bool,
 ) -> Result<std::net::UdpSocket> {
     let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))
         .context("create socket")?;
@@ -25,6 +66,16 @@ pub fn bind_socket(
         socket.set_only_v6(false).context("set_only_v6")?;
     }
 
+    #[cfg(unix)]
+    if reuse_port {
+        socket.set_reuse_port(true).context("set_reuse_port")?;
+    }
+
+    #[cfg(not(unix))]
+    if reuse_port {
+        return Err(anyhow::anyhow!("SO_REUSEPORT is only supported on Unix"));
+    }
+
     socket
         .bind(&socket2::SockAddr::from(addr))
         .context("binding endpoint")?;
@@ -1,4 +1,7 @@
 use anyhow::Result;
+use std::future::{poll_fn, Future};
+use std::pin::pin;
+use std::task::Poll;
 use std::time::Duration;
 use tokio::task::{AbortHandle, JoinError, JoinHandle};
 use tokio::time::sleep;
@@ -30,4 +33,43 @@ pub async fn join_or_abort_task<R>(task: JoinHandle<R>, duration: Duration) -> O
     let (_, result) = try_join!(abort_after(abort_handle, duration), task).ok()?;
 
     Some(result)
-}
\ No newline at end of file
+}
+
+/// Aborts a task when dropped.
+struct AbortOnDrop(AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Joins a task, aborting it if the join is dropped before the task has finished.
+///
+/// Arguments
+/// `task` - task to be joined
+///
+/// Returns
+/// `R` - the result of the task, or an error if the task panicked or was aborted
+pub async fn join_task<R>(task: JoinHandle<Result<R>>) -> Result<R> {
+    let _guard = AbortOnDrop(task.abort_handle());
+
+    task.await?
+}
+
+/// Polls a future exactly once.
+///
+/// Arguments
+/// `future` - the future to be polled
+///
+/// Returns
+/// `Some(output)` if the future completed immediately, `None` otherwise
+pub async fn poll_once<F: Future>(future: F) -> Option<F::Output> {
+    let mut future = pin!(future);
+
+    poll_fn(|cx| match future.as_mut().poll(cx) {
+        Poll::Ready(output) => Poll::Ready(Some(output)),
+        Poll::Pending => Poll::Ready(None),
+    })
+    .await
+}